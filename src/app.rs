@@ -1,13 +1,25 @@
+use crate::build_history::{MEDIAN_WINDOW, SLOWDOWN_FACTOR};
 use crate::command::{Command, CommandExecutor};
+use crate::config::{ModuleVisibility, ProjectConfig};
+use crate::diagnostics::{self, Level};
+use crate::patch::FormatMode;
 use crate::project::Project;
+use crate::runner::{self, ProcessEvent};
+use crate::test_results::TestStatus;
+use crate::workspace::TargetKind;
 use egui::{Color32, RichText, ScrollArea, TextEdit};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct ChatMessage {
     pub role: MessageRole,
     pub content: String,
     pub timestamp: String,
+    /// Background process whose output is still streaming into `content`
+    #[serde(skip)]
+    pub process: Option<usize>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, PartialEq)]
@@ -38,6 +50,30 @@ pub struct VibeRustCoderApp {
     process_text: String,
     process_analysis: String,
     selected_text: String,
+    show_reply_window: bool,
+    reply_text: String,
+    block_selection: Vec<bool>,
+    /// Diagnostic whose source is shown in the Diagnostics panel
+    selected_diagnostic: Option<usize>,
+    diagnostic_excerpt: String,
+    /// Status shown in the Test Results panel, every status when `None`
+    test_filter: Option<TestStatus>,
+    /// Test whose output is expanded in the Test Results panel
+    selected_test: Option<usize>,
+    /// Project settings being edited, while the Settings window is open
+    settings: Option<SettingsDraft>,
+    /// Command charted in the Build History panel, the latest one when unset
+    history_command: Option<String>,
+    /// Text a command produced for the clipboard, copied on the next frame
+    pending_clipboard: Option<String>,
+}
+
+/// `ProjectConfig` under edit; list and number fields are kept as typed
+/// until saved
+struct SettingsDraft {
+    config: ProjectConfig,
+    features: String,
+    jobs: String,
 }
 
 impl Default for VibeRustCoderApp {
@@ -55,6 +91,16 @@ impl Default for VibeRustCoderApp {
             process_text: String::new(),
             process_analysis: String::new(),
             selected_text: String::new(),
+            show_reply_window: false,
+            reply_text: String::new(),
+            block_selection: Vec::new(),
+            selected_diagnostic: None,
+            diagnostic_excerpt: String::new(),
+            test_filter: None,
+            selected_test: None,
+            settings: None,
+            history_command: None,
+            pending_clipboard: None,
         }
     }
 }
@@ -70,9 +116,42 @@ impl VibeRustCoderApp {
             role,
             content,
             timestamp,
+            process: None,
         });
     }
 
+    fn on_process_event(&mut self, event: ProcessEvent) {
+        let id = match &event {
+            ProcessEvent::Output { id, .. } | ProcessEvent::Finished { id, .. } => *id,
+        };
+        let Some(message) = self.chat_history.iter_mut().rev().find(|msg| msg.process == Some(id)) else {
+            return;
+        };
+
+        match event {
+            ProcessEvent::Output { line, .. } => {
+                message.content.push_str(&line);
+                message.content.push('\n');
+            }
+            ProcessEvent::Finished { exit_code, success, .. } => {
+                let Some(process) = self.command_executor.runner().process(id) else {
+                    return;
+                };
+                let elapsed = process.elapsed().as_secs_f32();
+                let status = match (success, exit_code) {
+                    (true, _) => format!("✅ {} finished in {:.1}s", process.command_line, elapsed),
+                    (false, Some(code)) => format!(
+                        "❌ {} failed with exit code {} after {:.1}s",
+                        process.command_line, code, elapsed
+                    ),
+                    (false, None) => format!("❌ {} was terminated after {:.1}s", process.command_line, elapsed),
+                };
+                message.content.push_str(&status);
+                message.process = None;
+            }
+        }
+    }
+
     fn execute_command(&mut self, command_text: &str) {
         self.add_message(MessageRole::User, command_text.to_string());
         self.last_command = command_text.to_string();
@@ -81,6 +160,7 @@ impl VibeRustCoderApp {
             Ok(command) => {
                 // Check if it's a search command to parse results
                 let is_search = matches!(command, Command::Search { .. });
+                let is_reply = matches!(command, Command::Reply { .. });
                 
                 let result = self.command_executor.execute(command, &mut self.project);
                 match result {
@@ -89,7 +169,27 @@ impl VibeRustCoderApp {
                         if is_search {
                             self.parse_search_results(&output);
                         }
+                        // Blocks that name a file start out ticked
+                        if is_reply {
+                            self.block_selection = self
+                                .command_executor
+                                .pending_blocks()
+                                .iter()
+                                .map(|block| block.file.is_some())
+                                .collect();
+                        }
+                        if let Some(prompt) = self.command_executor.take_fix_prompt() {
+                            self.pending_clipboard = Some(prompt);
+                        }
                         self.add_message(MessageRole::Assistant, output);
+                        // cargo commands keep writing into their message
+                        if let Some(id) = self.command_executor.take_started_process() {
+                            if let Some(message) = self.chat_history.last_mut() {
+                                message.process = Some(id);
+                            }
+                            self.selected_diagnostic = None;
+                            self.selected_test = None;
+                        }
                     }
                     Err(e) => {
                         self.add_message(MessageRole::Error, format!("Error: {}", e));
@@ -229,6 +329,560 @@ impl VibeRustCoderApp {
         self.process_analysis = patch;
     }
 
+    fn show_code_blocks(&mut self, ui: &mut egui::Ui) {
+        let mut apply = false;
+        let blocks = self.command_executor.pending_blocks_mut();
+        self.block_selection.resize(blocks.len(), false);
+
+        ScrollArea::vertical()
+            .id_salt("code_blocks")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for (idx, block) in blocks.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.block_selection[idx], format!("{}.", idx + 1));
+                        let mut file = block.file.clone().unwrap_or_default();
+                        let response = ui.add(
+                            TextEdit::singleline(&mut file)
+                                .desired_width(250.0)
+                                .hint_text("target file, e.g. src/npc.rs"),
+                        );
+                        if response.changed() {
+                            block.file = (!file.trim().is_empty()).then(|| file.trim().to_string());
+                        }
+                        if let Some(target) = &block.target {
+                            ui.label(RichText::new(format!("::{}", target)).color(Color32::GRAY));
+                        }
+                        ui.label(format!("{} lines", block.code.lines().count()));
+                    });
+                    ui.collapsing(format!("Preview block {}", idx + 1), |ui| {
+                        ui.label(RichText::new(&block.code).monospace().small());
+                    });
+                }
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("✅ Apply Selected").clicked() {
+                apply = true;
+            }
+            if ui.button("☐ Select None").clicked() {
+                self.block_selection.iter_mut().for_each(|selected| *selected = false);
+            }
+        });
+
+        if apply {
+            let numbers: Vec<String> = self
+                .block_selection
+                .iter()
+                .enumerate()
+                .filter(|(_, selected)| **selected)
+                .map(|(idx, _)| (idx + 1).to_string())
+                .collect();
+            if numbers.is_empty() {
+                self.add_message(MessageRole::System, "No code blocks selected".to_string());
+            } else {
+                self.execute_command(&format!("apply {}", numbers.join(" ")));
+            }
+        }
+    }
+
+    fn show_processes(&mut self, ui: &mut egui::Ui) {
+        let mut pending_command: Option<String> = None;
+        for process in self.command_executor.runner().processes().iter().filter(|p| p.is_running()) {
+            ui.horizontal(|ui| {
+                ui.label(runner::spinner(process.elapsed()).to_string());
+                ui.label(RichText::new(format!("#{}", process.id)).strong());
+                if let Some(pid) = process.pid {
+                    ui.label(RichText::new(format!("pid {}", pid)).color(Color32::GRAY));
+                }
+                ui.label(RichText::new(&process.command_line).monospace());
+                ui.label(format!("{:.0}s", process.elapsed().as_secs_f32()));
+                if ui.button("⏹ Stop").clicked() {
+                    pending_command = Some(format!("kill {}", process.id));
+                }
+                if ui.button("☠ Kill").clicked() {
+                    pending_command = Some(format!("kill -9 {}", process.id));
+                }
+            });
+        }
+        if let Some(command) = pending_command {
+            self.execute_command(&command);
+        }
+    }
+
+    fn show_diagnostics(&mut self, ui: &mut egui::Ui) {
+        let mut selected = None;
+        let mut pending_command: Option<String> = None;
+        ui.horizontal(|ui| {
+            if ui.button("🔧 Fix All").on_hover_text("Apply every machine-applicable suggestion").clicked() {
+                pending_command = Some("fix".to_string());
+            }
+            let can_undo = self.project.as_ref().is_some_and(|project| project.can_undo());
+            if ui.add_enabled(can_undo, egui::Button::new("↩ Undo Fix")).clicked() {
+                pending_command = Some("undo".to_string());
+            }
+            let has_errors = self
+                .command_executor
+                .diagnostics()
+                .iter()
+                .any(|diagnostic| diagnostic.level == Level::Error);
+            if ui
+                .add_enabled(has_errors, egui::Button::new("📋 Prepare Fix Prompt"))
+                .on_hover_text("Copy the errors with their code and the types they use, ready to paste to an assistant")
+                .clicked()
+            {
+                pending_command = Some("fix prompt".to_string());
+            }
+        });
+
+        let diagnostics = self.command_executor.diagnostics();
+        let mut by_file: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (idx, diagnostic) in diagnostics.iter().enumerate() {
+            if let Some(span) = diagnostic.primary_span() {
+                by_file.entry(span.file.as_str()).or_default().push(idx);
+            }
+        }
+
+        ScrollArea::vertical()
+            .id_salt("diagnostics")
+            .max_height(250.0)
+            .show(ui, |ui| {
+                for (file, indices) in &by_file {
+                    ui.label(RichText::new(format!("📄 {}", file)).strong());
+                    for &idx in indices {
+                        let diagnostic = &diagnostics[idx];
+                        let span = diagnostic.primary_span().expect("grouped by primary span");
+                        let color = match diagnostic.level {
+                            Level::Error => Color32::LIGHT_RED,
+                            Level::Warning => Color32::YELLOW,
+                            _ => Color32::LIGHT_GRAY,
+                        };
+                        let text = RichText::new(format!(
+                            "  {}:{}  {}",
+                            span.line_start,
+                            span.column_start,
+                            diagnostic.title()
+                        ))
+                        .color(color);
+                        ui.horizontal(|ui| {
+                            if ui.selectable_label(self.selected_diagnostic == Some(idx), text).clicked() {
+                                selected = Some(idx);
+                            }
+                            let applicable: Vec<_> = diagnostic
+                                .suggestions
+                                .iter()
+                                .filter(|suggestion| suggestion.is_applicable(true))
+                                .collect();
+                            if let Some(first) = applicable.first() {
+                                let hint = match first.parts.as_slice() {
+                                    [part] if part.replacement.is_empty() => format!("{} (removes the span)", first.message),
+                                    [part] => format!("{}: {}", first.message, part.replacement),
+                                    parts => format!("{} ({} edits)", first.message, parts.len()),
+                                };
+                                if ui.small_button("🔧 Apply suggestion").on_hover_text(hint).clicked() {
+                                    pending_command = Some(format!("fix {}", idx + 1));
+                                }
+                            }
+                        });
+
+                        if self.selected_diagnostic == Some(idx) {
+                            ui.label(RichText::new(&self.diagnostic_excerpt).monospace());
+                            for child in &diagnostic.children {
+                                ui.label(RichText::new(format!("  = {}", child)).small().color(Color32::GRAY));
+                            }
+                        }
+                    }
+                }
+            });
+
+        if let Some(idx) = selected {
+            let span = diagnostics[idx].primary_span().cloned();
+            self.diagnostic_excerpt = match (span, &self.project) {
+                (Some(span), Some(project)) => diagnostics::source_excerpt(&project.root_path, &span, 2)
+                    .unwrap_or_else(|e| e.to_string()),
+                _ => String::new(),
+            };
+            self.selected_diagnostic = Some(idx);
+        }
+        if let Some(command) = pending_command {
+            // Fixed diagnostics leave the list, so indices shift
+            self.selected_diagnostic = None;
+            self.execute_command(&command);
+        }
+    }
+
+    fn show_test_results(&mut self, ui: &mut egui::Ui) {
+        let mut pending_command: Option<String> = None;
+        let run = self.command_executor.test_run();
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.test_filter, None, "All");
+            ui.selectable_value(&mut self.test_filter, Some(TestStatus::Failed), "Failed");
+            ui.selectable_value(&mut self.test_filter, Some(TestStatus::Passed), "Passed");
+            ui.selectable_value(&mut self.test_filter, Some(TestStatus::Ignored), "Ignored");
+            ui.separator();
+            let any_failed = run.count(TestStatus::Failed) > 0;
+            if ui.add_enabled(any_failed, egui::Button::new("🔁 Rerun Failed")).clicked() {
+                pending_command = Some("test --failed".to_string());
+            }
+            if ui.button("💾 JSON").on_hover_text("Write .vibe/test-report.json").clicked() {
+                pending_command = Some("test report json".to_string());
+            }
+            if ui.button("💾 JUnit").on_hover_text("Write .vibe/junit.xml").clicked() {
+                pending_command = Some("test report junit".to_string());
+            }
+        });
+
+        ScrollArea::vertical()
+            .id_salt("test_results")
+            .max_height(250.0)
+            .show(ui, |ui| {
+                egui::Grid::new("test_results_grid").striped(true).show(ui, |ui| {
+                    for (idx, case) in run.cases.iter().enumerate() {
+                        if self.test_filter.is_some_and(|status| status != case.status) {
+                            continue;
+                        }
+                        let (icon, color) = match case.status {
+                            TestStatus::Passed => ("✅", Color32::LIGHT_GREEN),
+                            TestStatus::Failed => ("❌", Color32::LIGHT_RED),
+                            TestStatus::Ignored => ("⏭", Color32::GRAY),
+                        };
+                        ui.label(icon);
+                        let name = RichText::new(&case.name).monospace().color(color);
+                        if ui.selectable_label(self.selected_test == Some(idx), name).clicked() {
+                            self.selected_test = (self.selected_test != Some(idx)).then_some(idx);
+                        }
+                        ui.label(RichText::new(&case.suite).small().color(Color32::GRAY));
+                        ui.label(case.duration.map(|secs| format!("{:.3}s", secs)).unwrap_or_default());
+                        if ui.small_button("▶ Rerun").clicked() {
+                            pending_command = Some(format!("test {} --exact", case.name));
+                        }
+                        ui.end_row();
+
+                        if self.selected_test == Some(idx) {
+                            ui.label("");
+                            ui.vertical(|ui| {
+                                if let Some(panic) = &case.panic {
+                                    if let Some(location) = &panic.location {
+                                        ui.label(RichText::new(format!("panicked at {}", location)).strong());
+                                    }
+                                    ui.label(RichText::new(&panic.message).monospace().color(Color32::LIGHT_RED));
+                                }
+                                if case.stdout.is_empty() {
+                                    ui.label(RichText::new("(no captured output)").color(Color32::GRAY));
+                                } else {
+                                    ui.label(RichText::new(&case.stdout).monospace());
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+
+        if let Some(command) = pending_command {
+            self.execute_command(&command);
+        }
+    }
+
+    /// Package and target pickers; returns the `scope` command for a new
+    /// selection
+    fn show_scope_selector(&self, ui: &mut egui::Ui) -> Option<String> {
+        let project = self.project.as_ref()?;
+        let workspace = project.workspace.as_ref()?;
+        let scope = project.scope();
+        let mut command = None;
+        ui.horizontal(|ui| {
+            ui.label("Package:");
+            egui::ComboBox::from_id_salt("scope_package")
+                .selected_text(scope.package.as_deref().unwrap_or("(whole workspace)"))
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(scope.package.is_none(), "(whole workspace)").clicked() {
+                        command = Some("scope all".to_string());
+                    }
+                    for package in &workspace.packages {
+                        let selected = scope.package.as_deref() == Some(package.name.as_str());
+                        if ui.selectable_label(selected, &package.name).clicked() {
+                            command = Some(format!("scope {}", package.name));
+                        }
+                    }
+                });
+
+            let Some(package) = scope.package.as_deref().and_then(|name| workspace.package(name)) else {
+                return;
+            };
+            ui.label("Target:");
+            egui::ComboBox::from_id_salt("scope_target")
+                .selected_text(scope.target.as_ref().map(|target| target.label()).unwrap_or_else(|| "(all targets)".to_string()))
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(scope.target.is_none(), "(all targets)").clicked() {
+                        command = Some(format!("scope {}", package.name));
+                    }
+                    for target in package.targets.iter().filter(|target| target.kind != TargetKind::BuildScript) {
+                        if ui.selectable_label(scope.target.as_ref() == Some(target), target.label()).clicked() {
+                            command = Some(format!("scope {} {}:{}", package.name, target.kind.label(), target.name));
+                        }
+                    }
+                });
+            ui.label(RichText::new(format!("edition {}", package.edition)).color(Color32::GRAY));
+        });
+        command
+    }
+
+    /// Compile time bars and a warning count line for the recent runs of
+    /// one command, with runs much slower than their rolling median in red
+    fn show_build_history(&mut self, ui: &mut egui::Ui) {
+        let Some(project) = &self.project else {
+            return;
+        };
+        let history = &project.builds;
+        let mut commands: Vec<&str> = Vec::new();
+        for record in history.records.iter().rev() {
+            if !commands.contains(&record.command.as_str()) {
+                commands.push(&record.command);
+            }
+        }
+        let Some(&latest) = commands.first() else {
+            return;
+        };
+        let selected = self
+            .history_command
+            .as_deref()
+            .filter(|command| commands.contains(command))
+            .unwrap_or(latest)
+            .to_string();
+
+        ui.horizontal(|ui| {
+            ui.label("Command:");
+            egui::ComboBox::from_id_salt("history_command")
+                .selected_text(RichText::new(&selected).monospace())
+                .show_ui(ui, |ui| {
+                    for command in &commands {
+                        if ui.selectable_label(*command == selected, *command).clicked() {
+                            self.history_command = Some(command.to_string());
+                        }
+                    }
+                });
+        });
+
+        let runs: Vec<usize> = (0..history.records.len())
+            .filter(|&index| history.records[index].command == selected)
+            .collect();
+        let runs = &runs[runs.len().saturating_sub(40)..];
+        let max_duration = runs
+            .iter()
+            .map(|&index| history.records[index].duration_secs)
+            .fold(0.001, f64::max);
+        let max_warnings = runs.iter().map(|&index| history.records[index].warnings).max().unwrap_or(0).max(1);
+
+        let (response, painter) = ui.allocate_painter(egui::vec2(ui.available_width(), 140.0), egui::Sense::hover());
+        let rect = response.rect.shrink(4.0);
+        painter.rect_stroke(response.rect, 2.0, egui::Stroke::new(1.0, Color32::DARK_GRAY));
+        let slot = rect.width() / runs.len() as f32;
+        let mut warning_line = Vec::new();
+        for (position, &index) in runs.iter().enumerate() {
+            let record = &history.records[index];
+            let left = rect.left() + slot * position as f32;
+            let height = (record.duration_secs / max_duration) as f32 * rect.height();
+            let color = if !record.success {
+                Color32::from_rgb(120, 60, 60)
+            } else if history.slowdown(index).is_some() {
+                Color32::LIGHT_RED
+            } else {
+                Color32::from_rgb(90, 140, 200)
+            };
+            painter.rect_filled(
+                egui::Rect::from_min_max(
+                    egui::pos2(left + slot * 0.15, rect.bottom() - height),
+                    egui::pos2(left + slot * 0.85, rect.bottom()),
+                ),
+                1.0,
+                color,
+            );
+            if let Some(median) = history.rolling_median(index) {
+                let y = rect.bottom() - (median / max_duration) as f32 * rect.height();
+                painter.line_segment(
+                    [egui::pos2(left, y), egui::pos2(left + slot, y)],
+                    egui::Stroke::new(1.0, Color32::GRAY),
+                );
+            }
+            let y = rect.bottom() - record.warnings as f32 / max_warnings as f32 * rect.height();
+            warning_line.push(egui::pos2(left + slot / 2.0, y));
+        }
+        painter.add(egui::Shape::line(warning_line, egui::Stroke::new(1.5, Color32::YELLOW)));
+
+        if let Some(pointer) = response.hover_pos() {
+            let position = (((pointer.x - rect.left()) / slot) as usize).min(runs.len() - 1);
+            let index = runs[position];
+            let record = &history.records[index];
+            let mut text = format!(
+                "{}\n{:.1}s, {} warning(s), {} error(s){}\nHEAD {}",
+                record.started,
+                record.duration_secs,
+                record.warnings,
+                record.errors,
+                if record.success { "" } else { ", failed" },
+                record.git_head.as_deref().unwrap_or("unknown")
+            );
+            if let Some(median) = history.rolling_median(index) {
+                text.push_str(&format!("\nmedian of previous runs {:.1}s", median));
+            }
+            response.on_hover_text(text);
+        }
+
+        ui.label(
+            RichText::new(format!(
+                "Bars: compile time up to {:.1}s, red when over {}× the median of the last {} runs (grey ticks). Yellow: warnings, up to {}.",
+                max_duration, SLOWDOWN_FACTOR, MEDIAN_WINDOW, max_warnings
+            ))
+            .small()
+            .color(Color32::GRAY),
+        );
+    }
+
+    fn open_settings(&mut self) {
+        if let Some(project) = &self.project {
+            let config = project.config.clone();
+            self.settings = Some(SettingsDraft {
+                features: config.cargo.features.join(", "),
+                jobs: config.cargo.jobs.map(|jobs| jobs.to_string()).unwrap_or_default(),
+                config,
+            });
+        }
+    }
+
+    fn show_settings(&mut self, ctx: &egui::Context) {
+        let Some(draft) = &mut self.settings else {
+            return;
+        };
+        let mut close = false;
+        let mut save = false;
+        egui::Window::new("⚙ Project Settings")
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.label(RichText::new("Code insertion").strong());
+                egui::Grid::new("settings_code").num_columns(2).show(ui, |ui| {
+                    ui.label("Format inserted code:");
+                    egui::ComboBox::from_id_salt("format_mode")
+                        .selected_text(format!("{:?}", draft.config.format))
+                        .show_ui(ui, |ui| {
+                            for mode in [FormatMode::Auto, FormatMode::Rustfmt, FormatMode::Prettyplease, FormatMode::Off] {
+                                ui.selectable_value(&mut draft.config.format, mode, format!("{:?}", mode));
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("New mod declarations:");
+                    egui::ComboBox::from_id_salt("module_visibility")
+                        .selected_text(format!("{}mod", draft.config.module_visibility.prefix()))
+                        .show_ui(ui, |ui| {
+                            for visibility in [ModuleVisibility::Private, ModuleVisibility::Pub, ModuleVisibility::PubCrate] {
+                                ui.selectable_value(
+                                    &mut draft.config.module_visibility,
+                                    visibility,
+                                    format!("{}mod", visibility.prefix()),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("");
+                    ui.checkbox(&mut draft.config.sort_modules, "Keep mod declarations sorted");
+                    ui.end_row();
+                });
+
+                ui.separator();
+                ui.label(RichText::new("Default cargo options").strong());
+                ui.label(RichText::new("Added to build, run, test and check unless given on the command line. Bin and example only apply to run.").small().color(Color32::GRAY));
+                let cargo = &mut draft.config.cargo;
+                egui::Grid::new("settings_cargo").num_columns(2).show(ui, |ui| {
+                    ui.label("Features:");
+                    ui.text_edit_singleline(&mut draft.features);
+                    ui.end_row();
+                    ui.label("");
+                    ui.horizontal(|ui| {
+                        // Cargo rejects the two together
+                        let mut all_features = cargo.all_features == Some(true);
+                        if ui.checkbox(&mut all_features, "--all-features").changed() {
+                            cargo.all_features = all_features.then_some(true);
+                            cargo.no_default_features = None;
+                        }
+                        let mut no_default_features = cargo.no_default_features == Some(true);
+                        if ui.checkbox(&mut no_default_features, "--no-default-features").changed() {
+                            cargo.no_default_features = no_default_features.then_some(true);
+                            cargo.all_features = None;
+                        }
+                    });
+                    ui.end_row();
+                    for (label, value) in [
+                        ("Package (-p):", &mut cargo.package),
+                        ("Bin:", &mut cargo.bin),
+                        ("Example:", &mut cargo.example),
+                        ("Profile:", &mut cargo.profile),
+                        ("Target dir:", &mut cargo.target_dir),
+                    ] {
+                        ui.label(label);
+                        let mut text = value.clone().unwrap_or_default();
+                        if ui.text_edit_singleline(&mut text).changed() {
+                            *value = (!text.is_empty()).then_some(text);
+                        }
+                        ui.end_row();
+                    }
+                    ui.label("Jobs (-j):");
+                    ui.text_edit_singleline(&mut draft.jobs);
+                    ui.end_row();
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("💾 Save").clicked() {
+                        save = true;
+                    }
+                    if ui.button("❌ Close").clicked() {
+                        close = true;
+                    }
+                });
+            });
+
+        if save {
+            let mut config = draft.config.clone();
+            config.cargo.features = draft
+                .features
+                .split([',', ' '])
+                .filter(|feature| !feature.is_empty())
+                .map(String::from)
+                .collect();
+            for value in [
+                &mut config.cargo.package,
+                &mut config.cargo.bin,
+                &mut config.cargo.example,
+                &mut config.cargo.profile,
+                &mut config.cargo.target_dir,
+            ] {
+                *value = value.take().map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+            }
+            let jobs = draft.jobs.trim().to_string();
+            config.cargo.jobs = match jobs.parse() {
+                Ok(jobs) => Some(jobs),
+                Err(_) if jobs.is_empty() => None,
+                Err(_) => {
+                    self.add_message(MessageRole::Error, format!("Error: Invalid job count: {}", jobs));
+                    return;
+                }
+            };
+            let Some(project) = &mut self.project else {
+                return;
+            };
+            match config.save(&project.root_path) {
+                Ok(()) => {
+                    project.config = config;
+                    self.settings = None;
+                    self.add_message(MessageRole::Assistant, "Saved settings to .vibe/config.json".to_string());
+                }
+                Err(e) => self.add_message(MessageRole::Error, format!("Error: {}", e)),
+            }
+        } else if close {
+            self.settings = None;
+        }
+    }
+
     fn open_process_window(&mut self, text: String) {
         self.process_text = text;
         self.process_analysis.clear();
@@ -238,6 +892,17 @@ impl VibeRustCoderApp {
 
 impl eframe::App for VibeRustCoderApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        for event in self.command_executor.poll_processes(&mut self.project) {
+            self.on_process_event(event);
+        }
+        // Keep streaming output and the spinner moving while cargo runs
+        if self.command_executor.runner().has_running() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        if let Some(text) = self.pending_clipboard.take() {
+            ctx.output_mut(|o| o.copied_text = text);
+        }
+
         // Process Window (modal)
         if self.show_process_window {
             egui::Window::new("📋 Process Text")
@@ -306,6 +971,38 @@ impl eframe::App for VibeRustCoderApp {
                 });
         }
 
+        // Paste Reply Window
+        if self.show_reply_window {
+            egui::Window::new("📥 Paste AI Reply")
+                .default_width(600.0)
+                .default_height(400.0)
+                .show(ctx, |ui| {
+                    ui.label("Paste the whole reply, prose included. Blocks are matched to files by a `// src/a.rs` first line or a `**src/a.rs**` line above the fence.");
+                    ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            ui.add(
+                                TextEdit::multiline(&mut self.reply_text)
+                                    .desired_width(f32::INFINITY)
+                                    .desired_rows(15)
+                                    .code_editor(),
+                            );
+                        });
+                    ui.horizontal(|ui| {
+                        if ui.button("🔍 Extract Code Blocks").clicked() {
+                            let reply = std::mem::take(&mut self.reply_text);
+                            self.execute_command(&reply);
+                            self.show_reply_window = false;
+                        }
+                        if ui.button("❌ Close").clicked() {
+                            self.show_reply_window = false;
+                        }
+                    });
+                });
+        }
+
+        self.show_settings(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("🦀 Vibe Rust Coder - AI Code Assistant");
             ui.separator();
@@ -322,6 +1019,11 @@ impl eframe::App for VibeRustCoderApp {
                 }
             });
 
+            // Package and target the cargo commands, search and listing cover
+            if let Some(scope_command) = self.show_scope_selector(ui) {
+                self.execute_command(&scope_command);
+            }
+
             ui.separator();
 
             // Quick command buttons
@@ -348,13 +1050,96 @@ impl eframe::App for VibeRustCoderApp {
                     self.execute_command("profile");
                 }
                 
+                if ui.button("📥 Paste Reply").clicked() {
+                    self.show_reply_window = true;
+                }
+                
+                if ui.add_enabled(self.project.is_some(), egui::Button::new("⚙ Settings")).clicked() {
+                    self.open_settings();
+                }
+
                 if ui.button("❓ Help").clicked() {
                     self.execute_command("help");
                 }
             });
 
+            ui.horizontal(|ui| {
+                ui.label("Cargo:");
+                for (label, command) in [
+                    ("✔ Check", "check"),
+                    ("📎 Clippy", "clippy"),
+                    ("🎨 Fmt", "fmt"),
+                    ("🔍 Fmt Check", "fmt --check"),
+                    ("📚 Doc", "doc"),
+                    ("⏱ Bench", "bench"),
+                    ("🧹 Clean", "clean"),
+                    ("🌳 Tree", "tree"),
+                    ("⬆ Update", "update"),
+                ] {
+                    if ui.button(label).clicked() {
+                        self.execute_command(command);
+                    }
+                }
+            });
+
             ui.separator();
 
+            // Running cargo processes
+            if self.command_executor.runner().has_running() {
+                ui.collapsing("⚙ Running Processes", |ui| {
+                    self.show_processes(ui);
+                });
+                ui.separator();
+            }
+
+            // Errors and warnings of the last build, check or test
+            let diagnostics = self.command_executor.diagnostics();
+            if !diagnostics.is_empty() {
+                let errors = diagnostics.iter().filter(|d| d.level == Level::Error).count();
+                let warnings = diagnostics.iter().filter(|d| d.level == Level::Warning).count();
+                ui.collapsing(
+                    format!("🩺 Diagnostics ({} errors, {} warnings)", errors, warnings),
+                    |ui| {
+                        self.show_diagnostics(ui);
+                    },
+                );
+                ui.separator();
+            }
+
+            // Results of the last cargo test
+            let run = self.command_executor.test_run();
+            if !run.cases.is_empty() {
+                ui.collapsing(
+                    format!(
+                        "🧪 Test Results ({} passed, {} failed, {} ignored)",
+                        run.count(TestStatus::Passed),
+                        run.count(TestStatus::Failed),
+                        run.count(TestStatus::Ignored)
+                    ),
+                    |ui| {
+                        self.show_test_results(ui);
+                    },
+                );
+                ui.separator();
+            }
+
+            // Timings of recorded builds, checks, tests and profiles
+            let recorded_runs = self.project.as_ref().map_or(0, |project| project.builds.records.len());
+            if recorded_runs > 0 {
+                ui.collapsing(format!("📈 Build History ({} runs)", recorded_runs), |ui| {
+                    self.show_build_history(ui);
+                });
+                ui.separator();
+            }
+
+            // Code blocks of the last pasted reply
+            if !self.command_executor.pending_blocks().is_empty() {
+                ui.collapsing("📦 Code Blocks (tick the ones to apply)", |ui| {
+                    self.show_code_blocks(ui);
+                });
+                ui.separator();
+            }
+
             // Search results panel (if any)
             if !self.search_results.is_empty() {
                 ui.collapsing("🔍 Search Results (Click to interact)", |ui| {
@@ -436,6 +1221,13 @@ impl eframe::App for VibeRustCoderApp {
                     ui.horizontal(|ui| {
                         ui.label(RichText::new(&msg.timestamp).color(Color32::DARK_GRAY));
                         ui.label(RichText::new(prefix).color(color).strong());
+                        if let Some(process) = msg.process.and_then(|id| self.command_executor.runner().process(id)) {
+                            let elapsed = process.elapsed();
+                            ui.label(
+                                RichText::new(format!("{} running {:.0}s", runner::spinner(elapsed), elapsed.as_secs_f32()))
+                                    .color(Color32::YELLOW),
+                            );
+                        }
                     });
 
                     ui.add_space(4.0);
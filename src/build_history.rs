@@ -0,0 +1,156 @@
+use crate::config::CONFIG_DIR;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const HISTORY_FILE: &str = "builds.jsonl";
+
+/// How many earlier runs of the same command the median is taken over
+pub const MEDIAN_WINDOW: usize = 10;
+/// A run this many times slower than the median is flagged
+pub const SLOWDOWN_FACTOR: f64 = 1.5;
+/// Fewer earlier runs than this are too few to compare against
+const MIN_SAMPLES: usize = 3;
+
+/// One line of `.vibe/builds.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildRecord {
+    /// RFC 3339 local time the command started
+    pub started: String,
+    pub duration_secs: f64,
+    /// `cargo build -p engine`
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub warnings: usize,
+    pub errors: usize,
+    /// Short hash of HEAD when the command started, outside git `None`
+    pub git_head: Option<String>,
+}
+
+/// Every recorded build, check, test and profile run of a project, oldest
+/// first
+#[derive(Debug, Default)]
+pub struct BuildHistory {
+    pub records: Vec<BuildRecord>,
+}
+
+impl BuildHistory {
+    /// Reads the log, skipping lines that do not parse
+    pub fn load(root_path: &Path) -> Self {
+        let records = fs::read_to_string(Self::path(root_path))
+            .map(|content| {
+                content
+                    .lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { records }
+    }
+
+    /// Appends `record` to the log and to `records`
+    pub fn record(&mut self, root_path: &Path, record: BuildRecord) -> Result<()> {
+        let path = Self::path(root_path);
+        fs::create_dir_all(root_path.join(CONFIG_DIR))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// Median duration of up to `MEDIAN_WINDOW` earlier runs of the same
+    /// command as run `index`, when there are enough of them
+    pub fn rolling_median(&self, index: usize) -> Option<f64> {
+        let command = &self.records.get(index)?.command;
+        let mut durations: Vec<f64> = self.records[..index]
+            .iter()
+            .rev()
+            .filter(|record| &record.command == command && record.success)
+            .take(MEDIAN_WINDOW)
+            .map(|record| record.duration_secs)
+            .collect();
+        if durations.len() < MIN_SAMPLES {
+            return None;
+        }
+        durations.sort_by(f64::total_cmp);
+        let middle = durations.len() / 2;
+        Some(if durations.len().is_multiple_of(2) {
+            (durations[middle - 1] + durations[middle]) / 2.0
+        } else {
+            durations[middle]
+        })
+    }
+
+    /// How many times slower than its rolling median run `index` was, when
+    /// that is more than `SLOWDOWN_FACTOR`
+    pub fn slowdown(&self, index: usize) -> Option<f64> {
+        let median = self.rolling_median(index)?;
+        let ratio = self.records[index].duration_secs / median.max(0.001);
+        (ratio > SLOWDOWN_FACTOR).then_some(ratio)
+    }
+
+    fn path(root_path: &Path) -> PathBuf {
+        root_path.join(CONFIG_DIR).join(HISTORY_FILE)
+    }
+}
+
+/// Short hash of the checked out commit
+pub fn git_head(root_path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(root_path)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(command: &str, duration_secs: f64) -> BuildRecord {
+        BuildRecord {
+            started: "2026-10-19T10:00:00+02:00".to_string(),
+            duration_secs,
+            command: command.to_string(),
+            exit_code: Some(0),
+            success: true,
+            warnings: 1,
+            errors: 0,
+            git_head: Some("abc1234".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_history_flags_slow_runs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut history = BuildHistory::load(temp_dir.path());
+        for duration in [2.0, 2.4, 2.2, 9.0] {
+            history
+                .record(temp_dir.path(), record("cargo build", duration))
+                .unwrap();
+        }
+        history
+            .record(temp_dir.path(), record("cargo test", 30.0))
+            .unwrap();
+
+        let history = BuildHistory::load(temp_dir.path());
+        assert_eq!(history.records.len(), 5);
+        assert_eq!(history.rolling_median(2), None);
+        assert_eq!(history.rolling_median(3), Some(2.2));
+        assert!(history.slowdown(3).unwrap() > 4.0);
+        // Other commands are not compared against builds
+        assert_eq!(history.slowdown(4), None);
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Options shared by cargo's build, run, test and check, both typed on the
+/// command line and kept as per-project defaults in `.vibe/config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CargoArgs {
+    pub features: Vec<String>,
+    /// `None` when not given; `--all-features=false` turns a default off
+    pub all_features: Option<bool>,
+    /// Like `all_features`, the two never both hold
+    pub no_default_features: Option<bool>,
+    /// `-p`, the workspace member to build
+    pub package: Option<String>,
+    pub bin: Option<String>,
+    pub example: Option<String>,
+    /// `dev`, `release` or a custom `[profile.*]`
+    pub profile: Option<String>,
+    pub target_dir: Option<String>,
+    pub jobs: Option<u32>,
+}
+
+impl CargoArgs {
+    /// Picks the cargo options out of a command line. Everything else comes
+    /// back in order: a test name, `--exact`, `--` and the arguments after it.
+    pub fn parse(input: &str) -> Result<(Self, Vec<String>)> {
+        let mut args = CargoArgs::default();
+        let mut rest = Vec::new();
+        let mut words = split_words(input)?.into_iter();
+
+        while let Some(word) = words.next() {
+            if word == "--" {
+                rest.push(word);
+                rest.extend(words.by_ref());
+                break;
+            }
+            let (flag, inline) = match word.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                // `-j4`
+                _ if word.len() > 2 && word.starts_with("-j") => {
+                    ("-j".to_string(), Some(word[2..].to_string()))
+                }
+                _ => (word.clone(), None),
+            };
+            let mut value = |name: &str| {
+                inline
+                    .clone()
+                    .or_else(|| words.next())
+                    .ok_or_else(|| anyhow!("{} needs a value", name))
+            };
+            match flag.as_str() {
+                "--features" | "-F" => {
+                    let list = value(&flag)?;
+                    args.features.extend(
+                        list.split([',', ' '])
+                            .filter(|feature| !feature.is_empty())
+                            .map(String::from),
+                    );
+                }
+                "--all-features" => args.all_features = Some(switch(&flag, inline.as_deref())?),
+                "--no-default-features" => {
+                    args.no_default_features = Some(switch(&flag, inline.as_deref())?)
+                }
+                "-p" | "--package" => args.package = Some(value(&flag)?),
+                "--bin" => args.bin = Some(value(&flag)?),
+                "--example" => args.example = Some(value(&flag)?),
+                "--profile" => args.profile = Some(value(&flag)?),
+                "--release" | "-r" => args.profile = Some("release".to_string()),
+                "--target-dir" => args.target_dir = Some(value(&flag)?),
+                "-j" | "--jobs" => {
+                    let jobs = value(&flag)?;
+                    args.jobs = Some(
+                        jobs.parse()
+                            .map_err(|_| anyhow!("Invalid job count: {}", jobs))?,
+                    );
+                }
+                _ => rest.push(word),
+            }
+        }
+        if args.all_features == Some(true) && args.no_default_features == Some(true) {
+            return Err(anyhow!(
+                "--all-features and --no-default-features cannot be used together"
+            ));
+        }
+        Ok((args, rest))
+    }
+
+    /// These options with `defaults` filling in whatever was not given.
+    /// Features add up. Turning on `--all-features` or
+    /// `--no-default-features` also overrides the other's default, and of
+    /// two defaults that are both on `--all-features` wins.
+    pub fn or_defaults(&self, defaults: &CargoArgs) -> CargoArgs {
+        let mut features = defaults.features.clone();
+        for feature in &self.features {
+            if !features.contains(feature) {
+                features.push(feature.clone());
+            }
+        }
+        let (all_features, no_default_features) =
+            match (self.all_features, self.no_default_features) {
+                (Some(true), other) => (Some(true), Some(other.unwrap_or(false))),
+                (other, Some(true)) => (Some(other.unwrap_or(false)), Some(true)),
+                (all, none) => {
+                    let all = all.or(defaults.all_features);
+                    let none = none.or(defaults.no_default_features);
+                    (all, none.filter(|_| all != Some(true)))
+                }
+            };
+        CargoArgs {
+            features,
+            all_features,
+            no_default_features,
+            package: self.package.clone().or_else(|| defaults.package.clone()),
+            bin: self.bin.clone().or_else(|| defaults.bin.clone()),
+            example: self.example.clone().or_else(|| defaults.example.clone()),
+            profile: self.profile.clone().or_else(|| defaults.profile.clone()),
+            target_dir: self
+                .target_dir
+                .clone()
+                .or_else(|| defaults.target_dir.clone()),
+            jobs: self.jobs.or(defaults.jobs),
+        }
+    }
+
+    /// The options as cargo expects them, to follow the subcommand
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        if self.all_features == Some(true) {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features == Some(true) {
+            args.push("--no-default-features".to_string());
+        }
+        let options = [
+            ("-p", &self.package),
+            ("--bin", &self.bin),
+            ("--example", &self.example),
+            ("--profile", &self.profile),
+            ("--target-dir", &self.target_dir),
+        ];
+        for (flag, value) in options {
+            if let Some(value) = value {
+                args.push(flag.to_string());
+                args.push(value.clone());
+            }
+        }
+        if let Some(jobs) = self.jobs {
+            args.push("-j".to_string());
+            args.push(jobs.to_string());
+        }
+        args
+    }
+}
+
+/// The value of an on/off flag, `--flag` or `--flag=false`
+fn switch(flag: &str, value: Option<&str>) -> Result<bool> {
+    match value {
+        None | Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(value) => Err(anyhow!("{} takes true or false, not {}", flag, value)),
+    }
+}
+
+/// Splits a command line into words the way a shell would, honouring
+/// single and double quotes and backslash escapes
+pub fn split_words(input: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Unclosed quote in: {}", input));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cargo_args() {
+        let (args, rest) = CargoArgs::parse(
+            "--features \"serde audio\" -p engine --release -j4 physics --exact -- --nocapture",
+        )
+        .unwrap();
+        assert_eq!(args.features, vec!["serde", "audio"]);
+        assert_eq!(args.package.as_deref(), Some("engine"));
+        assert_eq!(args.profile.as_deref(), Some("release"));
+        assert_eq!(args.jobs, Some(4));
+        assert_eq!(rest, vec!["physics", "--exact", "--", "--nocapture"]);
+
+        let (args, _) = CargoArgs::parse("--features=net --bin=server").unwrap();
+        let defaults = CargoArgs {
+            features: vec!["audio".to_string()],
+            bin: Some("game".to_string()),
+            target_dir: Some("/tmp/target".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            args.or_defaults(&defaults).to_args(),
+            vec![
+                "--features",
+                "audio,net",
+                "--bin",
+                "server",
+                "--target-dir",
+                "/tmp/target"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_feature_switches_replace_defaults() {
+        let both = CargoArgs {
+            all_features: Some(true),
+            no_default_features: Some(true),
+            ..Default::default()
+        };
+        let merged = |input: &str| {
+            CargoArgs::parse(input)
+                .unwrap()
+                .0
+                .or_defaults(&both)
+                .to_args()
+        };
+        assert_eq!(merged(""), vec!["--all-features"]);
+        assert_eq!(
+            merged("--all-features=false"),
+            vec!["--no-default-features"]
+        );
+        assert_eq!(
+            merged("--no-default-features"),
+            vec!["--no-default-features"]
+        );
+        assert!(merged("--all-features=false --no-default-features=false").is_empty());
+
+        let err = CargoArgs::parse("--all-features --no-default-features").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--all-features and --no-default-features cannot be used together"
+        );
+        assert!(CargoArgs::parse("--all-features=yes").is_err());
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#"run "two words" it\'s 'a "b"'"#).unwrap(),
+            vec!["run", "two words", "it's", "a \"b\""]
+        );
+        assert!(split_words("run \"open").is_err());
+    }
+}
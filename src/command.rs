@@ -1,16 +1,118 @@
+use crate::build_history::{self, BuildHistory, BuildRecord};
+use crate::cargo_args::{self, CargoArgs};
+use crate::config;
+use crate::diagnostics::{self, CargoMessage, Diagnostic, Level, Suggestion};
+use crate::fix_prompt::PromptOptions;
+use crate::generate::Generator;
+use crate::manifest::{self, DependencySpec, Manifest};
 use crate::parser::RustParser;
+use crate::patch::InsertTarget;
 use crate::project::Project;
+use crate::refactor::RefactorPlan;
+use crate::reply::{self, CodeBlock};
+use crate::runner::{ProcessEvent, ProcessRunner};
+use crate::scaffold::Scaffold;
+use crate::test_results::{ReportFormat, TestRun, TestStatus};
+use crate::workspace::Scope;
 use anyhow::{anyhow, Result};
-use std::process::Command as ProcessCommand;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Search { query: String },
-    AddInto { file: String, code: String },
-    Build,
-    Run { args: Vec<String> },
-    Test { test_name: Option<String> },
-    Profile,
+    AddInto {
+        file: String,
+        target: Option<InsertTarget>,
+        code: String,
+        force: bool,
+    },
+    Replace {
+        file: String,
+        item: String,
+        code: String,
+        force: bool,
+    },
+    Delete { file: String, item: String },
+    Move {
+        file: String,
+        item: String,
+        target: String,
+    },
+    Rename { path: String, new_name: String },
+    ImplTrait { trait_name: String, type_name: String },
+    Generate { generator: Generator, type_name: String },
+    GenerateTests { file: String },
+    /// A pasted assistant reply with fenced code blocks
+    Reply { text: String },
+    /// Apply blocks of the last reply by number, or all of them
+    ApplyBlocks {
+        selection: Option<Vec<usize>>,
+        force: bool,
+    },
+    ApplyRefactor,
+    CancelRefactor,
+    /// Review, write or drop the merge held back by an external edit
+    ShowMerge,
+    ApplyMerge,
+    CancelMerge,
+    New { scaffold: Scaffold },
+    DepAdd { spec: DependencySpec },
+    DepRemove { name: String },
+    DepList,
+    FeatureAdd { name: String, enables: Vec<String> },
+    /// Apply compiler suggestions of the given diagnostics, or of all
+    Fix {
+        selection: Option<Vec<usize>>,
+        maybe_incorrect: bool,
+    },
+    /// Bundle the given diagnostics, or all errors, into a prompt for the
+    /// clipboard
+    FixPrompt {
+        selection: Option<Vec<usize>>,
+        options: PromptOptions,
+    },
+    /// Revert the last `fix`
+    Undo,
+    Build { cargo: CargoArgs, args: Vec<String> },
+    /// check, clippy, fmt, doc, bench, clean, tree or update, with extra
+    /// arguments passed on to cargo
+    Cargo {
+        tool: CargoTool,
+        cargo: CargoArgs,
+        args: Vec<String>,
+    },
+    /// `args` go to the program
+    Run { cargo: CargoArgs, args: Vec<String> },
+    /// `exact` matches the whole test path, as "rerun this test" does;
+    /// `libtest_args` are the ones after `--`
+    Test {
+        cargo: CargoArgs,
+        test_name: Option<String>,
+        exact: bool,
+        libtest_args: Vec<String>,
+    },
+    /// Rerun the tests that failed in the latest run
+    TestFailed,
+    /// Write the latest test results to a file
+    TestReport { format: ReportFormat, path: Option<String> },
+    /// A build with the release profile unless another one is given
+    Profile { cargo: CargoArgs },
+    /// List the packages and targets of the cargo workspace
+    ShowWorkspace,
+    /// Limit build, run, test, search and listing to a package and target;
+    /// no package clears the scope
+    Scope {
+        package: Option<String>,
+        target: Option<String>,
+    },
+    /// Show the last `count` recorded build, check, test and profile runs
+    Builds { count: usize },
+    /// List background processes
+    Ps,
+    /// Stop a background process, or kill it outright with `force`
+    Kill { id: usize, force: bool },
     ListFiles,
     ShowFile { file: String },
     ShowFunction { file: String, function: String },
@@ -18,11 +120,91 @@ pub enum Command {
     Help,
 }
 
+/// The cargo subcommands run as they are, beyond build, run and test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CargoTool {
+    Check,
+    Clippy,
+    Fmt,
+    Doc,
+    Bench,
+    Clean,
+    Tree,
+    Update,
+}
+
+impl CargoTool {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "check" => Some(CargoTool::Check),
+            "clippy" => Some(CargoTool::Clippy),
+            "fmt" => Some(CargoTool::Fmt),
+            "doc" => Some(CargoTool::Doc),
+            "bench" => Some(CargoTool::Bench),
+            "clean" => Some(CargoTool::Clean),
+            "tree" => Some(CargoTool::Tree),
+            "update" => Some(CargoTool::Update),
+            _ => None,
+        }
+    }
+
+    pub fn subcommand(&self) -> &'static str {
+        match self {
+            CargoTool::Check => "check",
+            CargoTool::Clippy => "clippy",
+            CargoTool::Fmt => "fmt",
+            CargoTool::Doc => "doc",
+            CargoTool::Bench => "bench",
+            CargoTool::Clean => "clean",
+            CargoTool::Tree => "tree",
+            CargoTool::Update => "update",
+        }
+    }
+
+    /// Whether it compiles, so its messages belong in the Diagnostics panel
+    /// and it takes the feature, package and profile options
+    fn reports_diagnostics(&self) -> bool {
+        matches!(
+            self,
+            CargoTool::Check | CargoTool::Clippy | CargoTool::Doc | CargoTool::Bench
+        )
+    }
+}
+
 impl Command {
     pub fn parse(input: &str) -> Result<Self> {
         let input = input.trim();
+
+        // `--force` on the first line of add, replace and apply skips the
+        // syntax check before writing; other commands keep the word
+        let (first_line, body) = input.split_once('\n').unwrap_or((input, ""));
+        let writes = matches!(
+            first_line.split_whitespace().next().map(str::to_lowercase).as_deref(),
+            Some("add" | "replace" | "apply")
+        );
+        let force = writes && first_line.split_whitespace().any(|word| word == "--force");
+        let stripped;
+        let input = if force {
+            let first_line: Vec<&str> = first_line.split_whitespace().filter(|word| *word != "--force").collect();
+            stripped = if body.is_empty() {
+                first_line.join(" ")
+            } else {
+                format!("{}\n{}", first_line.join(" "), body)
+            };
+            stripped.as_str()
+        } else {
+            input
+        };
         let parts: Vec<&str> = input.splitn(2, ' ').collect();
 
+        // A whole reply pasted from the chat, unless it is an explicit add or replace
+        let keyword = input.split_whitespace().next().unwrap_or("").to_lowercase();
+        if reply::looks_like_reply(input) && keyword != "add" && keyword != "replace" {
+            return Ok(Command::Reply {
+                text: input.to_string(),
+            });
+        }
+
         match parts[0].to_lowercase().as_str() {
             "search" => {
                 let query = parts.get(1).ok_or_else(|| anyhow!("Missing search query"))?;
@@ -32,34 +214,279 @@ impl Command {
             }
             "add" => {
                 if parts.len() < 2 {
-                    return Err(anyhow!("Usage: add into <file>\n<code>"));
+                    return Err(anyhow!("Usage: add into <file>[::<target>]\n<code>"));
                 }
                 let rest = parts[1];
                 if rest.starts_with("into ") {
                     let file_and_code: Vec<&str> = rest[5..].splitn(2, '\n').collect();
-                    let file = file_and_code[0].trim().to_string();
+                    let (file, target) = match file_and_code[0].split_once("::") {
+                        Some((file, target)) => (file, Some(InsertTarget::parse(target)?)),
+                        None => (file_and_code[0], None),
+                    };
                     let code = file_and_code
                         .get(1)
                         .unwrap_or(&"")
                         .to_string();
-                    Ok(Command::AddInto { file, code })
+                    Ok(Command::AddInto {
+                        file: file.trim().to_string(),
+                        target,
+                        code,
+                        force,
+                    })
                 } else {
                     Err(anyhow!("Expected 'add into <file>'"))
                 }
             }
-            "build" => Ok(Command::Build),
-            "run" => {
-                let args = parts
+            "replace" => {
+                let rest = parts
+                    .get(1)
+                    .ok_or_else(|| anyhow!("Usage: replace <file>::<item>\n<code>"))?;
+                let (path, code) = rest.split_once('\n').unwrap_or((rest, ""));
+                let (file, item) = path
+                    .split_once("::")
+                    .ok_or_else(|| anyhow!("Expected 'replace <file>::<item>'"))?;
+                Ok(Command::Replace {
+                    file: file.trim().to_string(),
+                    item: item.trim().to_string(),
+                    code: code.to_string(),
+                    force,
+                })
+            }
+            "delete" => {
+                let rest = parts
+                    .get(1)
+                    .ok_or_else(|| anyhow!("Usage: delete <file>::<item>"))?;
+                let (file, item) = rest
+                    .split_once("::")
+                    .ok_or_else(|| anyhow!("Expected 'delete <file>::<item>'"))?;
+                Ok(Command::Delete {
+                    file: file.trim().to_string(),
+                    item: item.trim().to_string(),
+                })
+            }
+            "move" => {
+                let rest = parts
                     .get(1)
-                    .map(|s| s.split_whitespace().map(String::from).collect())
-                    .unwrap_or_default();
-                Ok(Command::Run { args })
+                    .ok_or_else(|| anyhow!("Usage: move <file>::<item> to <target_file>"))?;
+                let (path, target) = rest
+                    .rsplit_once(" to ")
+                    .ok_or_else(|| anyhow!("Expected 'move <file>::<item> to <target_file>'"))?;
+                let (file, item) = path
+                    .split_once("::")
+                    .ok_or_else(|| anyhow!("Expected 'move <file>::<item> to <target_file>'"))?;
+                Ok(Command::Move {
+                    file: file.trim().to_string(),
+                    item: item.trim().to_string(),
+                    target: target.trim().to_string(),
+                })
+            }
+            "rename" => {
+                let rest = parts
+                    .get(1)
+                    .map(|s| s.trim())
+                    .ok_or_else(|| anyhow!("Usage: rename <file>[::<item>] <new_name>"))?;
+                match rest {
+                    "apply" => Ok(Command::ApplyRefactor),
+                    "cancel" => Ok(Command::CancelRefactor),
+                    _ => {
+                        let (path, new_name) = rest
+                            .rsplit_once(' ')
+                            .ok_or_else(|| anyhow!("Expected 'rename <file>[::<item>] <new_name>'"))?;
+                        Ok(Command::Rename {
+                            path: path.trim().to_string(),
+                            new_name: new_name.to_string(),
+                        })
+                    }
+                }
+            }
+            "merge" => match parts.get(1).map(|s| s.trim()) {
+                Some("show") => Ok(Command::ShowMerge),
+                Some("apply") => Ok(Command::ApplyMerge),
+                Some("cancel") => Ok(Command::CancelMerge),
+                _ => Err(anyhow!("Usage: merge show | apply | cancel")),
+            },
+            "new" => {
+                let rest = parts
+                    .get(1)
+                    .ok_or_else(|| anyhow!("Usage: new <module|bin|example|test|crate> <name>"))?;
+                Ok(Command::New {
+                    scaffold: Scaffold::parse(rest)?,
+                })
+            }
+            "dep" => {
+                let rest = parts
+                    .get(1)
+                    .map(|s| s.trim())
+                    .ok_or_else(|| anyhow!("Usage: dep add <crate>[@<version>] [--features a,b] | dep remove <crate> | dep list"))?;
+                let (action, args) = rest.split_once(' ').unwrap_or((rest, ""));
+                match action {
+                    "add" => Ok(Command::DepAdd {
+                        spec: DependencySpec::parse(args)?,
+                    }),
+                    "remove" | "rm" if !args.trim().is_empty() => Ok(Command::DepRemove {
+                        name: args.trim().to_string(),
+                    }),
+                    "list" => Ok(Command::DepList),
+                    _ => Err(anyhow!("Expected 'dep add', 'dep remove <crate>' or 'dep list'")),
+                }
+            }
+            "feature" => {
+                let rest = parts
+                    .get(1)
+                    .and_then(|s| s.trim().strip_prefix("add "))
+                    .ok_or_else(|| anyhow!("Usage: feature add <name> [<dep:crate> | <crate>/<feature> ...]"))?;
+                let mut words = rest.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty());
+                let name = words.next().ok_or_else(|| anyhow!("Missing feature name"))?;
+                Ok(Command::FeatureAdd {
+                    name: name.to_string(),
+                    enables: words.map(String::from).collect(),
+                })
+            }
+            "impl" => {
+                let rest = parts
+                    .get(1)
+                    .ok_or_else(|| anyhow!("Usage: impl <Trait> for <Type>"))?;
+                let (trait_name, type_name) = rest
+                    .split_once(" for ")
+                    .ok_or_else(|| anyhow!("Expected 'impl <Trait> for <Type>'"))?;
+                Ok(Command::ImplTrait {
+                    trait_name: trait_name.trim().to_string(),
+                    type_name: type_name.trim().to_string(),
+                })
+            }
+            "gen" => {
+                let rest = parts
+                    .get(1)
+                    .ok_or_else(|| anyhow!("Usage: gen <new|builder|getters> <Type> or gen tests <file>"))?;
+                let (kind, type_name) = rest
+                    .trim()
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("Expected 'gen <new|builder|getters> <Type>' or 'gen tests <file>'"))?;
+                if kind == "tests" {
+                    return Ok(Command::GenerateTests {
+                        file: type_name.trim().to_string(),
+                    });
+                }
+                Ok(Command::Generate {
+                    generator: Generator::parse(kind)?,
+                    type_name: type_name.trim().to_string(),
+                })
+            }
+            "apply" => {
+                let rest = parts
+                    .get(1)
+                    .map(|s| s.trim())
+                    .ok_or_else(|| anyhow!("Usage: apply <n> [<n> ...] | apply all"))?;
+                if rest == "all" {
+                    return Ok(Command::ApplyBlocks { selection: None, force });
+                }
+                let selection = rest
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|n| !n.is_empty())
+                    .map(|n| n.parse::<usize>().map_err(|_| anyhow!("Invalid block number: {}", n)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Command::ApplyBlocks {
+                    selection: Some(selection),
+                    force,
+                })
+            }
+            "build" => {
+                let (cargo, args) = CargoArgs::parse(parts.get(1).unwrap_or(&""))?;
+                Ok(Command::Build { cargo, args })
+            }
+            "fix" => {
+                let words: Vec<&str> = parts.get(1).map(|s| s.split_whitespace().collect()).unwrap_or_default();
+                if words.first() == Some(&"prompt") {
+                    return parse_fix_prompt(&words[1..]);
+                }
+                let maybe_incorrect = words.contains(&"--maybe-incorrect");
+                let selection = words
+                    .iter()
+                    .filter(|word| !word.starts_with("--"))
+                    .map(|n| n.parse::<usize>().map_err(|_| anyhow!("Invalid diagnostic number: {}", n)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Command::Fix {
+                    selection: (!selection.is_empty()).then_some(selection),
+                    maybe_incorrect,
+                })
+            }
+            "undo" => Ok(Command::Undo),
+            "run" => {
+                let (cargo, mut args) = CargoArgs::parse(parts.get(1).unwrap_or(&""))?;
+                if let Some(separator) = args.iter().position(|arg| arg == "--") {
+                    args.remove(separator);
+                }
+                Ok(Command::Run { cargo, args })
             }
             "test" => {
-                let test_name = parts.get(1).map(|s| s.to_string());
-                Ok(Command::Test { test_name })
+                let (cargo, mut rest) = CargoArgs::parse(parts.get(1).unwrap_or(&""))?;
+                let libtest_args = match rest.iter().position(|arg| arg == "--") {
+                    Some(separator) => rest.split_off(separator).split_off(1),
+                    None => Vec::new(),
+                };
+                let words: Vec<&str> = rest.iter().map(String::as_str).collect();
+                match words.as_slice() {
+                    ["--failed"] => Ok(Command::TestFailed),
+                    ["report", format, rest @ ..] => {
+                        let format = ReportFormat::parse(format)
+                            .ok_or_else(|| anyhow!("Usage: test report <json|junit> [path]"))?;
+                        Ok(Command::TestReport {
+                            format,
+                            path: rest.first().map(|path| path.to_string()),
+                        })
+                    }
+                    _ => Ok(Command::Test {
+                        test_name: words.iter().find(|word| !word.starts_with("--")).map(|s| s.to_string()),
+                        exact: words.contains(&"--exact"),
+                        cargo,
+                        libtest_args,
+                    }),
+                }
+            }
+            "profile" => {
+                let (cargo, _) = CargoArgs::parse(parts.get(1).unwrap_or(&""))?;
+                Ok(Command::Profile { cargo })
+            }
+            "workspace" => Ok(Command::ShowWorkspace),
+            "scope" => {
+                let words: Vec<&str> = parts.get(1).map(|s| s.split_whitespace().collect()).unwrap_or_default();
+                match words.as_slice() {
+                    [] => Ok(Command::ShowWorkspace),
+                    ["all"] => Ok(Command::Scope {
+                        package: None,
+                        target: None,
+                    }),
+                    [package] => Ok(Command::Scope {
+                        package: Some(package.to_string()),
+                        target: None,
+                    }),
+                    [package, target] => Ok(Command::Scope {
+                        package: Some(package.to_string()),
+                        target: Some(target.to_string()),
+                    }),
+                    _ => Err(anyhow!("Usage: scope <package> [<target>] | all")),
+                }
+            }
+            "builds" => {
+                let count = match parts.get(1).map(|s| s.trim()) {
+                    Some(count) => count.parse().map_err(|_| anyhow!("Usage: builds [count]"))?,
+                    None => 10,
+                };
+                Ok(Command::Builds { count })
+            }
+            "ps" => Ok(Command::Ps),
+            "kill" => {
+                let words: Vec<&str> = parts.get(1).map(|s| s.split_whitespace().collect()).unwrap_or_default();
+                let force = words.iter().any(|word| matches!(*word, "-9" | "-KILL" | "--force"));
+                let id = words
+                    .iter()
+                    .find(|word| !word.starts_with('-'))
+                    .ok_or_else(|| anyhow!("Usage: kill [-9] <id>"))?;
+                Ok(Command::Kill {
+                    id: id.parse().map_err(|_| anyhow!("Invalid process id: {}", id))?,
+                    force,
+                })
             }
-            "profile" => Ok(Command::Profile),
             "list" => {
                 if let Some(rest) = parts.get(1) {
                     if rest.starts_with("files") {
@@ -93,30 +520,331 @@ impl Command {
                 }
             }
             "help" => Ok(Command::Help),
-            _ => Err(anyhow!("Unknown command: {}", parts[0])),
+            name => match CargoTool::parse(name) {
+                Some(tool) if tool.reports_diagnostics() => {
+                    let (cargo, args) = CargoArgs::parse(parts.get(1).unwrap_or(&""))?;
+                    Ok(Command::Cargo { tool, cargo, args })
+                }
+                Some(tool) => Ok(Command::Cargo {
+                    tool,
+                    cargo: CargoArgs::default(),
+                    args: cargo_args::split_words(parts.get(1).unwrap_or(&""))?,
+                }),
+                None => Err(anyhow!("Unknown command: {}", parts[0])),
+            },
         }
     }
 }
 
 pub struct CommandExecutor {
     parser: RustParser,
+    /// A previewed refactoring waiting for `rename apply`
+    pending_refactor: Option<RefactorPlan>,
+    /// Code blocks of the last pasted reply, waiting for `apply`
+    pending_blocks: Vec<CodeBlock>,
+    runner: ProcessRunner,
+    /// The process the last command started, for the UI to follow
+    started_process: Option<usize>,
+    /// Processes printing cargo's JSON messages, with the diagnostics each
+    /// has reported so far, see `poll_processes`
+    json_processes: HashMap<usize, Vec<Diagnostic>>,
+    /// Errors and warnings of the latest build, check or test
+    diagnostics: Vec<Diagnostic>,
+    /// The JSON-mode process `diagnostics` belongs to
+    diagnostics_process: Option<usize>,
+    /// `cargo test` processes whose output feeds `test_run`
+    test_processes: HashSet<usize>,
+    /// Results of the latest `cargo test`
+    test_run: TestRun,
+    /// Whether the toolchain is nightly, where libtest can print JSON
+    libtest_json: Option<bool>,
+    /// Options of the latest `test`, reused to rerun failed tests
+    last_test_args: CargoArgs,
+    /// Running builds, checks, tests and profiles to log in the build history
+    tracked_builds: HashMap<usize, TrackedBuild>,
+    /// The latest `fix prompt`, waiting for the GUI to copy it
+    fix_prompt: Option<String>,
+}
+
+/// What is known about a tracked run when it starts
+struct TrackedBuild {
+    root_path: PathBuf,
+    started: String,
+    git_head: Option<String>,
 }
 
 impl CommandExecutor {
     pub fn new() -> Self {
         Self {
             parser: RustParser::new(),
+            pending_refactor: None,
+            pending_blocks: Vec::new(),
+            runner: ProcessRunner::new(),
+            started_process: None,
+            json_processes: HashMap::new(),
+            diagnostics: Vec::new(),
+            diagnostics_process: None,
+            test_processes: HashSet::new(),
+            test_run: TestRun::default(),
+            libtest_json: None,
+            last_test_args: CargoArgs::default(),
+            tracked_builds: HashMap::new(),
+            fix_prompt: None,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn test_run(&self) -> &TestRun {
+        &self.test_run
+    }
+
+    pub fn runner(&self) -> &ProcessRunner {
+        &self.runner
+    }
+
+    /// Output and exits of background processes since the last call.
+    /// Compiler messages of JSON-mode processes are collected per process,
+    /// those of the latest one into `diagnostics`, and passed on as rustc's
+    /// rendered text; test output
+    /// is parsed into `test_run`. Finished builds are logged in the build
+    /// history of the project they ran in.
+    pub fn poll_processes(&mut self, project: &mut Option<Project>) -> Vec<ProcessEvent> {
+        let mut events = Vec::new();
+        for event in self.runner.poll() {
+            match event {
+                ProcessEvent::Output { id, line } if self.json_processes.contains_key(&id) => {
+                    match diagnostics::parse_line(&line) {
+                        None if self.test_processes.contains(&id) => {
+                            if let Some(line) = self.test_run.feed(&line) {
+                                events.push(ProcessEvent::Output { id, line });
+                            }
+                        }
+                        None => events.push(ProcessEvent::Output { id, line }),
+                        Some(CargoMessage::Diagnostic(diagnostic)) => {
+                            if let Some(rendered) = &diagnostic.rendered {
+                                events.push(ProcessEvent::Output {
+                                    id,
+                                    line: rendered.trim_end().to_string(),
+                                });
+                            }
+                            // "aborting due to ..." and "N warnings emitted" point nowhere
+                            if diagnostic.primary_span().is_some() {
+                                if self.diagnostics_process == Some(id) {
+                                    self.diagnostics.push(diagnostic.clone());
+                                }
+                                self.json_processes.entry(id).or_default().push(diagnostic);
+                            }
+                        }
+                        Some(_) => {}
+                    }
+                }
+                ProcessEvent::Finished { id, exit_code, success } if self.json_processes.contains_key(&id) => {
+                    let reported = self.json_processes.remove(&id).unwrap_or_default();
+                    let count = |level: Level| reported.iter().filter(|d| d.level == level).count();
+                    let (errors, warnings) = (count(Level::Error), count(Level::Warning));
+                    if errors + warnings > 0 {
+                        let listed = if self.diagnostics_process == Some(id) {
+                            ", listed under Diagnostics"
+                        } else {
+                            ""
+                        };
+                        events.push(ProcessEvent::Output {
+                            id,
+                            line: format!("{} error(s), {} warning(s){}", errors, warnings, listed),
+                        });
+                    }
+                    let broken_links = reported
+                        .iter()
+                        .filter(|d| d.code.as_deref() == Some("rustdoc::broken_intra_doc_links"))
+                        .count();
+                    if broken_links > 0 {
+                        events.push(ProcessEvent::Output {
+                            id,
+                            line: format!("{} broken intra-doc link(s)", broken_links),
+                        });
+                    }
+                    if self.test_processes.remove(&id) && !self.test_run.cases.is_empty() {
+                        let run = &self.test_run;
+                        events.push(ProcessEvent::Output {
+                            id,
+                            line: format!(
+                                "{} passed, {} failed, {} ignored, listed under Test Results",
+                                run.count(TestStatus::Passed),
+                                run.count(TestStatus::Failed),
+                                run.count(TestStatus::Ignored)
+                            ),
+                        });
+                    }
+                    if let Some(line) = self.log_build(project, id, exit_code, success, errors, warnings) {
+                        events.push(ProcessEvent::Output { id, line });
+                    }
+                    events.push(event);
+                }
+                event => events.push(event),
+            }
         }
+        events
+    }
+
+    /// Appends a finished tracked run to its project's build history.
+    /// Returns a line to show when it was much slower than usual or could
+    /// not be logged.
+    fn log_build(
+        &mut self,
+        project: &mut Option<Project>,
+        id: usize,
+        exit_code: Option<i32>,
+        success: bool,
+        errors: usize,
+        warnings: usize,
+    ) -> Option<String> {
+        let tracked = self.tracked_builds.remove(&id)?;
+        let process = self.runner.process(id)?;
+        let record = BuildRecord {
+            started: tracked.started,
+            duration_secs: process.elapsed().as_secs_f64(),
+            command: process
+                .command_line
+                .replace(&format!(" {}", diagnostics::MESSAGE_FORMAT), ""),
+            exit_code,
+            success,
+            warnings,
+            errors,
+            git_head: tracked.git_head,
+        };
+
+        // The project may have been closed or switched while it ran
+        let Some(project) = project.as_mut().filter(|p| p.root_path == tracked.root_path) else {
+            return BuildHistory::default()
+                .record(&tracked.root_path, record)
+                .err()
+                .map(|e| format!("Build history not saved: {}", e));
+        };
+        if let Err(e) = project.builds.record(&tracked.root_path, record) {
+            return Some(format!("Build history not saved: {}", e));
+        }
+        let index = project.builds.records.len() - 1;
+        let ratio = project.builds.slowdown(index)?;
+        Some(format!(
+            "⚠ {:.1}× slower than the median of recent runs ({:.1}s)",
+            ratio,
+            project.builds.rolling_median(index).unwrap_or_default()
+        ))
+    }
+
+    /// The prompt the last `fix prompt` built, for the clipboard
+    pub fn take_fix_prompt(&mut self) -> Option<String> {
+        self.fix_prompt.take()
+    }
+
+    /// The process the last executed command started, if any
+    pub fn take_started_process(&mut self) -> Option<usize> {
+        self.started_process.take()
+    }
+
+    pub fn pending_blocks(&self) -> &[CodeBlock] {
+        &self.pending_blocks
+    }
+
+    /// Lets the GUI fill in a target file before applying
+    pub fn pending_blocks_mut(&mut self) -> &mut [CodeBlock] {
+        &mut self.pending_blocks
     }
 
     pub fn execute(&mut self, command: Command, project: &mut Option<Project>) -> Result<String> {
         match command {
             Command::Search { query } => self.search(project, &query),
-            Command::AddInto { file, code } => self.add_into(project, &file, &code),
-            Command::Build => self.build(project),
-            Command::Run { args } => self.run(project, args),
-            Command::Test { test_name } => self.test(project, test_name),
-            Command::Profile => self.profile(project),
+            Command::AddInto {
+                file,
+                target,
+                code,
+                force,
+            } => self.add_into(project, &file, target.as_ref(), &code, force),
+            Command::Replace {
+                file,
+                item,
+                code,
+                force,
+            } => self.replace(project, &file, &item, &code, force),
+            Command::Delete { file, item } => self.delete(project, &file, &item),
+            Command::Move { file, item, target } => self.move_item(project, &file, &item, &target),
+            Command::Rename { path, new_name } => self.rename(project, &path, &new_name),
+            Command::ImplTrait { trait_name, type_name } => self.impl_trait(project, &trait_name, &type_name),
+            Command::Generate { generator, type_name } => self.generate(project, generator, &type_name),
+            Command::GenerateTests { file } => self.generate_tests(project, &file),
+            Command::Reply { text } => Ok(self.read_reply(project, &text)),
+            Command::ApplyBlocks { selection, force } => self.apply_blocks(project, selection, force),
+            Command::ApplyRefactor => self.apply_refactor(project),
+            Command::CancelRefactor => match self.pending_refactor.take() {
+                Some(plan) => Ok(format!("Discarded: {}", plan.description)),
+                None => Ok("Nothing to cancel".to_string()),
+            },
+            Command::ShowMerge => self.show_merge(project),
+            Command::ApplyMerge => self.apply_merge(project),
+            Command::CancelMerge => {
+                let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+                match project.cancel_merge() {
+                    Some(merge) => Ok(format!("Discarded the merge for {}", merge.file)),
+                    None => Ok("Nothing to cancel".to_string()),
+                }
+            }
+            Command::New { scaffold } => {
+                let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+                Ok(project.scaffold(&scaffold)?.join("\n"))
+            }
+            Command::DepAdd { spec } => self.dep_add(project, spec),
+            Command::DepRemove { name } => self.dep_remove(project, &name),
+            Command::DepList => self.dep_list(project),
+            Command::FeatureAdd { name, enables } => self.feature_add(project, &name, &enables),
+            Command::Build { cargo, args } => self.build(project, cargo, args),
+            Command::Cargo { tool, cargo, args } => self.cargo(project, tool, cargo, args),
+            Command::Fix {
+                selection,
+                maybe_incorrect,
+            } => self.fix(project, selection, maybe_incorrect),
+            Command::FixPrompt { selection, options } => self.fix_prompt(project, selection, options),
+            Command::Undo => {
+                let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+                Ok(format!("Undid {}", project.undo()?))
+            }
+            Command::Run { cargo, args } => self.run(project, cargo, args),
+            Command::Test {
+                cargo,
+                test_name,
+                exact,
+                libtest_args,
+            } => self.test(project, cargo, test_name.into_iter().collect(), exact, libtest_args),
+            Command::TestFailed => {
+                let failed: Vec<String> = self.test_run.failed_names().into_iter().map(String::from).collect();
+                if failed.is_empty() {
+                    return Err(anyhow!("No failed tests in the latest run"));
+                }
+                self.test(project, self.last_test_args.clone(), failed, true, Vec::new())
+            }
+            Command::TestReport { format, path } => self.test_report(project, format, path),
+            Command::Profile { cargo } => self.profile(project, cargo),
+            Command::ShowWorkspace => self.show_workspace(project),
+            Command::Scope { package, target } => {
+                let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+                project.set_scope(package.as_deref(), target.as_deref())?;
+                Ok(match project.scope() {
+                    Scope {
+                        package: Some(package),
+                        target: Some(target),
+                    } => format!("Scoped to {} ({})", package, target.label()),
+                    Scope {
+                        package: Some(package),
+                        target: None,
+                    } => format!("Scoped to {}", package),
+                    _ => "Scope cleared, commands cover the whole workspace".to_string(),
+                })
+            }
+            Command::Builds { count } => self.builds(project, count),
+            Command::Ps => Ok(self.ps()),
+            Command::Kill { id, force } => self.kill(id, force),
             Command::ListFiles => self.list_files(project),
             Command::ShowFile { file } => self.show_file(project, &file),
             Command::ShowFunction { file, function } => self.show_function(project, &file, &function),
@@ -140,73 +868,705 @@ impl CommandExecutor {
         }
     }
 
-    fn add_into(&self, project: &mut Option<Project>, file: &str, code: &str) -> Result<String> {
+    fn add_into(
+        &self,
+        project: &mut Option<Project>,
+        file: &str,
+        target: Option<&InsertTarget>,
+        code: &str,
+        force: bool,
+    ) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        let insertion = project.add_code(file, target, code, force)?;
+
+        let mut output = match target {
+            Some(target) => format!("Code added to {}::{}", file, target),
+            None => format!("Code added to {}", file),
+        };
+        if !insertion.unused_imports.is_empty() {
+            output.push_str("\n\nPossibly unused imports introduced by the snippet:\n");
+            for import in &insertion.unused_imports {
+                output.push_str(&format!("  - {}\n", import));
+            }
+        }
+        Ok(output)
+    }
+
+    fn replace(
+        &self,
+        project: &mut Option<Project>,
+        file: &str,
+        item: &str,
+        code: &str,
+        force: bool,
+    ) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        if code.trim().is_empty() {
+            return Err(anyhow!("Missing replacement code for '{}'", item));
+        }
+        let insertion = project.replace_item(file, item, code, force)?;
+
+        let mut output = format!("Replaced {}::{}", file, item);
+        if !insertion.unused_imports.is_empty() {
+            output.push_str("\n\nPossibly unused imports introduced by the snippet:\n");
+            for import in &insertion.unused_imports {
+                output.push_str(&format!("  - {}\n", import));
+            }
+        }
+        Ok(output)
+    }
+
+    fn delete(&self, project: &mut Option<Project>, file: &str, item: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        project.delete_item(file, item)?;
+        Ok(format!("Deleted {}::{}", file, item))
+    }
+
+    fn move_item(&self, project: &mut Option<Project>, file: &str, item: &str, target: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        let report = project.move_item(file, item, target)?;
+
+        let mut output = format!("Moved {}::{} to {}", file, item, target);
+        if !report.updated.is_empty() {
+            output.push_str("\n\nUpdated paths in:\n");
+            for updated in &report.updated {
+                output.push_str(&format!("  - {}\n", updated));
+            }
+        }
+        if !report.stale_imports.is_empty() {
+            output.push_str(&format!("\n\nImports in {} that may no longer be needed:\n", file));
+            for import in &report.stale_imports {
+                output.push_str(&format!("  - {}\n", import));
+            }
+        }
+        if !report.unused_imports.is_empty() {
+            output.push_str(&format!("\n\nPossibly unused imports in {}:\n", target));
+            for import in &report.unused_imports {
+                output.push_str(&format!("  - {}\n", import));
+            }
+        }
+        Ok(output)
+    }
+
+    fn rename(&mut self, project: &Option<Project>, path: &str, new_name: &str) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let plan = project.plan_rename(path, new_name)?;
+        if plan.change_count() == 0 && plan.moves.is_empty() {
+            return Ok(format!("No occurrences of '{}' found", path));
+        }
+
+        let output = format!(
+            "{}\nType 'rename apply' to apply or 'rename cancel' to discard.",
+            plan.preview(&project.root_path)
+        );
+        self.pending_refactor = Some(plan);
+        Ok(output)
+    }
+
+    fn apply_refactor(&mut self, project: &mut Option<Project>) -> Result<String> {
         let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
-        project.add_code(file, code)?;
-        Ok(format!("Code added to {}", file))
+        let plan = self
+            .pending_refactor
+            .take()
+            .ok_or_else(|| anyhow!("Nothing to apply, run 'rename' first"))?;
+        project.apply_refactor(&plan)?;
+        Ok(format!(
+            "{}: {} change(s) in {} file(s) applied",
+            plan.description,
+            plan.change_count(),
+            plan.files.len()
+        ))
     }
 
-    fn build(&self, project: &Option<Project>) -> Result<String> {
+    fn show_merge(&self, project: &Option<Project>) -> Result<String> {
         let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
-        let output = ProcessCommand::new("cargo")
-            .arg("build")
-            .current_dir(&project.root_path)
-            .output()?;
+        let merge = project
+            .pending_merge()
+            .ok_or_else(|| anyhow!("No merge pending"))?;
+        Ok(format!(
+            "Merged {} ({} conflict(s)):\n\n{}\nType 'merge apply' to write it or 'merge cancel' to drop it.",
+            merge.file, merge.conflicts, merge.merged
+        ))
+    }
+
+    fn apply_merge(&self, project: &mut Option<Project>) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        let merge = project.apply_merge()?;
+        if merge.conflicts == 0 {
+            Ok(format!("Merged change written to {}", merge.file))
+        } else {
+            Ok(format!(
+                "Merged change written to {}; resolve the {} region(s) marked with <<<<<<< before building",
+                merge.file, merge.conflicts
+            ))
+        }
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    fn dep_add(&self, project: &Option<Project>, mut spec: DependencySpec) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let mut manifest = Manifest::load(&project.root_path)?;
+        let versions = manifest::offline_versions(&spec.name);
+        let existing = manifest.has_dependency(spec.kind, &spec.name);
 
-        if output.status.success() {
-            Ok(format!("Build successful!\n\n{}{}", stdout, stderr))
+        let mut notes = Vec::new();
+        match &spec.version {
+            // An existing entry keeps its requirement, path or git source
+            None if existing => {}
+            None => {
+                let newest = manifest::newest_release(&versions).ok_or_else(|| {
+                    anyhow!(
+                        "'{}' is not in the local registry cache, give a version with {}@<version> to add it anyway",
+                        spec.name,
+                        spec.name
+                    )
+                })?;
+                spec.version = Some(newest.clone());
+            }
+            Some(requirement) if !versions.iter().any(|v| manifest::satisfies(requirement, v)) => {
+                notes.push(format!(
+                    "No cached version of '{}' matches {}, an offline build will fail.",
+                    spec.name, requirement
+                ));
+            }
+            Some(_) => {}
+        }
+
+        manifest.add_dependency(&spec)?;
+        manifest.save()?;
+
+        let mut output = match (existing, &spec.version) {
+            (true, None) => format!("Updated {} in [{}]", spec.name, spec.kind.section()),
+            (true, Some(version)) => format!(
+                "Updated {} to \"{}\" in [{}]",
+                spec.name,
+                version,
+                spec.kind.section()
+            ),
+            (false, version) => format!(
+                "Added {} = \"{}\" to [{}]",
+                spec.name,
+                version.as_deref().unwrap_or("*"),
+                spec.kind.section()
+            ),
+        };
+        if !spec.features.is_empty() {
+            output.push_str(&format!(" with features {}", spec.features.join(", ")));
+        }
+        for note in notes {
+            output.push_str(&format!("\n{}", note));
+        }
+        if versions.is_empty() {
+            output.push_str("\nNo versions available offline.");
         } else {
-            Ok(format!("Build failed!\n\n{}{}", stdout, stderr))
+            output.push_str(&format!("\nAvailable offline: {}", versions.join(", ")));
         }
+        Ok(output)
     }
 
-    fn run(&self, project: &Option<Project>, args: Vec<String>) -> Result<String> {
+    fn dep_remove(&self, project: &Option<Project>, name: &str) -> Result<String> {
         let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
-        let mut cmd = ProcessCommand::new("cargo");
-        cmd.arg("run").current_dir(&project.root_path);
-        
+        let mut manifest = Manifest::load(&project.root_path)?;
+        let removed = manifest.remove_dependency(name)?;
+        manifest.save()?;
+
+        let sections: Vec<String> = removed.iter().map(|kind| format!("[{}]", kind.section())).collect();
+        Ok(format!("Removed {} from {}", name, sections.join(" and ")))
+    }
+
+    fn dep_list(&self, project: &Option<Project>) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let manifest = Manifest::load(&project.root_path)?;
+        let entries = manifest.dependencies();
+        if entries.is_empty() {
+            return Ok("No dependencies".to_string());
+        }
+
+        let mut output = String::new();
+        let mut section = None;
+        for entry in &entries {
+            if section != Some(entry.kind) {
+                output.push_str(&format!("\n[{}]\n", entry.kind.section()));
+                section = Some(entry.kind);
+            }
+            output.push_str(&format!("  {} {}", entry.name, entry.source));
+            if !entry.features.is_empty() {
+                output.push_str(&format!(" (features: {})", entry.features.join(", ")));
+            }
+            let registry = !entry.source.starts_with("path") && !entry.source.starts_with("git");
+            if registry && manifest::offline_versions(&entry.name).is_empty() {
+                output.push_str("  [not cached]");
+            }
+            output.push('\n');
+        }
+        Ok(output.trim_start().to_string())
+    }
+
+    fn feature_add(&self, project: &Option<Project>, name: &str, enables: &[String]) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let mut manifest = Manifest::load(&project.root_path)?;
+        manifest.add_feature(name, enables)?;
+        manifest.save()?;
+        if enables.is_empty() {
+            Ok(format!("Added feature '{}'", name))
+        } else {
+            Ok(format!("Feature '{}' enables {}", name, enables.join(", ")))
+        }
+    }
+
+    fn impl_trait(&self, project: &mut Option<Project>, trait_name: &str, type_name: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        let (file, insertion) = project.implement_trait(trait_name, type_name)?;
+
+        let mut output = format!("Added impl {} for {} to {}", trait_name, type_name, file);
+        if !insertion.unused_imports.is_empty() {
+            output.push_str("\n\nPossibly unused imports introduced by the impl:\n");
+            for import in &insertion.unused_imports {
+                output.push_str(&format!("  - {}\n", import));
+            }
+        }
+        Ok(output)
+    }
+
+    fn generate(&self, project: &mut Option<Project>, generator: Generator, type_name: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        let (file, generated, _) = project.generate_for_type(generator, type_name)?;
+
+        let mut output = format!(
+            "Added {} method(s) to {} in {}",
+            generated.methods.len(),
+            type_name,
+            file
+        );
+        if let Some(builder) = &generated.builder {
+            output.push_str(&format!(" and added {}", builder.name));
+        }
+        if !generated.skipped.is_empty() {
+            output.push_str(&format!("\nSkipped existing: {}", generated.skipped.join(", ")));
+        }
+        Ok(output)
+    }
+
+    fn generate_tests(&self, project: &mut Option<Project>, file: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        let (stubs, _) = project.generate_tests(file)?;
+
+        let mut output = if stubs.tests.is_empty() {
+            format!("No untested public functions in {}", file)
+        } else {
+            format!("Added {} stub test(s) to {}::mod tests", stubs.tests.len(), file)
+        };
+        if !stubs.covered.is_empty() {
+            output.push_str(&format!("\nAlready tested: {}", stubs.covered.join(", ")));
+        }
+        if !stubs.skipped.is_empty() {
+            output.push_str(&format!("\nSkipped: {}", stubs.skipped.join(", ")));
+        }
+        Ok(output)
+    }
+
+    fn read_reply(&mut self, project: &Option<Project>, text: &str) -> String {
+        let root = project.as_ref().map(|project| project.root_path.as_path());
+        self.pending_blocks = reply::extract_blocks(text, root);
+        if self.pending_blocks.is_empty() {
+            return "No Rust code blocks found in the reply".to_string();
+        }
+
+        let mut output = format!("Found {} code block(s):\n\n", self.pending_blocks.len());
+        for (i, block) in self.pending_blocks.iter().enumerate() {
+            output.push_str(&format!("{}. {}\n", i + 1, block.summary()));
+        }
+        output.push_str("\nUse 'apply <n> ...' or 'apply all' to add them to the project.");
+        output
+    }
+
+    fn apply_blocks(
+        &mut self,
+        project: &mut Option<Project>,
+        selection: Option<Vec<usize>>,
+        force: bool,
+    ) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        if self.pending_blocks.is_empty() {
+            return Err(anyhow!("No code blocks to apply, paste a reply first"));
+        }
+        let selection = selection.unwrap_or_else(|| (1..=self.pending_blocks.len()).collect());
+
+        let mut output = String::new();
+        for number in selection {
+            let block = self
+                .pending_blocks
+                .get(number.wrapping_sub(1))
+                .ok_or_else(|| anyhow!("There is no block {}", number))?;
+            let Some(file) = &block.file else {
+                output.push_str(&format!("{}. skipped: no target file, use 'add into <file>'\n", number));
+                continue;
+            };
+
+            match project.add_code(file, block.target.as_ref(), &block.code, force) {
+                Ok(insertion) => {
+                    output.push_str(&format!("{}. applied to {}\n", number, block.summary()));
+                    for import in &insertion.unused_imports {
+                        output.push_str(&format!("   possibly unused import: {}\n", import));
+                    }
+                }
+                Err(e) => output.push_str(&format!("{}. failed: {}\n", number, e)),
+            }
+        }
+        Ok(output)
+    }
+
+    /// Applies the suggestions of diagnostics `selection` (1-based), or of
+    /// all diagnostics. Picking diagnostics by number also takes their
+    /// maybe-incorrect suggestions; `fix` alone needs `--maybe-incorrect`.
+    fn fix(
+        &mut self,
+        project: &mut Option<Project>,
+        selection: Option<Vec<usize>>,
+        maybe_incorrect: bool,
+    ) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
+        if self.diagnostics.is_empty() {
+            return Err(anyhow!("No diagnostics, run 'check' or 'build' first"));
+        }
+        let (chosen, maybe_incorrect) = match selection {
+            Some(numbers) => {
+                for &number in &numbers {
+                    if number == 0 || number > self.diagnostics.len() {
+                        return Err(anyhow!("There is no diagnostic {}", number));
+                    }
+                }
+                (numbers.iter().map(|number| number - 1).collect::<Vec<_>>(), true)
+            }
+            None => ((0..self.diagnostics.len()).collect(), maybe_incorrect),
+        };
+
+        // Each suggestion with the diagnostic it belongs to
+        let suggestions: Vec<(usize, &Suggestion)> = chosen
+            .iter()
+            .flat_map(|&idx| self.diagnostics[idx].suggestions.iter().map(move |suggestion| (idx, suggestion)))
+            .filter(|(_, suggestion)| suggestion.is_applicable(maybe_incorrect))
+            .collect();
+        if suggestions.is_empty() {
+            return Ok("No applicable suggestions".to_string());
+        }
+        let report = project.apply_suggestions(&suggestions.iter().map(|(_, suggestion)| *suggestion).collect::<Vec<_>>())?;
+
+        // A diagnostic is resolved when each of its suggestions went in or
+        // lost out to an alternative of its own that did
+        let applied = |index: usize| report.applied.contains(&index);
+        let fixed: HashSet<usize> = chosen
+            .into_iter()
+            .filter(|&idx| {
+                let own: Vec<usize> = (0..suggestions.len()).filter(|&index| suggestions[index].0 == idx).collect();
+                own.iter().any(|&index| applied(index))
+                    && own.iter().all(|&index| {
+                        applied(index)
+                            || own
+                                .iter()
+                                .any(|&other| applied(other) && suggestions[other].1.overlaps(suggestions[index].1))
+                    })
+            })
+            .collect();
+        self.diagnostics = std::mem::take(&mut self.diagnostics)
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| !fixed.contains(idx))
+            .map(|(_, diagnostic)| diagnostic)
+            .collect();
+
+        let mut output = if report.applied.is_empty() {
+            "No suggestions applied".to_string()
+        } else {
+            format!(
+                "Applied {} suggestion(s) to {}. 'undo' reverts them.",
+                report.applied.len(),
+                report.files.join(", ")
+            )
+        };
+        for skipped in &report.skipped {
+            output.push_str(&format!("\n  skipped {}", skipped));
+        }
+        Ok(output)
+    }
+
+    /// Builds a prompt from diagnostics `selection` (1-based), or from all
+    /// errors, and keeps it for `take_fix_prompt`
+    fn fix_prompt(
+        &mut self,
+        project: &Option<Project>,
+        selection: Option<Vec<usize>>,
+        options: PromptOptions,
+    ) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let chosen: Vec<&Diagnostic> = match selection {
+            Some(numbers) => numbers
+                .iter()
+                .map(|&number| {
+                    number
+                        .checked_sub(1)
+                        .and_then(|idx| self.diagnostics.get(idx))
+                        .ok_or_else(|| anyhow!("There is no diagnostic {}", number))
+                })
+                .collect::<Result<_>>()?,
+            None => self.diagnostics.iter().filter(|d| d.level == Level::Error).collect(),
+        };
+        if chosen.is_empty() {
+            return Err(anyhow!("No errors, run 'check' or 'build' first"));
+        }
+        let prompt = project.fix_prompt(&chosen, options)?;
+        let mut output = format!(
+            "Copied a fix prompt for {} diagnostic(s) to the clipboard, about {} tokens",
+            prompt.diagnostics, prompt.tokens
+        );
+        if prompt.omitted > 0 {
+            output.push_str(&format!(
+                "\n  {} section(s) left out to fit the budget of {} tokens, raise it with --budget",
+                prompt.omitted, options.budget
+            ));
+        }
+        self.fix_prompt = Some(prompt.text);
+        Ok(output)
+    }
+
+    fn build(&mut self, project: &Option<Project>, cargo: CargoArgs, args: Vec<String>) -> Result<String> {
+        let mut cargo_args = vec!["build".to_string()];
+        cargo_args.extend(cargo_options(project, &cargo, false));
+        cargo_args.extend(args);
+        let output = self.spawn_cargo(project, cargo_args, true)?;
+        self.track_build(project);
+        Ok(output)
+    }
+
+    /// `fmt --check` prints rustfmt's diff, `doc` lists broken intra-doc
+    /// links as diagnostics, `update` never touches the network
+    fn cargo(&mut self, project: &Option<Project>, tool: CargoTool, cargo: CargoArgs, args: Vec<String>) -> Result<String> {
+        let mut cargo_args = vec![tool.subcommand().to_string()];
+        if tool.reports_diagnostics() {
+            cargo_args.extend(cargo_options(project, &cargo, false));
+        }
+        if tool == CargoTool::Update && !args.iter().any(|arg| arg == "--offline") {
+            cargo_args.push("--offline".to_string());
+        }
+        cargo_args.extend(args);
+        let output = self.spawn_cargo(project, cargo_args, tool.reports_diagnostics())?;
+        if tool == CargoTool::Check {
+            self.track_build(project);
+        }
+        Ok(output)
+    }
+
+    fn run(&mut self, project: &Option<Project>, cargo: CargoArgs, args: Vec<String>) -> Result<String> {
+        let mut cargo_args = vec!["run".to_string()];
+        cargo_args.extend(cargo_options(project, &cargo, true));
         if !args.is_empty() {
-            cmd.arg("--").args(&args);
+            cargo_args.push("--".to_string());
+            cargo_args.extend(args);
         }
+        self.spawn_cargo(project, cargo_args, false)
+    }
+
+    /// Runs the tests matching any of `filters`, all tests without any.
+    /// On nightly libtest reports as JSON, with timings.
+    fn test(
+        &mut self,
+        project: &Option<Project>,
+        cargo: CargoArgs,
+        filters: Vec<String>,
+        exact: bool,
+        extra_libtest_args: Vec<String>,
+    ) -> Result<String> {
+        let root = &project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?.root_path;
+        let libtest_json = *self.libtest_json.get_or_insert_with(|| {
+            std::process::Command::new("rustc")
+                .arg("-V")
+                .current_dir(root)
+                .output()
+                .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("-nightly"))
+        });
 
-        let output = cmd.output()?;
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut cargo_args = vec!["test".to_string()];
+        cargo_args.extend(cargo_options(project, &cargo, false));
+        // Cargo takes a single TESTNAME, libtest takes any number of filters
+        let mut libtest_args = filters;
+        if exact {
+            libtest_args.push("--exact".to_string());
+        }
+        if libtest_json {
+            libtest_args.extend(["-Z", "unstable-options", "--format", "json", "--report-time"].map(String::from));
+        }
+        libtest_args.extend(extra_libtest_args);
+        if !libtest_args.is_empty() {
+            cargo_args.push("--".to_string());
+            cargo_args.extend(libtest_args);
+        }
 
-        Ok(format!("Run output:\n\n{}{}", stdout, stderr))
+        let output = self.spawn_cargo(project, cargo_args, true)?;
+        self.test_run = TestRun::default();
+        self.test_processes.extend(self.started_process);
+        self.last_test_args = cargo;
+        self.track_build(project);
+        Ok(output)
     }
 
-    fn test(&self, project: &Option<Project>, test_name: Option<String>) -> Result<String> {
+    fn test_report(&self, project: &Option<Project>, format: ReportFormat, path: Option<String>) -> Result<String> {
         let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
-        let mut cmd = ProcessCommand::new("cargo");
-        cmd.arg("test").current_dir(&project.root_path);
+        if self.test_run.cases.is_empty() {
+            return Err(anyhow!("No test results, run 'test' first"));
+        }
+        let path = match path {
+            Some(path) => project.root_path.join(path),
+            None => project.root_path.join(config::CONFIG_DIR).join(format.default_file()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, self.test_run.report(format))
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        Ok(format!(
+            "Wrote {} test result(s) to {}",
+            self.test_run.cases.len(),
+            path.display()
+        ))
+    }
+
+    fn profile(&mut self, project: &Option<Project>, mut cargo: CargoArgs) -> Result<String> {
+        cargo.profile.get_or_insert_with(|| "release".to_string());
+        let mut cargo_args = vec!["build".to_string()];
+        cargo_args.extend(cargo_options(project, &cargo, false));
+        let output = self.spawn_cargo(project, cargo_args, true)?;
+        self.track_build(project);
+        Ok(output)
+    }
 
-        if let Some(name) = test_name {
-            cmd.arg(&name);
+    /// Logs the process just started in the build history once it finishes
+    fn track_build(&mut self, project: &Option<Project>) {
+        if let (Some(id), Some(project)) = (self.started_process, project) {
+            self.tracked_builds.insert(
+                id,
+                TrackedBuild {
+                    root_path: project.root_path.clone(),
+                    started: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                    git_head: build_history::git_head(&project.root_path),
+                },
+            );
         }
+    }
 
-        let output = cmd.output()?;
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    fn builds(&self, project: &Option<Project>, count: usize) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let records = &project.builds.records;
+        if records.is_empty() {
+            return Ok("No builds recorded yet; build, check, test and profile are logged in .vibe/builds.jsonl".to_string());
+        }
+        let first = records.len().saturating_sub(count);
+        let mut output = format!("Last {} of {} recorded run(s):\n\n", records.len() - first, records.len());
+        for (index, record) in records.iter().enumerate().skip(first) {
+            let started = chrono::DateTime::parse_from_rfc3339(&record.started)
+                .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|_| record.started.clone());
+            output.push_str(&format!(
+                "{} {}  {:>7.1}s  {} warning(s), {} error(s)  {}  {}",
+                if record.success { "✅" } else { "❌" },
+                started,
+                record.duration_secs,
+                record.warnings,
+                record.errors,
+                record.git_head.as_deref().unwrap_or("-------"),
+                record.command
+            ));
+            if let Some(ratio) = project.builds.slowdown(index) {
+                output.push_str(&format!("  ⚠ {:.1}× median", ratio));
+            }
+            output.push('\n');
+        }
+        Ok(output)
+    }
 
-        Ok(format!("Test output:\n\n{}{}", stdout, stderr))
+    /// Starts cargo in the background; its output streams into the chat
+    /// message that echoes the command line. With `json`, compiler messages
+    /// are requested as JSON and the run's diagnostics replace the previous ones.
+    fn spawn_cargo(&mut self, project: &Option<Project>, args: Vec<String>, json: bool) -> Result<String> {
+        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+        let mut cargo_args = args.clone();
+        if json {
+            cargo_args.insert(1, diagnostics::MESSAGE_FORMAT.to_string());
+        }
+        let id = self.runner.spawn("cargo", &cargo_args, &project.root_path)?;
+        if json {
+            self.diagnostics.clear();
+            self.diagnostics_process = Some(id);
+            self.json_processes.insert(id, Vec::new());
+        }
+        self.started_process = Some(id);
+        Ok(format!("$ cargo {}\n", args.join(" ")))
     }
 
-    fn profile(&self, project: &Option<Project>) -> Result<String> {
+    fn show_workspace(&self, project: &Option<Project>) -> Result<String> {
         let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
-        let output = ProcessCommand::new("cargo")
-            .arg("build")
-            .arg("--release")
-            .current_dir(&project.root_path)
-            .output()?;
+        let workspace = project
+            .workspace
+            .as_ref()
+            .ok_or_else(|| anyhow!("No cargo workspace loaded (is there a Cargo.toml?)"))?;
+        let scope = project.scope();
+        let mut output = format!("Workspace with {} package(s)", workspace.packages.len());
+        match (&scope.package, &scope.target) {
+            (Some(package), Some(target)) => output.push_str(&format!(", scoped to {} ({})", package, target.label())),
+            (Some(package), None) => output.push_str(&format!(", scoped to {}", package)),
+            _ => {}
+        }
+        output.push_str(":\n");
+        for package in &workspace.packages {
+            let dir = if package.dir.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                package.dir.display().to_string()
+            };
+            output.push_str(&format!(
+                "\n{} {} ({}, edition {})\n",
+                package.name, package.version, dir, package.edition
+            ));
+            let targets: Vec<String> = package.targets.iter().map(|target| target.label()).collect();
+            output.push_str(&format!("  targets: {}\n", targets.join(", ")));
+            if !package.features.is_empty() {
+                output.push_str(&format!("  features: {}\n", package.features.join(", ")));
+            }
+        }
+        Ok(output)
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    fn ps(&self) -> String {
+        let processes = self.runner.processes();
+        let running: Vec<_> = processes.iter().filter(|process| process.is_running()).collect();
+        let finished = processes.len() - running.len();
+        if running.is_empty() {
+            return format!("No running processes ({} finished)", finished);
+        }
 
-        Ok(format!("Profile build output:\n\n{}{}", stdout, stderr))
+        let mut output = format!("{:>4}  {:>7}  {:>7}  COMMAND\n", "ID", "PID", "TIME");
+        for process in running {
+            let pid = process.pid.map(|pid| pid.to_string()).unwrap_or_else(|| "-".to_string());
+            output.push_str(&format!(
+                "{:>4}  {:>7}  {:>6.1}s  {}\n",
+                process.id,
+                pid,
+                process.elapsed().as_secs_f32(),
+                process.command_line
+            ));
+        }
+        if finished > 0 {
+            output.push_str(&format!("\n{} finished process(es)", finished));
+        }
+        output
+    }
+
+    fn kill(&mut self, id: usize, force: bool) -> Result<String> {
+        if force {
+            self.runner.kill(id)?;
+            Ok(format!("Killed process {}", id))
+        } else {
+            self.runner.stop(id)?;
+            Ok(format!("Asked process {} to stop, 'kill -9 {}' if it keeps running", id, id))
+        }
     }
 
     fn list_files(&self, project: &Option<Project>) -> Result<String> {
@@ -220,21 +1580,21 @@ impl CommandExecutor {
         Ok(output)
     }
 
-    fn show_file(&self, project: &Option<Project>, file: &str) -> Result<String> {
-        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+    fn show_file(&self, project: &mut Option<Project>, file: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
         let content = project.read_file(file)?;
         Ok(format!("Content of {}:\n\n{}", file, content))
     }
 
-    fn show_function(&self, project: &Option<Project>, file: &str, function: &str) -> Result<String> {
-        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+    fn show_function(&self, project: &mut Option<Project>, file: &str, function: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
         let content = project.read_file(file)?;
         let func_code = self.parser.extract_function(&content, function)?;
         Ok(format!("Function '{}' in {}:\n\n{}", function, file, func_code))
     }
 
-    fn list_functions(&self, project: &Option<Project>, file: &str) -> Result<String> {
-        let project = project.as_ref().ok_or_else(|| anyhow!("No project loaded"))?;
+    fn list_functions(&self, project: &mut Option<Project>, file: &str) -> Result<String> {
+        let project = project.as_mut().ok_or_else(|| anyhow!("No project loaded"))?;
         let content = project.read_file(file)?;
         let functions = self.parser.list_functions(&content)?;
         
@@ -250,10 +1610,86 @@ impl CommandExecutor {
 
 search <query>              - Search for files, functions, or variables
 add into <file>             - Add code into a file (multiline)
-build                       - Build the project with cargo build
-run [args]                  - Run the project with cargo run
-test [name]                 - Run tests with cargo test
-profile                     - Build with --release for profiling
+add into <file>::<target>   - Add into `impl Type`, `impl Trait for Type`,
+                              `mod name` or `Type` (fields, variants, derives)
+replace <file>::<item>      - Replace one function, method, type, impl or const
+delete <file>::<item>       - Delete one function, method, type, impl or const
+move <file>::<item> to <file>
+                            - Move a type or function with its impls to another
+                              module and fix the paths that reach it
+rename <file>[::<item>] <new_name>
+                            - Rename a module, function, type, method, field
+                              or variant across the project (with preview)
+rename apply | cancel       - Apply or discard the previewed rename
+impl <Trait> for <Type>     - Stub out a project or std trait impl with todo!()
+gen new <Type>              - Add a `new` constructor taking every field
+gen builder <Type>          - Add <Type>Builder with chainable setters and build()
+gen getters <Type>          - Add a getter and setter per field
+gen tests <file>            - Add stub tests for untested public functions
+<pasted reply>              - List the ```rust blocks of an AI reply and the
+                              files they name (// src/a.rs or **src/a.rs**)
+apply <n> [<n> ...] | all   - Add the chosen blocks of the last reply
+new module <path>           - Create src/<path>.rs and declare it (systems/ai)
+new bin | example | test <name>
+                            - Create src/bin, examples or tests/<name>.rs,
+                              registering [[bin]] and [[example]] in Cargo.toml
+new crate <path> [--bin]    - Create a workspace member crate and add it to
+                              [workspace] members
+dep add <crate>[@<version>] [--features a,b] [--dev|--build] [--optional]
+                            - Add a dependency to Cargo.toml (newest cached
+                              version by default)
+dep remove <crate>          - Remove a dependency
+dep list                    - List dependencies, flagging ones not cached offline
+feature add <name> [<dep:crate> <crate>/<feature> ...]
+                            - Add or extend a [features] entry
+merge show | apply | cancel - Review, write or drop the three-way merge made
+                              when a file was edited elsewhere since 'show'
+
+add, replace and apply refuse to write code that does not parse;
+add --force to the first line to write it anyway.
+build [options]             - Build the project with cargo build
+check [options]             - Type-check the project with cargo check
+clippy [args]               - Lint with cargo clippy (clippy -- -D warnings)
+fmt [--check]               - Format with cargo fmt; --check shows the diff
+doc [args]                  - Build docs, listing broken intra-doc links
+bench [name]                - Run benchmarks with cargo bench
+clean                       - Remove the target directory
+tree [args]                 - Show the dependency tree (tree -d, tree -i serde)
+update [crate]              - Update Cargo.lock from the offline cache
+run [options] [-- args]     - Run the project with cargo run
+test [options] [name] [--exact] [-- libtest args]
+                            - Run tests with cargo test
+test --failed               - Rerun the tests that failed last time
+test report json | junit [path]
+                            - Write the latest test results to a file
+                              (default .vibe/test-report.json or junit.xml)
+profile [--profile <name>]  - Build with the release (or given) profile
+workspace                   - List the workspace packages, targets and features
+scope <package> [<target>]  - Limit build, run, test, search and listing to a
+                              package and target (bin:server, test:combat, lib)
+scope all                   - Cover the whole workspace again
+builds [count]              - Show recent build, check, test and profile runs
+                              with timings, flagging ones much slower than usual
+ps                          - List running build, run and test processes
+kill [-9] <id>              - Stop a process by its ps id (-9 kills it outright)
+fix [<n> ...] [--maybe-incorrect]
+                            - Apply the machine-applicable compiler suggestions
+                              of all diagnostics, or of diagnostics n...
+fix prompt [<n> ...] [--context <lines>] [--budget <tokens>]
+                            - Copy a prompt asking for a fix of all errors, or
+                              of diagnostics n..., with the code around them
+                              and the types they mention (default 3 lines,
+                              4000 tokens)
+undo                        - Revert the last fix
+
+options: --features a,b  --all-features  --no-default-features  -p <package>
+         --bin <name>  --example <name>  --profile <name> | --release
+         --target-dir <dir>  -j <jobs>
+Defaults for them are kept per project in the Settings panel;
+--all-features=false or --no-default-features=false turns a default off.
+build, check, clippy, doc, bench and test list errors and warnings in the
+Diagnostics panel;
+test results are listed under Test Results.
 list files                  - List all Rust files in the project
 list functions <file>       - List all functions in a file
 show <file>                 - Show file contents
@@ -263,6 +1699,18 @@ help                        - Show this help message
 Examples:
   search npc.rs
   add into src/npc.rs
+  add into src/npc.rs::impl Npc
+  add into src/npc.rs::mod tests
+  replace src/npc.rs::Npc::new
+  delete src/npc.rs::impl Display for Npc
+  move src/main.rs::Npc to src/npc.rs
+  rename src/npc.rs::spawn spawn_npc
+  rename src/npc.rs::Npc::hp health
+  rename src/npc.rs enemy
+  impl Display for Npc
+  impl From<u32> for Npc
+  gen builder Npc
+  gen tests src/npc.rs
   build
   run --verbose
   test test_npc
@@ -272,3 +1720,275 @@ Examples:
 "#.to_string()
     }
 }
+
+/// Cargo options for `cargo` completed with the workspace scope and then
+/// the project's defaults from the settings panel. The default `--bin` and
+/// `--example` only apply to `run`, so they do not narrow builds and tests
+/// to one target.
+fn cargo_options(project: &Option<Project>, cargo: &CargoArgs, run: bool) -> Vec<String> {
+    let Some(project) = project else {
+        return cargo.to_args();
+    };
+    let mut defaults = project.config.cargo.clone();
+    if !run {
+        defaults.bin = None;
+        defaults.example = None;
+    }
+    let scope = project.scope();
+    let mut scoped = cargo.clone();
+    if scoped.package.is_none() {
+        scoped.package = scope.package.clone();
+    }
+    // An explicit target replaces the scoped one
+    let explicit_target = cargo.bin.is_some() || cargo.example.is_some();
+    let mut options = scoped.or_defaults(&defaults).to_args();
+    if let (Some(target), false) = (&scope.target, explicit_target) {
+        options.extend(target.cargo_args(run));
+    }
+    options
+}
+
+/// `fix prompt [n...] [--context <lines>] [--budget <tokens>]`, the words
+/// after `prompt`
+fn parse_fix_prompt(words: &[&str]) -> Result<Command> {
+    let mut options = PromptOptions::default();
+    let mut selection = Vec::new();
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let mut number = |name: &str| {
+            let value = words.next().ok_or_else(|| anyhow!("{} needs a value", name))?;
+            value.parse::<usize>().map_err(|_| anyhow!("Invalid {} value: {}", name, value))
+        };
+        match *word {
+            "--context" => options.context = number(word)?,
+            "--budget" => options.budget = number(word)?,
+            n => selection.push(n.parse::<usize>().map_err(|_| anyhow!("Invalid diagnostic number: {}", n))?),
+        }
+    }
+    Ok(Command::FixPrompt {
+        selection: (!selection.is_empty()).then_some(selection),
+        options,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dep_add_keeps_existing_requirement() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"game\"\n\n[dependencies]\nrand = \"0.7\"\nengine = { path = \"../engine\" }\n",
+        )
+        .unwrap();
+        let mut project = Some(Project::load(temp_dir.path().to_path_buf()).unwrap());
+        let mut executor = CommandExecutor::new();
+
+        // Neither crate needs to be in the registry cache, both are declared
+        for input in ["dep add rand --features small_rng", "dep add engine --features debug"] {
+            let output = executor.execute(Command::parse(input).unwrap(), &mut project).unwrap();
+            assert!(output.starts_with("Updated "), "{}", output);
+        }
+        let command = Command::parse("dep add no-such-crate-in-any-cache").unwrap();
+        assert!(executor.execute(command, &mut project).is_err());
+
+        let content = fs::read_to_string(temp_dir.path().join("Cargo.toml")).unwrap();
+        assert_eq!(
+            content,
+            "[package]\nname = \"game\"\n\n[dependencies]\nrand = { version = \"0.7\", features = [\"small_rng\"] }\nengine = { path = \"../engine\", features = [\"debug\"] }\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_keeps_diagnostics_it_could_not_fix() {
+        use crate::diagnostics::{Applicability, Span, SuggestionPart};
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let original = "fn main() {\n    let mut x = 1;\n    let mut y = 2;\n    println!(\"{} {}\", x, y);\n}\n";
+        fs::write(temp_dir.path().join("src/main.rs"), original).unwrap();
+        let unused_mut = |byte_start, line| {
+            let span = Span {
+                file: "src/main.rs".to_string(),
+                byte_start,
+                byte_end: byte_start + 4,
+                line_start: line,
+                line_end: line,
+                column_start: 9,
+                column_end: 13,
+                is_primary: true,
+                label: None,
+            };
+            Diagnostic {
+                level: Level::Warning,
+                code: Some("unused_mut".to_string()),
+                message: "variable does not need to be mutable".to_string(),
+                spans: vec![span.clone()],
+                children: Vec::new(),
+                suggestions: vec![Suggestion {
+                    message: "remove this `mut`".to_string(),
+                    parts: vec![SuggestionPart {
+                        span,
+                        replacement: String::new(),
+                    }],
+                    applicability: Applicability::MachineApplicable,
+                }],
+                rendered: None,
+            }
+        };
+        let mut project = Some(Project::load(temp_dir.path().to_path_buf()).unwrap());
+        let mut executor = CommandExecutor::new();
+        // The second one's span is off by a byte, so it is skipped as stale
+        executor.diagnostics = vec![unused_mut(20, 2), unused_mut(40, 3)];
+
+        executor.execute(Command::parse("fix").unwrap(), &mut project).unwrap();
+        assert_eq!(executor.diagnostics().len(), 1);
+        assert_eq!(executor.diagnostics()[0].spans[0].byte_start, 40);
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("src/main.rs")).unwrap(),
+            original.replacen("let mut x", "let x", 1)
+        );
+    }
+
+    #[test]
+    fn test_parse_cargo_tools() {
+        match Command::parse("clippy -p engine --all-features -- -D warnings").unwrap() {
+            Command::Cargo { tool, cargo, args } => {
+                assert_eq!(tool, CargoTool::Clippy);
+                assert_eq!(cargo.package.as_deref(), Some("engine"));
+                assert_eq!(cargo.all_features, Some(true));
+                assert_eq!(args, ["--", "-D", "warnings"]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        // Tools that do not compile pass every option on to cargo
+        match Command::parse("tree -p engine --depth 1").unwrap() {
+            Command::Cargo { tool, cargo, args } => {
+                assert_eq!(tool, CargoTool::Tree);
+                assert_eq!(cargo, CargoArgs::default());
+                assert_eq!(args, ["-p", "engine", "--depth", "1"]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        for (input, expected) in [
+            ("fmt", CargoTool::Fmt),
+            ("doc --no-deps", CargoTool::Doc),
+            ("bench", CargoTool::Bench),
+            ("clean --release", CargoTool::Clean),
+            ("check", CargoTool::Check),
+            ("update", CargoTool::Update),
+        ] {
+            assert!(
+                matches!(Command::parse(input).unwrap(), Command::Cargo { tool, .. } if tool == expected),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(Command::parse("lint").unwrap_err().to_string(), "Unknown command: lint");
+    }
+
+    #[test]
+    fn test_parse_cargo_options() {
+        match Command::parse("build --features a,b -p game --release -j4").unwrap() {
+            Command::Build { cargo, args } => {
+                assert_eq!(cargo.features, ["a", "b"]);
+                assert_eq!(cargo.package.as_deref(), Some("game"));
+                assert_eq!(cargo.profile.as_deref(), Some("release"));
+                assert_eq!(cargo.jobs, Some(4));
+                assert!(args.is_empty());
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        match Command::parse("test --no-default-features physics --exact -- --nocapture").unwrap() {
+            Command::Test {
+                cargo,
+                test_name,
+                exact,
+                libtest_args,
+            } => {
+                assert_eq!(cargo.no_default_features, Some(true));
+                assert_eq!(test_name.as_deref(), Some("physics"));
+                assert!(exact);
+                assert_eq!(libtest_args, ["--nocapture"]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        match Command::parse("run --bin editor -- --level 2").unwrap() {
+            Command::Run { cargo, args } => {
+                assert_eq!(cargo.bin.as_deref(), Some("editor"));
+                assert_eq!(args, ["--level", "2"]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        let error = |input: &str| Command::parse(input).unwrap_err().to_string();
+        assert_eq!(
+            error("build --all-features --no-default-features"),
+            "--all-features and --no-default-features cannot be used together"
+        );
+        assert_eq!(error("check -j many"), "Invalid job count: many");
+        assert_eq!(error("test -p"), "-p needs a value");
+        assert_eq!(
+            error("build --all-features=maybe"),
+            "--all-features takes true or false, not maybe"
+        );
+        assert_eq!(error("run \"unclosed"), "Unclosed quote in: \"unclosed");
+    }
+
+    #[test]
+    fn test_parse_replace_and_delete() {
+        match Command::parse("replace --force src/npc.rs::Npc::new\nfn new() -> Self { Npc }").unwrap() {
+            Command::Replace { file, item, code, force } => {
+                assert_eq!(file, "src/npc.rs");
+                assert_eq!(item, "Npc::new");
+                assert_eq!(code, "fn new() -> Self { Npc }");
+                assert!(force);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        match Command::parse("delete src/npc.rs::impl Display for Npc").unwrap() {
+            Command::Delete { file, item } => {
+                assert_eq!(file, "src/npc.rs");
+                assert_eq!(item, "impl Display for Npc");
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        let error = |input: &str| Command::parse(input).unwrap_err().to_string();
+        assert_eq!(error("replace"), "Usage: replace <file>::<item>\n<code>");
+        assert_eq!(error("replace src/npc.rs\nfn new() {}"), "Expected 'replace <file>::<item>'");
+        assert_eq!(error("delete"), "Usage: delete <file>::<item>");
+        assert_eq!(error("delete src/npc.rs"), "Expected 'delete <file>::<item>'");
+    }
+
+    #[test]
+    fn test_parse_force_only_for_writes() {
+        assert!(matches!(
+            Command::parse("add --force into src/npc.rs\nfn spawn() {}").unwrap(),
+            Command::AddInto { force: true, .. }
+        ));
+        assert!(matches!(
+            Command::parse("apply 1,3 --force").unwrap(),
+            Command::ApplyBlocks { selection: Some(selection), force: true } if selection == [1, 3]
+        ));
+        assert!(matches!(
+            Command::parse("apply all").unwrap(),
+            Command::ApplyBlocks { selection: None, force: false }
+        ));
+        // Other commands keep the word
+        match Command::parse("search --force").unwrap() {
+            Command::Search { query } => assert_eq!(query, "--force"),
+            other => panic!("unexpected command: {:?}", other),
+        }
+        assert_eq!(
+            Command::parse("apply 1 x").unwrap_err().to_string(),
+            "Invalid block number: x"
+        );
+    }
+}
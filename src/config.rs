@@ -0,0 +1,87 @@
+use crate::cargo_args::CargoArgs;
+use crate::patch::FormatMode;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_DIR: &str = ".vibe";
+const CONFIG_FILE: &str = "config.json";
+
+/// Visibility written on generated `mod` declarations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleVisibility {
+    #[default]
+    Private,
+    Pub,
+    PubCrate,
+}
+
+impl ModuleVisibility {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ModuleVisibility::Private => "",
+            ModuleVisibility::Pub => "pub ",
+            ModuleVisibility::PubCrate => "pub(crate) ",
+        }
+    }
+
+    /// The visibility for a declaration inside another module: a private
+    /// `mod ai;` in `systems` would be out of reach for the rest of the crate
+    pub fn nested(self) -> Self {
+        match self {
+            ModuleVisibility::Private => ModuleVisibility::PubCrate,
+            visibility => visibility,
+        }
+    }
+}
+
+/// Per-project settings stored in `.vibe/config.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    /// How inserted code is pretty-printed
+    pub format: FormatMode,
+    /// Visibility of `mod` declarations added for new files
+    pub module_visibility: ModuleVisibility,
+    /// Keep `mod` declarations in alphabetical order instead of appending
+    pub sort_modules: bool,
+    /// Options added to every build, run, test and check
+    pub cargo: CargoArgs,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            format: FormatMode::default(),
+            module_visibility: ModuleVisibility::default(),
+            sort_modules: true,
+            cargo: CargoArgs::default(),
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn load(root_path: &Path) -> Result<Self> {
+        let path = Self::path(root_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, root_path: &Path) -> Result<()> {
+        let path = Self::path(root_path);
+        fs::create_dir_all(root_path.join(CONFIG_DIR))?;
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    fn path(root_path: &Path) -> PathBuf {
+        root_path.join(CONFIG_DIR).join(CONFIG_FILE)
+    }
+}
@@ -0,0 +1,423 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// `--message-format` that keeps rustc's rendered text next to the JSON
+pub const MESSAGE_FORMAT: &str = "--message-format=json-diagnostic-rendered-ansi";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+    /// `failure-note`, `error: internal compiler error`, ...
+    Other(String),
+}
+
+impl Level {
+    fn parse(level: &str) -> Self {
+        match level {
+            "error" => Level::Error,
+            "warning" => Level::Warning,
+            "note" => Level::Note,
+            "help" => Level::Help,
+            other => Level::Other(other.to_string()),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+            Level::Other(level) => level,
+        }
+    }
+}
+
+/// How safe rustc considers a suggestion to apply without review, from
+/// the safest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Applicability {
+    MachineApplicable,
+    MaybeIncorrect,
+    HasPlaceholders,
+    Unspecified,
+}
+
+impl Applicability {
+    fn parse(applicability: Option<&str>) -> Self {
+        match applicability {
+            Some("MachineApplicable") => Applicability::MachineApplicable,
+            Some("MaybeIncorrect") => Applicability::MaybeIncorrect,
+            Some("HasPlaceholders") => Applicability::HasPlaceholders,
+            _ => Applicability::Unspecified,
+        }
+    }
+}
+
+/// A source range a diagnostic points at. Lines and columns are 1-based,
+/// the file is relative to the workspace root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    pub is_primary: bool,
+    pub label: Option<String>,
+}
+
+/// One edit of a suggestion
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuggestionPart {
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// A change rustc or clippy proposes, made of one or more edits that only
+/// make sense together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// The help text, e.g. "remove this `mut`"
+    pub message: String,
+    pub parts: Vec<SuggestionPart>,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    /// Whether any edit of `self` touches a range one of `other` does
+    pub fn overlaps(&self, other: &Suggestion) -> bool {
+        self.parts.iter().any(|part| {
+            other.parts.iter().any(|theirs| {
+                let (a, b) = (&part.span, &theirs.span);
+                a.file == b.file
+                    && (a.byte_start == b.byte_start
+                        || (a.byte_start < b.byte_end && b.byte_start < a.byte_end))
+            })
+        })
+    }
+
+    /// Whether `fix` may apply it: always for machine-applicable ones, and
+    /// for maybe-incorrect ones when the user opted in
+    pub fn is_applicable(&self, maybe_incorrect: bool) -> bool {
+        match self.applicability {
+            Applicability::MachineApplicable => true,
+            Applicability::MaybeIncorrect => maybe_incorrect,
+            Applicability::HasPlaceholders | Applicability::Unspecified => false,
+        }
+    }
+}
+
+/// One compiler or clippy message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    /// `E0308`, `unused_mut`, `clippy::needless_return`, ...
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<Span>,
+    /// Notes and help lines attached to the message
+    pub children: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+    /// rustc's own text rendering, ANSI colours removed
+    pub rendered: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.spans
+            .iter()
+            .find(|span| span.is_primary)
+            .or(self.spans.first())
+    }
+
+    /// `error[E0308]: mismatched types`
+    pub fn title(&self) -> String {
+        match &self.code {
+            Some(code) => format!("{}[{}]: {}", self.level.label(), code, self.message),
+            None => format!("{}: {}", self.level.label(), self.message),
+        }
+    }
+}
+
+/// A line of cargo's JSON output
+pub enum CargoMessage {
+    Diagnostic(Diagnostic),
+    /// Artifacts, build script runs, `build-finished` and the like
+    Other,
+}
+
+#[derive(Deserialize)]
+struct RawRecord {
+    reason: String,
+    message: Option<RawMessage>,
+}
+
+#[derive(Deserialize)]
+struct RawMessage {
+    message: String,
+    code: Option<RawCode>,
+    level: String,
+    #[serde(default)]
+    spans: Vec<RawSpan>,
+    #[serde(default)]
+    children: Vec<RawMessage>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RawSpan {
+    file_name: String,
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    column_end: usize,
+    is_primary: bool,
+    label: Option<String>,
+    suggested_replacement: Option<String>,
+    suggestion_applicability: Option<String>,
+}
+
+impl RawSpan {
+    fn to_span(&self) -> Span {
+        Span {
+            file: self.file_name.clone(),
+            byte_start: self.byte_start,
+            byte_end: self.byte_end,
+            line_start: self.line_start,
+            line_end: self.line_end,
+            column_start: self.column_start,
+            column_end: self.column_end,
+            is_primary: self.is_primary,
+            label: self.label.clone(),
+        }
+    }
+}
+
+/// Parses one line of cargo output; `None` for plain text lines such as
+/// test output or the `Compiling ...` progress on stderr
+pub fn parse_line(line: &str) -> Option<CargoMessage> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let record: RawRecord = serde_json::from_str(line).ok()?;
+    Some(match (record.reason.as_str(), record.message) {
+        ("compiler-message", Some(message)) => CargoMessage::Diagnostic(diagnostic(message)),
+        _ => CargoMessage::Other,
+    })
+}
+
+fn diagnostic(raw: RawMessage) -> Diagnostic {
+    let mut suggestions = Vec::new();
+    collect_suggestions(&raw, &mut suggestions);
+    Diagnostic {
+        level: Level::parse(&raw.level),
+        code: raw.code.map(|code| code.code),
+        spans: raw.spans.iter().map(RawSpan::to_span).collect(),
+        children: raw
+            .children
+            .iter()
+            .map(|child| format!("{}: {}", child.level, child.message))
+            .collect(),
+        suggestions,
+        rendered: raw.rendered.as_deref().map(strip_ansi),
+        message: raw.message,
+    }
+}
+
+/// The spans of one message with a replacement form one suggestion. Spans
+/// that overlap each other are alternatives, each its own suggestion.
+fn collect_suggestions(raw: &RawMessage, suggestions: &mut Vec<Suggestion>) {
+    let single = |span: &RawSpan, replacement: &String| Suggestion {
+        message: raw.message.clone(),
+        parts: vec![SuggestionPart {
+            span: span.to_span(),
+            replacement: replacement.clone(),
+        }],
+        applicability: Applicability::parse(span.suggestion_applicability.as_deref()),
+    };
+    let edits: Vec<Suggestion> = raw
+        .spans
+        .iter()
+        .filter_map(|span| Some(single(span, span.suggested_replacement.as_ref()?)))
+        .collect();
+    let alternatives = edits
+        .iter()
+        .enumerate()
+        .any(|(i, edit)| edits[i + 1..].iter().any(|other| edit.overlaps(other)));
+    if alternatives || edits.len() < 2 {
+        suggestions.extend(edits);
+    } else {
+        // The least certain part decides for the whole suggestion
+        let applicability = edits
+            .iter()
+            .map(|edit| edit.applicability)
+            .max()
+            .unwrap_or(Applicability::Unspecified);
+        suggestions.push(Suggestion {
+            message: raw.message.clone(),
+            parts: edits.into_iter().flat_map(|edit| edit.parts).collect(),
+            applicability,
+        });
+    }
+    for child in &raw.children {
+        collect_suggestions(child, suggestions);
+    }
+}
+
+/// Removes ANSI colour sequences (`ESC [ ... m`) from rendered output
+pub fn strip_ansi(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// The lines of `span` with `context` lines around them, numbered, with
+/// the spanned columns underlined when the span is on one line
+pub fn source_excerpt(root: &Path, span: &Span, context: usize) -> Result<String> {
+    let content = fs::read_to_string(root.join(&span.file))
+        .map_err(|e| anyhow!("Failed to read {}: {}", span.file, e))?;
+    let lines: Vec<&str> = content.lines().collect();
+    let first = span.line_start.saturating_sub(context + 1);
+    let last = (span.line_end + context).min(lines.len());
+    let width = last.to_string().len();
+
+    let mut excerpt = String::new();
+    for (index, line) in lines.iter().enumerate().take(last).skip(first) {
+        let number = index + 1;
+        let marker = if (span.line_start..=span.line_end).contains(&number) {
+            '>'
+        } else {
+            ' '
+        };
+        excerpt.push_str(&format!(
+            "{}{:>width$} | {}\n",
+            marker,
+            number,
+            line,
+            width = width
+        ));
+        if number == span.line_start && span.line_start == span.line_end {
+            let underline = "^".repeat(span.column_end.saturating_sub(span.column_start).max(1));
+            excerpt.push_str(&format!(
+                " {:>width$} | {}{}\n",
+                "",
+                " ".repeat(span.column_start.saturating_sub(1)),
+                underline,
+                width = width
+            ));
+        }
+    }
+    Ok(excerpt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNUSED_MUT: &str = r#"{"reason":"compiler-message","package_id":"game 0.1.0","manifest_path":"/p/Cargo.toml","target":{"name":"game"},"message":{"rendered":"\u001b[33mwarning\u001b[0m: variable does not need to be mutable\n","$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(unused_mut)]` on by default","rendered":null,"spans":[]},{"children":[],"code":null,"level":"help","message":"remove this `mut`","rendered":null,"spans":[{"byte_end":20,"byte_start":16,"column_end":13,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"","suggestion_applicability":"MachineApplicable","text":[]}]}],"code":{"code":"unused_mut","explanation":null},"level":"warning","message":"variable does not need to be mutable","spans":[{"byte_end":21,"byte_start":16,"column_end":14,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}"#;
+
+    #[test]
+    fn test_parse_compiler_message() {
+        let Some(CargoMessage::Diagnostic(diagnostic)) = parse_line(UNUSED_MUT) else {
+            panic!("not a diagnostic");
+        };
+        assert_eq!(diagnostic.level, Level::Warning);
+        assert_eq!(
+            diagnostic.title(),
+            "warning[unused_mut]: variable does not need to be mutable"
+        );
+        assert_eq!(diagnostic.primary_span().unwrap().line_start, 2);
+        assert_eq!(diagnostic.suggestions.len(), 1);
+        assert_eq!(
+            diagnostic.suggestions[0].applicability,
+            Applicability::MachineApplicable
+        );
+        assert_eq!(diagnostic.suggestions[0].message, "remove this `mut`");
+        assert_eq!(
+            diagnostic.rendered.as_deref(),
+            Some("warning: variable does not need to be mutable\n")
+        );
+        assert!(parse_line("   Compiling game v0.1.0").is_none());
+    }
+
+    #[test]
+    fn test_multipart_suggestions_stay_together() {
+        let span = |start: usize, replacement: &str| {
+            format!(
+                r#"{{"byte_end":{},"byte_start":{},"column_end":1,"column_start":1,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":1,"line_start":1,"suggested_replacement":"{}","suggestion_applicability":"MachineApplicable"}}"#,
+                start + 1,
+                start,
+                replacement
+            )
+        };
+        let child = |message: &str, spans: &[String]| {
+            format!(
+                r#"{{"children":[],"code":null,"level":"help","message":"{}","rendered":null,"spans":[{}]}}"#,
+                message,
+                spans.join(",")
+            )
+        };
+        let line = format!(
+            r#"{{"reason":"compiler-message","message":{{"children":[{},{}],"code":null,"level":"error","message":"m","rendered":null,"spans":[]}}}}"#,
+            child("wrap it", &[span(0, "Some("), span(5, ")")]),
+            child("use one of", &[span(9, "a"), span(9, "b")])
+        );
+        let Some(CargoMessage::Diagnostic(diagnostic)) = parse_line(&line) else {
+            panic!("not a diagnostic");
+        };
+        let parts: Vec<usize> = diagnostic
+            .suggestions
+            .iter()
+            .map(|suggestion| suggestion.parts.len())
+            .collect();
+        assert_eq!(parts, vec![2, 1, 1]);
+        assert!(diagnostic.suggestions[1].overlaps(&diagnostic.suggestions[2]));
+    }
+
+    #[test]
+    fn test_source_excerpt() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::write(
+            temp_dir.path().join("src/main.rs"),
+            "fn main() {\n    let mut x = 1;\n    println!(\"{}\", x);\n}\n",
+        )
+        .unwrap();
+        let Some(CargoMessage::Diagnostic(diagnostic)) = parse_line(UNUSED_MUT) else {
+            panic!("not a diagnostic");
+        };
+
+        let excerpt =
+            source_excerpt(temp_dir.path(), diagnostic.primary_span().unwrap(), 1).unwrap();
+        assert_eq!(
+            excerpt,
+            " 1 | fn main() {\n>2 |     let mut x = 1;\n   |         ^^^^^\n 3 |     println!(\"{}\", x);\n"
+        );
+    }
+}
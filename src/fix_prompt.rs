@@ -0,0 +1,410 @@
+use crate::diagnostics::{Diagnostic, Level};
+use crate::parser::RustParser;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{ImplItem, Item, TraitItem};
+
+/// Functions longer than this are shown around the error only, plus their
+/// first line
+const MAX_FUNCTION_LINES: usize = 60;
+
+const HEADER: &str = "\
+The Rust project below fails to build. Fix the errors listed under Diagnostics.
+Answer in Vibe Rust Coder commands, one per changed item, code on the lines after it:
+
+replace <file>::<item>
+<the whole corrected function, method, type or impl>
+
+Use `add into <file>` for new items. Change only what the errors require and keep
+existing names and signatures unless an error is about them.
+";
+
+#[derive(Debug, Clone, Copy)]
+pub struct PromptOptions {
+    /// Lines of context around each error, beyond its enclosing function
+    pub context: usize,
+    /// Rough upper bound on the prompt size in tokens
+    pub budget: usize,
+}
+
+impl Default for PromptOptions {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            budget: 4000,
+        }
+    }
+}
+
+pub struct FixPrompt {
+    pub text: String,
+    pub tokens: usize,
+    pub diagnostics: usize,
+    /// Sections left out to stay within the budget
+    pub omitted: usize,
+}
+
+/// Tokens a model will see for `text`, estimated at four characters each
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Bundles `diagnostics` with the code they point at and the definitions of
+/// the project types they mention. `files` are searched for those types;
+/// diagnostic paths are relative to `root`.
+pub fn build(
+    root: &Path,
+    files: &[PathBuf],
+    diagnostics: &[&Diagnostic],
+    options: PromptOptions,
+) -> Result<FixPrompt> {
+    if diagnostics.is_empty() {
+        return Err(anyhow!("No diagnostics to put in a prompt"));
+    }
+    let parser = RustParser::new();
+
+    // Errors first; identical messages (one per target) only once
+    let mut ordered: Vec<&Diagnostic> = diagnostics.to_vec();
+    ordered.sort_by_key(|diagnostic| diagnostic.level != Level::Error);
+    let mut seen = HashSet::new();
+    ordered.retain(|diagnostic| seen.insert(message_text(diagnostic)));
+
+    // Line ranges to show per file, merged where they touch
+    let mut ranges: BTreeMap<String, Vec<(usize, usize)>> = BTreeMap::new();
+    let mut contents: BTreeMap<String, String> = BTreeMap::new();
+    let mut mentioned = message_text_all(&ordered);
+    for diagnostic in &ordered {
+        let Some(span) = diagnostic.primary_span() else {
+            continue;
+        };
+        if !contents.contains_key(&span.file) {
+            let Ok(content) = fs::read_to_string(root.join(&span.file)) else {
+                continue;
+            };
+            contents.insert(span.file.clone(), content);
+        }
+        let content = &contents[&span.file];
+        let around = (
+            span.line_start.saturating_sub(options.context).max(1),
+            span.line_end + options.context,
+        );
+        let file_ranges = ranges.entry(span.file.clone()).or_default();
+        file_ranges.push(around);
+        if let Ok(Some(function)) = parser.enclosing_function(content, span.line_start) {
+            if function.end_line - function.start_line < MAX_FUNCTION_LINES {
+                file_ranges.push((function.start_line, function.end_line));
+            } else {
+                file_ranges.push((function.start_line, function.start_line));
+            }
+        }
+    }
+
+    let mut sections = Vec::new();
+    sections.push(format!("{}\n## Diagnostics\n", HEADER));
+    for diagnostic in &ordered {
+        sections.push(format!(
+            "```\n{}\n```\n",
+            message_text(diagnostic).trim_end()
+        ));
+    }
+    for (file, file_ranges) in &mut ranges {
+        let lines: Vec<&str> = contents[file].lines().collect();
+        let section = numbered_excerpt(file, &lines, merge_ranges(file_ranges));
+        mentioned.push_str(&section);
+        sections.push(section);
+    }
+    let signatures = type_signatures(files, root, &type_names(&mentioned));
+    if !signatures.is_empty() {
+        sections.push("## Referenced types\n".to_string());
+        sections.extend(signatures);
+    }
+
+    // Keep sections in order while they fit; the header and the first
+    // diagnostic always go in
+    let mut text = String::new();
+    let mut omitted = 0;
+    for (index, section) in sections.iter().enumerate() {
+        let fits = estimate_tokens(&text) + estimate_tokens(section) <= options.budget;
+        if fits || index < 2 {
+            text.push_str(section);
+            text.push('\n');
+        } else {
+            omitted += 1;
+        }
+    }
+    if omitted > 0 {
+        text.push_str(&format!(
+            "({} more section(s) left out to fit the token budget)\n",
+            omitted
+        ));
+    }
+    Ok(FixPrompt {
+        tokens: estimate_tokens(&text),
+        diagnostics: ordered.len(),
+        text,
+        omitted,
+    })
+}
+
+/// rustc's rendering, or the title and notes when there is none
+fn message_text(diagnostic: &Diagnostic) -> String {
+    match &diagnostic.rendered {
+        Some(rendered) => rendered.clone(),
+        None => {
+            let mut text = diagnostic.title();
+            for child in &diagnostic.children {
+                text.push_str("\n  = ");
+                text.push_str(child);
+            }
+            text
+        }
+    }
+}
+
+fn message_text_all(diagnostics: &[&Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| message_text(diagnostic))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn merge_ranges(ranges: &mut [(usize, usize)]) -> Vec<(usize, usize)> {
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn numbered_excerpt(file: &str, lines: &[&str], ranges: Vec<(usize, usize)>) -> String {
+    let width = lines.len().to_string().len();
+    let mut excerpt = format!("### {}\n```rust\n", file);
+    for (position, (start, end)) in ranges.into_iter().enumerate() {
+        if position > 0 {
+            excerpt.push_str(&format!("{:>width$} ⋮\n", "", width = width));
+        }
+        for number in start..=end.min(lines.len()) {
+            excerpt.push_str(&format!(
+                "{:>width$} | {}\n",
+                number,
+                lines[number - 1],
+                width = width
+            ));
+        }
+    }
+    excerpt.push_str("```\n");
+    excerpt
+}
+
+/// CamelCase words, the candidates for project types
+fn type_names(text: &str) -> Vec<String> {
+    let pattern = Regex::new(r"\b[A-Z][A-Za-z0-9_]*[a-z][A-Za-z0-9_]*\b").expect("valid regex");
+    let mut names: Vec<String> = Vec::new();
+    for found in pattern.find_iter(text) {
+        if !names.iter().any(|name| name == found.as_str()) {
+            names.push(found.as_str().to_string());
+        }
+    }
+    names
+}
+
+/// Definitions of the project's types and traits called one of `names`,
+/// with the signatures of their inherent methods; doc comments and
+/// function bodies left out
+fn type_signatures(files: &[PathBuf], root: &Path, names: &[String]) -> Vec<String> {
+    let wanted = |ident: &syn::Ident| names.iter().any(|name| ident == name);
+    let mut found: Vec<(usize, String)> = Vec::new();
+    for path in files {
+        let Ok(content) = fs::read_to_string(path) else {
+            continue;
+        };
+        let Ok(parsed) = syn::parse_file(&content) else {
+            continue;
+        };
+        let mut items = Vec::new();
+        collect_items(&parsed.items, &mut items);
+
+        let mut kept: Vec<Item> = Vec::new();
+        let mut first_name = None;
+        for item in items {
+            let ident = match item {
+                Item::Struct(item) => &item.ident,
+                Item::Enum(item) => &item.ident,
+                Item::Union(item) => &item.ident,
+                Item::Trait(item) => &item.ident,
+                Item::Type(item) => &item.ident,
+                Item::Impl(item) if item.trait_.is_none() => match &*item.self_ty {
+                    syn::Type::Path(path) => match path.path.segments.last() {
+                        Some(segment) => &segment.ident,
+                        None => continue,
+                    },
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if wanted(ident) {
+                first_name.get_or_insert_with(|| ident.to_string());
+                kept.push(signature_only(item.clone()));
+            }
+        }
+        let Some(first_name) = first_name else {
+            continue;
+        };
+        let order = names
+            .iter()
+            .position(|name| *name == first_name)
+            .unwrap_or(usize::MAX);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let code = prettyplease::unparse(&syn::File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: kept,
+        })
+        .replace(" {}\n", ";\n");
+        found.push((
+            order,
+            format!("### {}\n```rust\n{}```\n", relative.display(), code),
+        ));
+    }
+    found.sort_by_key(|(order, _)| *order);
+    found.into_iter().map(|(_, section)| section).collect()
+}
+
+fn collect_items<'a>(items: &'a [Item], collected: &mut Vec<&'a Item>) {
+    for item in items {
+        match item {
+            Item::Mod(module) => {
+                if let Some((_, items)) = &module.content {
+                    collect_items(items, collected);
+                }
+            }
+            item => collected.push(item),
+        }
+    }
+}
+
+/// `item` without doc comments, and with empty method bodies that are
+/// turned into `;` after printing
+fn signature_only(mut item: Item) -> Item {
+    let is_doc = |attr: &syn::Attribute| attr.path().is_ident("doc");
+    match &mut item {
+        Item::Struct(item) => {
+            item.attrs.retain(|attr| !is_doc(attr));
+            for field in item.fields.iter_mut() {
+                field.attrs.retain(|attr| !is_doc(attr));
+            }
+        }
+        Item::Enum(item) => {
+            item.attrs.retain(|attr| !is_doc(attr));
+            for variant in item.variants.iter_mut() {
+                variant.attrs.retain(|attr| !is_doc(attr));
+            }
+        }
+        Item::Union(item) => item.attrs.retain(|attr| !is_doc(attr)),
+        Item::Type(item) => item.attrs.retain(|attr| !is_doc(attr)),
+        Item::Trait(item) => {
+            item.attrs.retain(|attr| !is_doc(attr));
+            for trait_item in item.items.iter_mut() {
+                if let TraitItem::Fn(method) = trait_item {
+                    method.attrs.retain(|attr| !is_doc(attr));
+                    method.default = None;
+                    method.semi_token = Some(Default::default());
+                }
+            }
+        }
+        Item::Impl(item) => {
+            item.attrs.retain(|attr| !is_doc(attr));
+            item.items
+                .retain(|impl_item| matches!(impl_item, ImplItem::Fn(_)));
+            for impl_item in item.items.iter_mut() {
+                if let ImplItem::Fn(method) = impl_item {
+                    method.attrs.retain(|attr| !is_doc(attr));
+                    method.block.stmts.clear();
+                }
+            }
+        }
+        _ => {}
+    }
+    item
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Span;
+
+    fn diagnostic(message: &str, line: usize) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            code: Some("E0308".to_string()),
+            message: message.to_string(),
+            spans: vec![Span {
+                file: "src/main.rs".to_string(),
+                byte_start: 0,
+                byte_end: 0,
+                line_start: line,
+                line_end: line,
+                column_start: 1,
+                column_end: 2,
+                is_primary: true,
+                label: None,
+            }],
+            children: Vec::new(),
+            suggestions: Vec::new(),
+            rendered: Some(format!("error[E0308]: {}\n", message)),
+        }
+    }
+
+    #[test]
+    fn test_build_fix_prompt() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = temp_dir.path().join("src/main.rs");
+        let npc = temp_dir.path().join("src/npc.rs");
+        let mut code = String::from("mod npc;\n\nfn spawn() -> npc::Npc {\n    let hp: u32 = \"full\";\n    npc::Npc::new(hp)\n}\n");
+        code.push_str(&"\n".repeat(20));
+        code.push_str("fn main() {\n    spawn();\n}\n");
+        fs::write(&main, code).unwrap();
+        fs::write(
+            &npc,
+            "/// A character\n#[derive(Debug)]\npub struct Npc {\n    /// Health\n    pub hp: u32,\n}\n\nimpl Npc {\n    pub fn new(hp: u32) -> Self {\n        Self { hp }\n    }\n}\n",
+        )
+        .unwrap();
+        let files = vec![main, npc];
+
+        let mismatch = diagnostic("mismatched types, expected `u32` for `Npc`", 4);
+        let again = diagnostic("mismatched types, expected `u32` for `Npc`", 4);
+        let prompt = build(
+            temp_dir.path(),
+            &files,
+            &[&mismatch, &again],
+            PromptOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(prompt.diagnostics, 1);
+        assert_eq!(prompt.omitted, 0);
+        assert!(prompt.text.starts_with(HEADER));
+        assert!(prompt.text.contains("3 | fn spawn() -> npc::Npc {\n"));
+        assert!(prompt.text.contains("7 | \n```"));
+        assert!(!prompt.text.contains("fn main()"));
+        assert!(prompt.text.contains(
+            "### src/npc.rs\n```rust\n#[derive(Debug)]\npub struct Npc {\n    pub hp: u32,\n}\nimpl Npc {\n    pub fn new(hp: u32) -> Self;\n}\n```"
+        ));
+
+        let tight = PromptOptions {
+            context: 0,
+            budget: 150,
+        };
+        let prompt = build(temp_dir.path(), &files, &[&mismatch], tight).unwrap();
+        assert!(prompt.omitted > 0);
+        assert!(prompt.text.contains("left out to fit the token budget"));
+    }
+}
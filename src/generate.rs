@@ -0,0 +1,1071 @@
+use crate::parser::StructField;
+use crate::patch::{located_lines, span_range, type_name};
+use crate::refactor::module_path;
+use anyhow::{anyhow, Result};
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{GenericArgument, GenericParam, Generics, Item, ItemTrait, PathArguments, TraitItem};
+
+/// Required items of common std traits, written as trait declarations
+/// with the paths they need once imported
+struct StdTrait {
+    name: &'static str,
+    path: &'static str,
+    uses: &'static [&'static str],
+    declaration: &'static str,
+}
+
+const STD_TRAITS: &[StdTrait] = &[
+    StdTrait {
+        name: "Display",
+        path: "fmt::Display",
+        uses: &["use std::fmt;"],
+        declaration: "trait Display { fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result; }",
+    },
+    StdTrait {
+        name: "Debug",
+        path: "fmt::Debug",
+        uses: &["use std::fmt;"],
+        declaration: "trait Debug { fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result; }",
+    },
+    StdTrait {
+        name: "From",
+        path: "From",
+        uses: &[],
+        declaration: "trait From<T> { fn from(value: T) -> Self; }",
+    },
+    StdTrait {
+        name: "TryFrom",
+        path: "TryFrom",
+        uses: &[],
+        declaration: "trait TryFrom<T> { type Error; fn try_from(value: T) -> Result<Self, Self::Error>; }",
+    },
+    StdTrait {
+        name: "FromStr",
+        path: "FromStr",
+        uses: &["use std::str::FromStr;"],
+        declaration: "trait FromStr { type Err; fn from_str(s: &str) -> Result<Self, Self::Err>; }",
+    },
+    StdTrait {
+        name: "AsRef",
+        path: "AsRef",
+        uses: &[],
+        declaration: "trait AsRef<T> { fn as_ref(&self) -> &T; }",
+    },
+    StdTrait {
+        name: "Iterator",
+        path: "Iterator",
+        uses: &[],
+        declaration: "trait Iterator { type Item; fn next(&mut self) -> Option<Self::Item>; }",
+    },
+    StdTrait {
+        name: "IntoIterator",
+        path: "IntoIterator",
+        uses: &[],
+        declaration: "trait IntoIterator { type Item; type IntoIter; fn into_iter(self) -> Self::IntoIter; }",
+    },
+    StdTrait {
+        name: "Default",
+        path: "Default",
+        uses: &[],
+        declaration: "trait Default { fn default() -> Self; }",
+    },
+    StdTrait {
+        name: "Clone",
+        path: "Clone",
+        uses: &[],
+        declaration: "trait Clone { fn clone(&self) -> Self; }",
+    },
+    StdTrait {
+        name: "Drop",
+        path: "Drop",
+        uses: &[],
+        declaration: "trait Drop { fn drop(&mut self); }",
+    },
+    StdTrait {
+        name: "PartialEq",
+        path: "PartialEq",
+        uses: &[],
+        declaration: "trait PartialEq<Rhs = Self> { fn eq(&self, other: &Rhs) -> bool; }",
+    },
+    StdTrait {
+        name: "PartialOrd",
+        path: "PartialOrd",
+        uses: &["use std::cmp::Ordering;"],
+        declaration: "trait PartialOrd<Rhs = Self> { fn partial_cmp(&self, other: &Rhs) -> Option<Ordering>; }",
+    },
+    StdTrait {
+        name: "Ord",
+        path: "Ord",
+        uses: &["use std::cmp::Ordering;"],
+        declaration: "trait Ord { fn cmp(&self, other: &Self) -> Ordering; }",
+    },
+    StdTrait {
+        name: "Hash",
+        path: "Hash",
+        uses: &["use std::hash::{Hash, Hasher};"],
+        declaration: "trait Hash { fn hash<H: Hasher>(&self, state: &mut H); }",
+    },
+    StdTrait {
+        name: "Deref",
+        path: "Deref",
+        uses: &["use std::ops::Deref;"],
+        declaration: "trait Deref { type Target; fn deref(&self) -> &Self::Target; }",
+    },
+    StdTrait {
+        name: "DerefMut",
+        path: "DerefMut",
+        uses: &["use std::ops::DerefMut;"],
+        declaration: "trait DerefMut { fn deref_mut(&mut self) -> &mut Self::Target; }",
+    },
+    StdTrait {
+        name: "Add",
+        path: "Add",
+        uses: &["use std::ops::Add;"],
+        declaration: "trait Add<Rhs = Self> { type Output; fn add(self, rhs: Rhs) -> Self::Output; }",
+    },
+    StdTrait {
+        name: "Sub",
+        path: "Sub",
+        uses: &["use std::ops::Sub;"],
+        declaration: "trait Sub<Rhs = Self> { type Output; fn sub(self, rhs: Rhs) -> Self::Output; }",
+    },
+    StdTrait {
+        name: "Index",
+        path: "Index",
+        uses: &["use std::ops::Index;"],
+        declaration: "trait Index<Idx> { type Output; fn index(&self, index: Idx) -> &Self::Output; }",
+    },
+    StdTrait {
+        name: "Error",
+        path: "Error",
+        uses: &["use std::error::Error;"],
+        declaration: "trait Error {}",
+    },
+];
+
+/// A type or trait definition found in the project
+pub struct Definition<T> {
+    pub file: PathBuf,
+    /// Module path within the file, for items in inline modules
+    pub inline_modules: Vec<String>,
+    pub item: T,
+}
+
+/// Finds the struct, enum or union called `name` in `files`
+pub fn find_type(files: &[PathBuf], name: &str) -> Result<Definition<Item>> {
+    find(files, &|item| match item {
+        Item::Struct(s) if s.ident == name => Some(item.clone()),
+        Item::Enum(e) if e.ident == name => Some(item.clone()),
+        Item::Union(u) if u.ident == name => Some(item.clone()),
+        _ => None,
+    })
+    .ok_or_else(|| anyhow!("Type '{}' not found in the project", name))
+}
+
+/// Finds the trait called `name` in `files`
+pub fn find_trait(files: &[PathBuf], name: &str) -> Option<Definition<ItemTrait>> {
+    find(files, &|item| match item {
+        Item::Trait(t) if t.ident == name => Some(t.clone()),
+        _ => None,
+    })
+}
+
+fn find<T>(files: &[PathBuf], matches: &dyn Fn(&Item) -> Option<T>) -> Option<Definition<T>> {
+    for file in files {
+        let Ok(content) = fs::read_to_string(file) else {
+            continue;
+        };
+        let Ok(parsed) = syn::parse_file(&content) else {
+            continue;
+        };
+        let mut modules = Vec::new();
+        if let Some(item) = find_in(&parsed.items, matches, &mut modules) {
+            return Some(Definition {
+                file: file.clone(),
+                inline_modules: modules,
+                item,
+            });
+        }
+    }
+    None
+}
+
+fn find_in<T>(
+    items: &[Item],
+    matches: &dyn Fn(&Item) -> Option<T>,
+    modules: &mut Vec<String>,
+) -> Option<T> {
+    for item in items {
+        if let Some(found) = matches(item) {
+            return Some(found);
+        }
+        if let Item::Mod(module) = item {
+            if let Some((_, items)) = &module.content {
+                modules.push(module.ident.to_string());
+                if let Some(found) = find_in(items, matches, modules) {
+                    return Some(found);
+                }
+                modules.pop();
+            }
+        }
+    }
+    None
+}
+
+pub fn generics_of(item: &Item) -> Generics {
+    match item {
+        Item::Struct(s) => s.generics.clone(),
+        Item::Enum(e) => e.generics.clone(),
+        Item::Union(u) => u.generics.clone(),
+        _ => Generics::default(),
+    }
+}
+
+/// Where a trait comes from when generating an impl for it
+pub enum TraitSource {
+    /// Declared in the project, importable through `use_path` when needed
+    Project {
+        declaration: Box<ItemTrait>,
+        use_path: Option<String>,
+    },
+    Std(&'static str),
+}
+
+impl TraitSource {
+    pub fn std(name: &str) -> Option<Self> {
+        STD_TRAITS
+            .iter()
+            .find(|t| t.name == name)
+            .map(|t| TraitSource::Std(t.name))
+    }
+}
+
+/// Renders `impl <requested> for <ty>` with every required method stubbed
+/// as `todo!()`, required associated types set to `()` and the imports the
+/// signatures need. `requested` may carry type arguments, e.g. `From<u32>`.
+pub fn trait_impl(source: &TraitSource, requested: &str, ty: &Item) -> Result<String> {
+    let requested: syn::Path =
+        syn::parse_str(requested).map_err(|e| anyhow!("Invalid trait '{}': {}", requested, e))?;
+    let segment = requested
+        .segments
+        .last()
+        .ok_or_else(|| anyhow!("Empty trait path"))?;
+
+    let (declaration, path, uses) = match source {
+        TraitSource::Project {
+            declaration,
+            use_path,
+        } => (
+            declaration.as_ref().clone(),
+            requested.clone(),
+            use_path.iter().cloned().collect(),
+        ),
+        TraitSource::Std(name) => {
+            let std_trait = STD_TRAITS
+                .iter()
+                .find(|t| t.name == *name)
+                .ok_or_else(|| anyhow!("Unknown trait"))?;
+            let mut path: syn::Path = syn::parse_str(std_trait.path)?;
+            if let Some(last) = path.segments.last_mut() {
+                last.arguments = segment.arguments.clone();
+            }
+            let declaration: ItemTrait = syn::parse_str(std_trait.declaration)?;
+            let uses: Vec<String> = std_trait.uses.iter().map(|u| u.to_string()).collect();
+            (declaration, path, uses)
+        }
+    };
+
+    let arguments: Vec<&syn::Type> = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    // Trait type parameters become the requested arguments or their defaults
+    let mut substitutions = HashMap::new();
+    let mut position = 0;
+    for param in &declaration.generics.params {
+        let GenericParam::Type(param) = param else {
+            continue;
+        };
+        let replacement = match (arguments.get(position), &param.default) {
+            (Some(ty), _) => ty.to_token_stream(),
+            (None, Some(default)) => default.to_token_stream(),
+            (None, None) => {
+                return Err(anyhow!(
+                    "Trait '{}' needs a type argument for '{}', e.g. {}<u32>",
+                    declaration.ident,
+                    param.ident,
+                    declaration.ident
+                ))
+            }
+        };
+        substitutions.insert(param.ident.to_string(), replacement);
+        position += 1;
+    }
+
+    let mut members = Vec::new();
+    for item in &declaration.items {
+        match item {
+            TraitItem::Type(assoc) if assoc.default.is_none() => {
+                let ident = &assoc.ident;
+                members.push(quote!(type #ident = ();));
+            }
+            TraitItem::Const(constant) if constant.default.is_none() => {
+                let ident = &constant.ident;
+                let ty = substitute(constant.ty.to_token_stream(), &substitutions);
+                members.push(quote!(const #ident: #ty = todo!();));
+            }
+            TraitItem::Fn(method) if method.default.is_none() => {
+                let sig = substitute(method.sig.to_token_stream(), &substitutions);
+                members.push(quote!(#sig { todo!() }));
+            }
+            _ => {}
+        }
+    }
+
+    let ty_ident = match ty {
+        Item::Struct(s) => &s.ident,
+        Item::Enum(e) => &e.ident,
+        Item::Union(u) => &u.ident,
+        _ => return Err(anyhow!("Expected a struct, enum or union")),
+    };
+    let generics = generics_of(ty);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let block = quote! {
+        impl #impl_generics #path for #ty_ident #ty_generics #where_clause {
+            #(#members)*
+        }
+    };
+
+    let mut code = uses.join("\n");
+    if !code.is_empty() {
+        code.push_str("\n\n");
+    }
+    code.push_str(&block.to_string());
+    Ok(code)
+}
+
+/// Replaces identifiers named in `substitutions` throughout `tokens`
+fn substitute(tokens: TokenStream, substitutions: &HashMap<String, TokenStream>) -> TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|token| match token {
+            TokenTree::Ident(ident) => match substitutions.get(&ident.to_string()) {
+                Some(replacement) => replacement.clone().into_iter().collect::<Vec<_>>(),
+                None => vec![TokenTree::Ident(ident)],
+            },
+            TokenTree::Group(group) => {
+                let mut replaced =
+                    Group::new(group.delimiter(), substitute(group.stream(), substitutions));
+                replaced.set_span(group.span());
+                vec![TokenTree::Group(replaced)]
+            }
+            token => vec![token],
+        })
+        .collect()
+}
+
+/// Whether `content` already has `impl <trait_name> for <ty>`
+pub fn has_trait_impl(content: &str, trait_name: &str, ty: &str) -> bool {
+    let Ok(file) = syn::parse_file(content) else {
+        return false;
+    };
+    file.items.iter().any(|item| match item {
+        Item::Impl(block) => {
+            let implements = block
+                .trait_
+                .as_ref()
+                .and_then(|(_, path, _)| path.segments.last())
+                .is_some_and(|segment| segment.ident == trait_name);
+            implements && type_name(&block.self_ty).as_deref() == Some(ty)
+        }
+        _ => false,
+    })
+}
+
+/// `use` declaration for an item declared at `inline_modules` inside `file`
+pub fn use_declaration(
+    root: &Path,
+    file: &Path,
+    inline_modules: &[String],
+    name: &str,
+) -> Option<String> {
+    let mut segments = vec!["crate".to_string()];
+    segments.extend(module_path(root, file)?);
+    segments.extend(inline_modules.iter().cloned());
+    segments.push(name.to_string());
+    Some(format!("use {};", segments.join("::")))
+}
+
+/// The trait's own name in a requested path like `std::convert::From<u32>`
+pub fn trait_ident(requested: &str) -> Result<String> {
+    let path: syn::Path =
+        syn::parse_str(requested).map_err(|e| anyhow!("Invalid trait '{}': {}", requested, e))?;
+    path.segments
+        .last()
+        .map(|segment| segment.ident.to_string())
+        .ok_or_else(|| anyhow!("Empty trait path"))
+}
+
+/// What `gen <kind>` produces for a struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generator {
+    /// `new` taking every field
+    New,
+    /// `<Type>Builder` with chainable setters and `build()`
+    Builder,
+    /// A getter and a `set_` method per field
+    Getters,
+}
+
+impl Generator {
+    pub fn parse(kind: &str) -> Result<Self> {
+        match kind {
+            "new" => Ok(Generator::New),
+            "builder" => Ok(Generator::Builder),
+            "getters" | "accessors" => Ok(Generator::Getters),
+            _ => Err(anyhow!(
+                "Unknown generator '{}'. Expected new, builder or getters",
+                kind
+            )),
+        }
+    }
+}
+
+/// Generated code for one struct
+#[derive(Debug, Default)]
+pub struct Generated {
+    /// Methods for the type's inherent impl
+    pub methods: Vec<String>,
+    /// The builder type, for `gen builder`
+    pub builder: Option<BuilderCode>,
+    /// Methods left out because the type already has them
+    pub skipped: Vec<String>,
+}
+
+/// A builder struct and the methods of its impl
+#[derive(Debug)]
+pub struct BuilderCode {
+    pub name: String,
+    pub declaration: String,
+    pub methods: Vec<String>,
+}
+
+/// Generates `generator`'s methods for the struct `ty` declared in `content`,
+/// skipping methods the type already has
+pub fn for_struct(
+    generator: Generator,
+    content: &str,
+    ty: &Item,
+    fields: &[StructField],
+) -> Result<Generated> {
+    let Item::Struct(structure) = ty else {
+        return Err(anyhow!("Code generation only works on structs"));
+    };
+    let existing = inherent_methods(content, &structure.ident.to_string());
+    let mut generated = Generated::default();
+    let mut method = |name: String, code: TokenStream| {
+        if existing.contains(&name) {
+            generated.skipped.push(name);
+        } else {
+            generated.methods.push(code.to_string());
+        }
+    };
+
+    match generator {
+        Generator::New => {
+            let names: Vec<_> = fields.iter().map(|f| format_ident!("{}", f.name)).collect();
+            let types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+            method(
+                "new".to_string(),
+                quote!(pub fn new(#(#names: #types),*) -> Self { Self { #(#names),* } }),
+            );
+        }
+        Generator::Getters => {
+            for field in fields {
+                let name = format_ident!("{}", field.name);
+                let setter = format_ident!("set_{}", field.name);
+                let (returns, body) = getter_shape(&field.ty, &name);
+                method(
+                    field.name.clone(),
+                    quote!(pub fn #name(&self) -> #returns { #body }),
+                );
+
+                let ty = &field.ty;
+                let code = if is_named(ty, "String") {
+                    quote!(pub fn #setter(&mut self, #name: impl Into<String>) { self.#name = #name.into(); })
+                } else {
+                    quote!(pub fn #setter(&mut self, #name: #ty) { self.#name = #name; })
+                };
+                method(setter.to_string(), code);
+            }
+        }
+        Generator::Builder => {
+            if !structure.generics.params.is_empty() {
+                return Err(anyhow!("gen builder does not support generic types yet"));
+            }
+            let ident = &structure.ident;
+            let builder = format_ident!("{}Builder", ident);
+            if declares(content, &builder.to_string()) {
+                return Err(anyhow!("'{}' already exists", builder));
+            }
+
+            let vis = &structure.vis;
+            let mut slots = Vec::new();
+            let mut setters = Vec::new();
+            let mut values = Vec::new();
+            for field in fields {
+                let name = format_ident!("{}", field.name);
+                let ty = &field.ty;
+                let inner = option_inner(ty);
+                let slot = inner.unwrap_or(ty);
+                slots.push(quote!(#name: Option<#slot>));
+                setters.push(if is_named(slot, "String") {
+                    quote!(pub fn #name(mut self, #name: impl Into<String>) -> Self { self.#name = Some(#name.into()); self })
+                } else {
+                    quote!(pub fn #name(mut self, #name: #slot) -> Self { self.#name = Some(#name); self })
+                });
+                let missing = format!("missing field '{}'", field.name);
+                values.push(match inner {
+                    Some(_) => quote!(#name: self.#name),
+                    None => quote!(#name: self.#name.ok_or(#missing)?),
+                });
+            }
+
+            method(
+                "builder".to_string(),
+                quote!(pub fn builder() -> #builder { #builder::default() }),
+            );
+            setters.push(quote! {
+                pub fn build(self) -> Result<#ident, String> {
+                    Ok(#ident { #(#values),* })
+                }
+            });
+            generated.builder = Some(BuilderCode {
+                name: builder.to_string(),
+                declaration: quote!(#[derive(Default)] #vis struct #builder { #(#slots),* })
+                    .to_string(),
+                methods: setters.iter().map(|setter| setter.to_string()).collect(),
+            });
+        }
+    }
+    Ok(generated)
+}
+
+/// Wraps `methods` in a new inherent impl for `ty`, generics included
+pub fn inherent_impl(ty: &Item, methods: &[String]) -> Result<String> {
+    let Item::Struct(structure) = ty else {
+        return Err(anyhow!("Code generation only works on structs"));
+    };
+    let ident = &structure.ident;
+    let (impl_generics, ty_generics, where_clause) = structure.generics.split_for_impl();
+    Ok(format!(
+        "{} {{\n{}\n}}",
+        quote!(impl #impl_generics #ident #ty_generics #where_clause),
+        methods.join("\n\n")
+    ))
+}
+
+/// Offset just past the last impl of `ty`, or the type itself, where code
+/// that belongs with the type goes
+pub fn after_type(content: &str, ty: &str) -> Option<usize> {
+    let file = syn::parse_file(content).ok()?;
+    let anchor = file.items.iter().rev().find(|item| match item {
+        Item::Impl(block) => type_name(&block.self_ty).as_deref() == Some(ty),
+        _ => false,
+    });
+    let anchor = anchor.or_else(|| {
+        file.items
+            .iter()
+            .find(|item| matches!(item, Item::Struct(s) if s.ident == ty))
+    })?;
+    Some(located_lines(content, span_range(content, anchor)).1)
+}
+
+/// Whether `content` has an inherent impl for `ty` to add methods to
+pub fn has_inherent_impl(content: &str, ty: &str) -> bool {
+    syn::parse_file(content).is_ok_and(|file| {
+        file.items.iter().any(|item| {
+            matches!(item, Item::Impl(block) if block.trait_.is_none() && type_name(&block.self_ty).as_deref() == Some(ty))
+        })
+    })
+}
+
+fn inherent_methods(content: &str, ty: &str) -> HashSet<String> {
+    let Ok(file) = syn::parse_file(content) else {
+        return HashSet::new();
+    };
+    file.items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Impl(block)
+                if block.trait_.is_none() && type_name(&block.self_ty).as_deref() == Some(ty) =>
+            {
+                Some(block)
+            }
+            _ => None,
+        })
+        .flat_map(|block| block.items.iter())
+        .filter_map(|item| match item {
+            syn::ImplItem::Fn(method) => Some(method.sig.ident.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn declares(content: &str, name: &str) -> bool {
+    syn::parse_file(content).is_ok_and(|file| {
+        file.items.iter().any(|item| match item {
+            Item::Struct(s) => s.ident == name,
+            Item::Enum(e) => e.ident == name,
+            _ => false,
+        })
+    })
+}
+
+/// Return type and body of a getter: borrowed views for owned containers,
+/// copies for primitives
+fn getter_shape(ty: &syn::Type, name: &syn::Ident) -> (TokenStream, TokenStream) {
+    const COPY: &[&str] = &[
+        "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64",
+        "u128", "usize", "f32", "f64",
+    ];
+
+    if let syn::Type::Reference(_) = ty {
+        return (quote!(#ty), quote!(self.#name));
+    }
+    if is_named(ty, "String") {
+        return (quote!(&str), quote!(&self.#name));
+    }
+    if let Some(inner) = option_inner(ty) {
+        return (quote!(Option<&#inner>), quote!(self.#name.as_ref()));
+    }
+    if let Some(inner) = generic_inner(ty, "Vec") {
+        return (quote!(&[#inner]), quote!(&self.#name));
+    }
+    if let Some(inner) = generic_inner(ty, "Box") {
+        return (quote!(&#inner), quote!(&self.#name));
+    }
+    if COPY.iter().any(|primitive| is_named(ty, primitive)) {
+        return (quote!(#ty), quote!(self.#name));
+    }
+    (quote!(&#ty), quote!(&self.#name))
+}
+
+fn is_named(ty: &syn::Type, name: &str) -> bool {
+    matches!(ty, syn::Type::Path(path) if path.qself.is_none()
+        && path.path.segments.last().is_some_and(|s| s.ident == name && s.arguments.is_empty()))
+}
+
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    generic_inner(ty, "Option")
+}
+
+/// `T` of `wrapper<T>`
+fn generic_inner<'a>(ty: &'a syn::Type, wrapper: &str) -> Option<&'a syn::Type> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Stub tests for the untested public functions of a file
+#[derive(Debug, Default)]
+pub struct TestStubs {
+    /// One `#[test]` function per untested function
+    pub tests: Vec<String>,
+    /// Functions the tests module already calls
+    pub covered: Vec<String>,
+    /// Functions a plain `#[test]` can't call, like `async fn`
+    pub skipped: Vec<String>,
+}
+
+/// What a tests module calls: paths by their last two segments, and
+/// methods by the type of their receiver where that can be told
+#[derive(Default)]
+struct Calls {
+    /// `(Some("Npc"), "new")` for `Npc::new`, `(None, "spawn")` for `spawn`
+    paths: HashSet<(Option<String>, String)>,
+    /// `("Npc", "heal")` for `npc.heal()` on a local known to be an `Npc`
+    methods: HashSet<(String, String)>,
+    /// Types of the locals seen so far
+    locals: HashMap<String, String>,
+}
+
+impl Calls {
+    /// The type `expr` evaluates to, when it is a known local, a struct
+    /// literal or a `Type::function(..)` call
+    fn expr_type(&self, expr: &syn::Expr) -> Option<String> {
+        match expr {
+            syn::Expr::Path(path) => self
+                .locals
+                .get(&path.path.get_ident()?.to_string())
+                .cloned(),
+            syn::Expr::Struct(literal) => Some(literal.path.segments.last()?.ident.to_string()),
+            syn::Expr::Call(call) => {
+                let syn::Expr::Path(function) = &*call.func else {
+                    return None;
+                };
+                let segments = &function.path.segments;
+                let owner = segments.iter().rev().nth(1)?.ident.to_string();
+                owner.starts_with(char::is_uppercase).then_some(owner)
+            }
+            syn::Expr::Reference(reference) => self.expr_type(&reference.expr),
+            syn::Expr::Paren(paren) => self.expr_type(&paren.expr),
+            _ => None,
+        }
+    }
+}
+
+impl<'ast> syn::visit::Visit<'ast> for Calls {
+    fn visit_local(&mut self, local: &'ast syn::Local) {
+        syn::visit::visit_local(self, local);
+        let (pat, ty) = match &local.pat {
+            syn::Pat::Type(typed) => (&*typed.pat, type_name(&typed.ty)),
+            pat => (pat, None),
+        };
+        let ty = ty.or_else(|| self.expr_type(&local.init.as_ref()?.expr));
+        if let (syn::Pat::Ident(binding), Some(ty)) = (pat, ty) {
+            self.locals.insert(binding.ident.to_string(), ty);
+        }
+    }
+
+    fn visit_expr_path(&mut self, path: &'ast syn::ExprPath) {
+        let mut segments = path.path.segments.iter().rev();
+        if let Some(name) = segments.next() {
+            let qualifier = segments.next().map(|segment| segment.ident.to_string());
+            self.paths.insert((qualifier, name.ident.to_string()));
+        }
+        syn::visit::visit_expr_path(self, path);
+    }
+
+    fn visit_expr_method_call(&mut self, call: &'ast syn::ExprMethodCall) {
+        if let Some(ty) = self.expr_type(&call.receiver) {
+            self.methods.insert((ty, call.method.to_string()));
+        }
+        syn::visit::visit_expr_method_call(self, call);
+    }
+
+    /// `assert_eq!(npc.hp(), 3)` and other macros taking expressions
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let parser = syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated;
+        if let Ok(exprs) = mac.parse_body_with(parser) {
+            for expr in &exprs {
+                self.visit_expr(expr);
+            }
+        }
+    }
+}
+
+/// Builds a stub test for every public function and inherent method in
+/// `content` that the file's `mod tests` doesn't call yet, by its path
+/// (`spawn`, `world::spawn`, `Npc::new`) or on a receiver of its type.
+/// Arguments get placeholders derived from their types and each test ends
+/// in `todo!()`.
+pub fn test_stubs(content: &str) -> Result<TestStubs> {
+    let file = syn::parse_file(content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+    let tests_module = file.items.iter().find_map(|item| match item {
+        Item::Mod(module) if module.ident == "tests" => Some(module),
+        _ => None,
+    });
+    let mut calls = Calls::default();
+    if let Some(module) = tests_module {
+        syn::visit::Visit::visit_item_mod(&mut calls, module);
+    }
+
+    let mut stubs = TestStubs::default();
+    let mut add = |owner: Option<&str>, sig: &syn::Signature| {
+        let name = sig.ident.to_string();
+        let label = match owner {
+            Some(owner) => format!("{}::{}", owner, name),
+            None => name.clone(),
+        };
+        let covered = match owner {
+            // `String::new` is not a call of a free `new`
+            None => calls.paths.iter().any(|(qualifier, called)| {
+                *called == name
+                    && qualifier
+                        .as_deref()
+                        .is_none_or(|qualifier| !qualifier.starts_with(char::is_uppercase))
+            }),
+            Some(owner) => {
+                calls
+                    .paths
+                    .contains(&(Some(owner.to_string()), name.clone()))
+                    || calls.methods.contains(&(owner.to_string(), name.clone()))
+            }
+        };
+        if covered {
+            stubs.covered.push(label);
+            return;
+        }
+        if sig.asyncness.is_some() || sig.ident == "main" {
+            stubs.skipped.push(label);
+            return;
+        }
+
+        let test_name = match owner {
+            Some(owner) => format_ident!("test_{}_{}", snake_case(owner), name),
+            None => format_ident!("test_{}", name),
+        };
+        let ident = &sig.ident;
+        let args = sig.inputs.iter().filter_map(|input| match input {
+            syn::FnArg::Typed(arg) => Some(placeholder(&arg.ty)),
+            syn::FnArg::Receiver(_) => None,
+        });
+        let receiver = sig.inputs.iter().find_map(|input| match input {
+            syn::FnArg::Receiver(receiver) => Some(receiver),
+            _ => None,
+        });
+
+        let (setup, call) = match (owner, receiver) {
+            (Some(owner), Some(receiver)) => {
+                let owner = format_ident!("{}", owner);
+                let binding = if receiver.reference.is_some() && receiver.mutability.is_some() {
+                    quote!(let mut subject: #owner = Default::default();)
+                } else {
+                    quote!(let subject: #owner = Default::default();)
+                };
+                (binding, quote!(subject.#ident(#(#args),*)))
+            }
+            (Some(owner), None) => {
+                let owner = format_ident!("{}", owner);
+                (quote!(), quote!(#owner::#ident(#(#args),*)))
+            }
+            (None, _) => (quote!(), quote!(#ident(#(#args),*))),
+        };
+        let body = match &sig.output {
+            syn::ReturnType::Default => quote!(#call; todo!("assert on the effects");),
+            syn::ReturnType::Type(..) => {
+                quote!(let _result = #call; todo!("assert on the result");)
+            }
+        };
+        stubs
+            .tests
+            .push(quote!(#[test] fn #test_name() { #setup #body }).to_string());
+    };
+
+    for item in &file.items {
+        match item {
+            Item::Fn(function) if matches!(function.vis, syn::Visibility::Public(_)) => {
+                add(None, &function.sig)
+            }
+            Item::Impl(block) if block.trait_.is_none() => {
+                let Some(owner) = type_name(&block.self_ty) else {
+                    continue;
+                };
+                for member in &block.items {
+                    if let syn::ImplItem::Fn(method) = member {
+                        if matches!(method.vis, syn::Visibility::Public(_)) {
+                            add(Some(&owner), &method.sig);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(stubs)
+}
+
+/// A value of type `ty` to pass in a stub test
+fn placeholder(ty: &syn::Type) -> TokenStream {
+    const INTEGERS: &[&str] = &[
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+    ];
+
+    match ty {
+        syn::Type::Reference(reference) => {
+            let inner = &*reference.elem;
+            if is_named(inner, "str") {
+                return quote!("");
+            }
+            if is_named(inner, "Path") {
+                return quote!(std::path::Path::new(""));
+            }
+            if let syn::Type::Slice(_) = inner {
+                return quote!(&[]);
+            }
+            let value = placeholder(inner);
+            match reference.mutability {
+                Some(_) => quote!(&mut #value),
+                None => quote!(&#value),
+            }
+        }
+        ty if INTEGERS.iter().any(|name| is_named(ty, name)) => quote!(0),
+        ty if is_named(ty, "f32") || is_named(ty, "f64") => quote!(0.0),
+        ty if is_named(ty, "bool") => quote!(false),
+        ty if is_named(ty, "char") => quote!('a'),
+        ty if is_named(ty, "String") => quote!(String::new()),
+        ty if generic_inner(ty, "Vec").is_some() => quote!(Vec::new()),
+        ty if option_inner(ty).is_some() => quote!(None),
+        _ => quote!(Default::default()),
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npc() -> Item {
+        syn::parse_str("struct Npc { hp: u32 }").unwrap()
+    }
+
+    fn pretty(code: &str) -> String {
+        prettyplease::unparse(&syn::parse_file(code).unwrap())
+    }
+
+    fn render(methods: &[String]) -> String {
+        pretty(&inherent_impl(&npc(), methods).unwrap())
+    }
+
+    #[test]
+    fn test_getters_follow_field_types() {
+        let ty: Item = syn::parse_str(
+            "struct Npc { name: String, hp: u32, target: Option<Npc>, tags: Vec<String> }",
+        )
+        .unwrap();
+        let fields = crate::parser::RustParser::new()
+            .struct_fields(
+                "struct Npc { name: String, hp: u32, target: Option<Npc>, tags: Vec<String> }",
+                "Npc",
+            )
+            .unwrap();
+        let generated = for_struct(
+            Generator::Getters,
+            "impl Npc { fn hp(&self) -> u32 { 1 } }",
+            &ty,
+            &fields,
+        )
+        .unwrap();
+        assert_eq!(generated.skipped, vec!["hp".to_string()]);
+
+        let code = render(&generated.methods);
+        assert!(code.contains("pub fn name(&self) -> &str {\n        &self.name\n    }"));
+        assert!(code.contains("pub fn set_name(&mut self, name: impl Into<String>) {\n        self.name = name.into();\n    }"));
+        assert!(code.contains(
+            "pub fn target(&self) -> Option<&Npc> {\n        self.target.as_ref()\n    }"
+        ));
+        assert!(code.contains("pub fn tags(&self) -> &[String] {"));
+        assert!(code.contains("pub fn set_hp(&mut self, hp: u32) {"));
+    }
+
+    #[test]
+    fn test_builder() {
+        let source = "pub struct Npc { name: String, hp: Option<u32> }";
+        let ty: Item = syn::parse_str(source).unwrap();
+        let fields = crate::parser::RustParser::new()
+            .struct_fields(source, "Npc")
+            .unwrap();
+        let generated = for_struct(Generator::Builder, source, &ty, &fields).unwrap();
+
+        assert_eq!(
+            render(&generated.methods),
+            "impl Npc {\n    pub fn builder() -> NpcBuilder {\n        NpcBuilder::default()\n    }\n}\n"
+        );
+        let builder = generated.builder.unwrap();
+        assert_eq!(
+            pretty(&builder.declaration),
+            "#[derive(Default)]\npub struct NpcBuilder {\n    name: Option<String>,\n    hp: Option<u32>,\n}\n"
+        );
+        assert_eq!(builder.methods.len(), 3);
+        assert!(pretty(&inherent_impl(&npc(), &builder.methods).unwrap())
+            .contains("Ok(Npc {\n            name: self.name.ok_or(\"missing field 'name'\")?,\n            hp: self.hp,\n        })"));
+    }
+
+    #[test]
+    fn test_stubs_for_untested_functions() {
+        let content = "pub fn spawn(name: &str, hp: u32) -> Npc { todo!() }\n\
+            pub fn reset(npcs: &mut Vec<Npc>) {}\n\
+            fn hidden() {}\n\
+            impl Npc { pub fn heal(&mut self, amount: f32) {} pub fn new() -> Self { todo!() } pub fn hp(&self) -> u32 { 0 } }\n\
+            #[cfg(test)]\nmod tests { #[test] fn test_reset() { super::reset(&mut vec![]); let name = String::new(); \
+            let npc = Npc::default(); assert_eq!(npc.hp(), name.len() as u32); } }\n";
+        let stubs = test_stubs(content).unwrap();
+        assert_eq!(
+            stubs.covered,
+            vec!["reset".to_string(), "Npc::hp".to_string()]
+        );
+        assert_eq!(stubs.tests.len(), 3);
+        assert_eq!(
+            pretty(&stubs.tests[0]),
+            "#[test]\nfn test_spawn() {\n    let _result = spawn(\"\", 0);\n    todo!(\"assert on the result\");\n}\n"
+        );
+        assert_eq!(
+            pretty(&stubs.tests[1]),
+            "#[test]\nfn test_npc_heal() {\n    let mut subject: Npc = Default::default();\n    subject.heal(0.0);\n    todo!(\"assert on the effects\");\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_std_trait_impl() {
+        let code = trait_impl(&TraitSource::std("Display").unwrap(), "Display", &npc()).unwrap();
+        assert_eq!(
+            pretty(&code),
+            "use std::fmt;\nimpl fmt::Display for Npc {\n    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {\n        todo!()\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_generic_trait_arguments_are_substituted() {
+        let code = trait_impl(&TraitSource::std("From").unwrap(), "From<u32>", &npc()).unwrap();
+        assert_eq!(
+            pretty(&code),
+            "impl From<u32> for Npc {\n    fn from(value: u32) -> Self {\n        todo!()\n    }\n}\n"
+        );
+        assert!(trait_impl(&TraitSource::std("From").unwrap(), "From", &npc()).is_err());
+    }
+
+    #[test]
+    fn test_project_trait_impl() {
+        let declaration: ItemTrait = syn::parse_str(
+            "trait Behaviour { type State; fn tick(&mut self, dt: f32) -> Self::State; fn name(&self) -> &str { \"npc\" } }",
+        )
+        .unwrap();
+        let source = TraitSource::Project {
+            declaration: Box::new(declaration),
+            use_path: Some("use crate::ai::Behaviour;".to_string()),
+        };
+        let code = trait_impl(&source, "Behaviour", &npc()).unwrap();
+        assert_eq!(
+            pretty(&code),
+            "use crate::ai::Behaviour;\nimpl Behaviour for Npc {\n    type State = ();\n    fn tick(&mut self, dt: f32) -> Self::State {\n        todo!()\n    }\n}\n"
+        );
+    }
+}
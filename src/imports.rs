@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashSet};
+use syn::{Item, ItemUse, UseTree};
+
+/// The last segment of a flattened `use` path
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Leaf {
+    Name(String),
+    Rename(String, String),
+    Glob,
+}
+
+/// One imported name, e.g. `std::collections::HashMap`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct UseLeaf {
+    path: Vec<String>,
+    leaf: Leaf,
+}
+
+impl UseLeaf {
+    /// The name this import brings into scope, if it has one
+    pub(crate) fn binding(&self) -> Option<&str> {
+        match &self.leaf {
+            Leaf::Name(name) if name == "self" => self.path.last().map(String::as_str),
+            Leaf::Name(name) => Some(name),
+            Leaf::Rename(_, alias) if alias == "_" => None,
+            Leaf::Rename(_, alias) => Some(alias),
+            Leaf::Glob => None,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// Whether this leaf imports the item `name` itself, renamed or not
+    pub(crate) fn imports(&self, name: &str) -> bool {
+        match &self.leaf {
+            Leaf::Name(leaf) | Leaf::Rename(leaf, _) => leaf == name,
+            Leaf::Glob => false,
+        }
+    }
+
+    /// The full path of the imported item, `None` for a glob
+    pub(crate) fn target(&self) -> Option<Vec<String>> {
+        match &self.leaf {
+            Leaf::Name(name) if name == "self" => Some(self.path.clone()),
+            Leaf::Name(name) | Leaf::Rename(name, _) => {
+                Some(self.path.iter().chain([name]).cloned().collect())
+            }
+            Leaf::Glob => None,
+        }
+    }
+
+    /// The same import from another module
+    pub(crate) fn with_path(&self, path: Vec<String>) -> Self {
+        Self {
+            path,
+            leaf: self.leaf.clone(),
+        }
+    }
+
+    fn leaf_text(&self) -> String {
+        match &self.leaf {
+            Leaf::Name(name) => name.clone(),
+            Leaf::Rename(name, alias) => format!("{} as {}", name, alias),
+            Leaf::Glob => "*".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for UseLeaf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for segment in &self.path {
+            write!(f, "{}::", segment)?;
+        }
+        write!(f, "{}", self.leaf_text())
+    }
+}
+
+/// How the incoming `use` declarations fold into the existing file
+#[derive(Debug, Default)]
+pub(crate) struct ImportPlan {
+    /// Existing `use` items (by index into the file's items) to re-render
+    pub replacements: Vec<(usize, String)>,
+    /// New `use` statements that have no existing declaration to join
+    pub additions: Vec<String>,
+    /// Imports that were not present before the merge
+    pub introduced: Vec<UseLeaf>,
+}
+
+type GroupKey = (String, Vec<String>);
+
+/// Attributes, visibility and leading `::` must match for two uses to merge
+fn group_key(item: &ItemUse) -> String {
+    let attrs = &item.attrs;
+    let vis = &item.vis;
+    let colon = if item.leading_colon.is_some() { "::" } else { "" };
+    format!("{} {} use {}", quote::quote!(#(#attrs)*), quote::quote!(#vis), colon)
+}
+
+fn flatten(tree: &UseTree, prefix: &mut Vec<String>, out: &mut Vec<UseLeaf>) {
+    match tree {
+        UseTree::Path(path) => {
+            prefix.push(path.ident.to_string());
+            flatten(&path.tree, prefix, out);
+            prefix.pop();
+        }
+        UseTree::Name(name) => out.push(UseLeaf {
+            path: prefix.clone(),
+            leaf: Leaf::Name(name.ident.to_string()),
+        }),
+        UseTree::Rename(rename) => out.push(UseLeaf {
+            path: prefix.clone(),
+            leaf: Leaf::Rename(rename.ident.to_string(), rename.rename.to_string()),
+        }),
+        UseTree::Glob(_) => out.push(UseLeaf {
+            path: prefix.clone(),
+            leaf: Leaf::Glob,
+        }),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                flatten(tree, prefix, out);
+            }
+        }
+    }
+}
+
+pub(crate) fn leaves_of(item: &ItemUse) -> Vec<UseLeaf> {
+    let mut leaves = Vec::new();
+    flatten(&item.tree, &mut Vec::new(), &mut leaves);
+    leaves
+}
+
+#[derive(Default)]
+struct Node {
+    leaves: Vec<String>,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert(&mut self, path: &[String], leaf: String) {
+        match path.split_first() {
+            Some((segment, rest)) => self.children.entry(segment.clone()).or_default().insert(rest, leaf),
+            None => {
+                if !self.leaves.contains(&leaf) {
+                    self.leaves.push(leaf);
+                }
+            }
+        }
+    }
+
+    fn entries(&self) -> Vec<String> {
+        let mut entries: Vec<String> = self.leaves.clone();
+        for (segment, child) in &self.children {
+            entries.push(format!("{}::{}", segment, child.render()));
+        }
+        // rustfmt order: `self` first, then the rest
+        entries.sort_by(|a, b| (a != "self").cmp(&(b != "self")).then(a.cmp(b)));
+        entries
+    }
+
+    fn render(&self) -> String {
+        let entries = self.entries();
+        if entries.len() == 1 {
+            entries[0].clone()
+        } else {
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+/// Renders leaves sharing one group key as `use` statements, one per root
+fn render(key: &str, leaves: &[UseLeaf]) -> Vec<String> {
+    let mut root = Node::default();
+    for leaf in leaves {
+        root.insert(&leaf.path, leaf.leaf_text());
+    }
+
+    let mut statements = Vec::new();
+    for leaf in &root.leaves {
+        statements.push(format!("{}{};", key, leaf));
+    }
+    for (segment, child) in &root.children {
+        statements.push(format!("{}{}::{};", key, segment, child.render()));
+    }
+    statements.into_iter().map(|s| pretty(&s)).collect()
+}
+
+/// Re-renders `item` with a new set of leaves, keeping its attributes and
+/// visibility
+pub(crate) fn render_use(item: &ItemUse, leaves: &[UseLeaf]) -> String {
+    render(&group_key(item), leaves).join("\n")
+}
+
+fn pretty(statement: &str) -> String {
+    match syn::parse_file(statement) {
+        Ok(file) => prettyplease::unparse(&file).trim_end().to_string(),
+        Err(_) => statement.trim().to_string(),
+    }
+}
+
+/// Merges `incoming` use declarations into the `use` items of `existing`,
+/// dropping imports that are already present
+pub(crate) fn plan(existing: &[Item], incoming: &[&ItemUse]) -> ImportPlan {
+    let mut seen = HashSet::new();
+    let mut globbed = HashSet::new();
+    let mut targets: Vec<(usize, String, Vec<UseLeaf>)> = Vec::new();
+
+    for (index, item) in existing.iter().enumerate() {
+        if let Item::Use(item) = item {
+            let key = group_key(item);
+            let leaves = leaves_of(item);
+            for leaf in &leaves {
+                if leaf.leaf == Leaf::Glob {
+                    globbed.insert((key.clone(), leaf.path.clone()));
+                }
+                seen.insert((key.clone(), leaf.clone()));
+            }
+            targets.push((index, key, leaves));
+        }
+    }
+
+    // New leaves grouped by key and module path, in order of appearance
+    let mut groups: Vec<(GroupKey, Vec<UseLeaf>)> = Vec::new();
+    let mut plan = ImportPlan::default();
+
+    for item in incoming {
+        let key = group_key(item);
+        for leaf in leaves_of(item) {
+            if globbed.contains(&(key.clone(), leaf.path.clone()))
+                || !seen.insert((key.clone(), leaf.clone()))
+            {
+                continue;
+            }
+            plan.introduced.push(leaf.clone());
+
+            let group = (key.clone(), leaf.path.clone());
+            match groups.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, leaves)) => leaves.push(leaf),
+                None => groups.push((group, vec![leaf])),
+            }
+        }
+    }
+
+    for ((key, path), new_leaves) in groups {
+        // Only merge into declarations that import from that one module, so
+        // `use a::b::{X, Y};` grows but never turns into a nested tree
+        let target = targets.iter_mut().find(|(_, k, leaves)| {
+            !path.is_empty() && *k == key && leaves.iter().all(|l| l.path == path)
+        });
+
+        match target {
+            Some((index, _, leaves)) => {
+                leaves.extend(new_leaves);
+                let rendered = render(&key, leaves).join("\n");
+                match plan.replacements.iter_mut().find(|(i, _)| i == index) {
+                    Some((_, text)) => *text = rendered,
+                    None => plan.replacements.push((*index, rendered)),
+                }
+            }
+            None => plan.additions.extend(render(&key, &new_leaves)),
+        }
+    }
+
+    plan
+}
+
+/// Introduced imports whose name never appears outside `use` items in `content`
+pub(crate) fn unused_imports(content: &str, introduced: &[UseLeaf]) -> Vec<String> {
+    let Ok(file) = syn::parse_file(content) else {
+        return Vec::new();
+    };
+
+    let mut idents = HashSet::new();
+    for item in &file.items {
+        if !matches!(item, Item::Use(_)) {
+            collect_idents(quote::quote!(#item), &mut idents);
+        }
+    }
+
+    introduced
+        .iter()
+        .filter(|leaf| leaf.binding().is_some_and(|name| !idents.contains(name)))
+        .map(|leaf| leaf.to_string())
+        .collect()
+}
+
+/// Walks a token stream, including macro bodies, collecting every identifier
+fn collect_idents(tokens: proc_macro2::TokenStream, idents: &mut HashSet<String>) {
+    for token in tokens {
+        match token {
+            proc_macro2::TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            proc_macro2::TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uses(code: &str) -> Vec<ItemUse> {
+        syn::parse_file(code)
+            .unwrap()
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Use(u) => Some(u),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_import_is_dropped() {
+        let existing = syn::parse_file("use std::collections::{HashMap, HashSet};").unwrap();
+        let incoming = uses("use std::collections::HashMap;");
+        let plan = plan(&existing.items, &incoming.iter().collect::<Vec<_>>());
+        assert!(plan.replacements.is_empty());
+        assert!(plan.additions.is_empty());
+        assert!(plan.introduced.is_empty());
+    }
+
+    #[test]
+    fn test_import_merges_into_existing_tree() {
+        let existing = syn::parse_file("use std::collections::HashMap;\nuse serde::Serialize;").unwrap();
+        let incoming = uses("use std::collections::{BTreeMap, HashMap};\nuse std::fmt;");
+        let plan = plan(&existing.items, &incoming.iter().collect::<Vec<_>>());
+        assert_eq!(
+            plan.replacements,
+            vec![(0, "use std::collections::{BTreeMap, HashMap};".to_string())]
+        );
+        assert_eq!(plan.additions, vec!["use std::fmt;".to_string()]);
+        assert_eq!(plan.introduced.len(), 2);
+    }
+
+    #[test]
+    fn test_unused_imports_are_reported() {
+        let content = "use std::fmt;\nuse std::collections::HashMap;\n\nfn f() -> HashMap<u8, u8> { HashMap::new() }\n";
+        let introduced = leaves_of(&uses("use std::{collections::HashMap, fmt};")[0]);
+        assert_eq!(unused_imports(content, &introduced), vec!["std::fmt".to_string()]);
+    }
+}
@@ -1,8 +1,24 @@
 mod app;
+mod build_history;
+mod cargo_args;
 mod command;
+mod config;
+mod diagnostics;
+mod fix_prompt;
+mod generate;
+mod imports;
+mod manifest;
+mod merge;
 mod parser;
 mod patch;
 mod project;
+mod refactor;
+mod reply;
+mod runner;
+mod scaffold;
+mod test_results;
+mod validate;
+mod workspace;
 
 use anyhow::Result;
 use tracing_subscriber;
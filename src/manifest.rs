@@ -0,0 +1,471 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+/// Which dependency table of `Cargo.toml` an entry lives in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DependencyKind {
+    #[default]
+    Normal,
+    Dev,
+    Build,
+}
+
+impl DependencyKind {
+    pub fn section(&self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Dev => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        }
+    }
+
+    const ALL: [DependencyKind; 3] = [
+        DependencyKind::Normal,
+        DependencyKind::Dev,
+        DependencyKind::Build,
+    ];
+}
+
+/// A dependency to add, as given to `dep add`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencySpec {
+    pub name: String,
+    /// Version requirement; a new dependency gets the newest cached version
+    /// when not given, an existing one keeps its requirement
+    pub version: Option<String>,
+    pub features: Vec<String>,
+    pub optional: bool,
+    pub kind: DependencyKind,
+}
+
+impl DependencySpec {
+    /// Parses `serde@1.0 --features derive,rc --dev --optional`
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut words = input.split_whitespace();
+        let crate_spec = words.next().ok_or_else(|| anyhow!("Missing crate name"))?;
+        let (name, version) = match crate_spec.split_once('@') {
+            Some((name, version)) => (name, Some(version.to_string())),
+            None => (crate_spec, None),
+        };
+        let mut spec = DependencySpec {
+            name: name.to_string(),
+            version,
+            ..Default::default()
+        };
+
+        while let Some(word) = words.next() {
+            match word {
+                "--features" | "-F" => {
+                    let list = words
+                        .next()
+                        .ok_or_else(|| anyhow!("Missing feature list after {}", word))?;
+                    spec.features.extend(
+                        list.split(',')
+                            .map(str::trim)
+                            .filter(|f| !f.is_empty())
+                            .map(String::from),
+                    );
+                }
+                "--dev" => spec.kind = DependencyKind::Dev,
+                "--build" => spec.kind = DependencyKind::Build,
+                "--optional" => spec.optional = true,
+                _ => return Err(anyhow!("Unknown option for dep add: {}", word)),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// One row of `dep list`
+pub struct DependencyEntry {
+    pub name: String,
+    pub kind: DependencyKind,
+    /// Version requirement, or `path`/`git` when the crate is not from a registry
+    pub source: String,
+    pub features: Vec<String>,
+}
+
+/// `Cargo.toml` of the project, edited in place so comments and layout survive
+pub struct Manifest {
+    path: PathBuf,
+    document: DocumentMut,
+}
+
+impl Manifest {
+    pub fn load(root_path: &Path) -> Result<Self> {
+        let path = root_path.join("Cargo.toml");
+        let content = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let document = content
+            .parse::<DocumentMut>()
+            .map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))?;
+        Ok(Self { path, document })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, self.document.to_string())?;
+        Ok(())
+    }
+
+    /// Adds `spec` or, when the crate is already a dependency of that kind,
+    /// merges its features into the existing entry
+    pub fn add_dependency(&mut self, spec: &DependencySpec) -> Result<()> {
+        if !self.document.contains_key("package") {
+            return Err(anyhow!(
+                "Cargo.toml has no [package], add the dependency to a member crate"
+            ));
+        }
+        let table = self.table_mut(spec.kind.section())?;
+
+        let (version, mut features, optional) = match table.get(&spec.name) {
+            Some(existing) => (
+                existing
+                    .as_str()
+                    .or_else(|| existing.get("version").and_then(Item::as_str))
+                    .map(String::from),
+                existing
+                    .get("features")
+                    .and_then(Item::as_array)
+                    .map(|array| {
+                        array
+                            .iter()
+                            .filter_map(|f| f.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                existing
+                    .get("optional")
+                    .and_then(Item::as_bool)
+                    .unwrap_or(false),
+            ),
+            None => (None, Vec::new(), false),
+        };
+        for feature in &spec.features {
+            if !features.contains(feature) {
+                features.push(feature.clone());
+            }
+        }
+        let version = spec.version.clone().or(version);
+        let optional = spec.optional || optional;
+
+        // Tables keep keys like `path` or `default-features`; a plain version string is rebuilt
+        if let Some(existing) = table.get_mut(&spec.name).and_then(Item::as_table_like_mut) {
+            if let Some(version) = version {
+                existing.insert("version", toml_edit::value(version));
+            }
+            if !features.is_empty() {
+                existing.insert("features", toml_edit::value(string_array(&features)));
+            }
+            if optional {
+                existing.insert("optional", toml_edit::value(true));
+            }
+            // Keys added to `{ path = ".." }` otherwise inherit its closing space
+            if let Some(inline) = table
+                .get_mut(&spec.name)
+                .and_then(Item::as_inline_table_mut)
+            {
+                inline.fmt();
+            }
+            return Ok(());
+        }
+
+        let version = version.ok_or_else(|| anyhow!("No version given for '{}'", spec.name))?;
+        let item = if features.is_empty() && !optional {
+            toml_edit::value(version)
+        } else {
+            let mut inline = InlineTable::new();
+            inline.insert("version", version.into());
+            if !features.is_empty() {
+                inline.insert("features", Value::Array(string_array(&features)));
+            }
+            if optional {
+                inline.insert("optional", true.into());
+            }
+            toml_edit::value(inline)
+        };
+        table.insert(&spec.name, item);
+        Ok(())
+    }
+
+    /// Whether `name` is already listed in the dependency table of `kind`
+    pub fn has_dependency(&self, kind: DependencyKind, name: &str) -> bool {
+        self.document
+            .get(kind.section())
+            .and_then(|table| table.get(name))
+            .is_some()
+    }
+
+    /// Removes `name` from whichever dependency tables list it
+    pub fn remove_dependency(&mut self, name: &str) -> Result<Vec<DependencyKind>> {
+        let mut removed = Vec::new();
+        for kind in DependencyKind::ALL {
+            let table = self
+                .document
+                .get_mut(kind.section())
+                .and_then(Item::as_table_like_mut);
+            if table.and_then(|table| table.remove(name)).is_some() {
+                removed.push(kind);
+            }
+        }
+        if removed.is_empty() {
+            return Err(anyhow!("'{}' is not a dependency", name));
+        }
+        Ok(removed)
+    }
+
+    pub fn dependencies(&self) -> Vec<DependencyEntry> {
+        let mut entries = Vec::new();
+        for kind in DependencyKind::ALL {
+            let Some(table) = self
+                .document
+                .get(kind.section())
+                .and_then(Item::as_table_like)
+            else {
+                continue;
+            };
+            for (name, item) in table.iter() {
+                let source = match item.as_str() {
+                    Some(version) => version.to_string(),
+                    None => ["version", "path", "git"]
+                        .iter()
+                        .find_map(|key| item.get(key).and_then(Item::as_str).map(|v| (key, v)))
+                        .map(|(key, value)| match *key {
+                            "version" => value.to_string(),
+                            _ => format!("{} = {}", key, value),
+                        })
+                        .unwrap_or_else(|| "*".to_string()),
+                };
+                let features = item
+                    .get("features")
+                    .and_then(Item::as_array)
+                    .map(|array| {
+                        array
+                            .iter()
+                            .filter_map(|f| f.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                entries.push(DependencyEntry {
+                    name: name.to_string(),
+                    kind,
+                    source,
+                    features,
+                });
+            }
+        }
+        entries
+    }
+
+    /// Adds the crate feature `name` to `[features]`, or extends it with
+    /// the entries of `enables` it doesn't list yet
+    pub fn add_feature(&mut self, name: &str, enables: &[String]) -> Result<()> {
+        let table = self.table_mut("features")?;
+        match table.get_mut(name).and_then(Item::as_array_mut) {
+            Some(existing) => {
+                for entry in enables {
+                    if !existing.iter().any(|e| e.as_str() == Some(entry)) {
+                        existing.push(entry.as_str());
+                    }
+                }
+            }
+            None => {
+                table.insert(name, toml_edit::value(string_array(enables)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a `[[bin]]`, `[[example]]` or `[[test]]` entry unless one with
+    /// that name exists. Returns whether anything was added.
+    pub fn add_target(&mut self, section: &str, name: &str, path: &str) -> Result<bool> {
+        let targets = self
+            .document
+            .entry(section)
+            .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .ok_or_else(|| anyhow!("{} in Cargo.toml is not an array of tables", section))?;
+        if targets
+            .iter()
+            .any(|target| target.get("name").and_then(Item::as_str) == Some(name))
+        {
+            return Ok(false);
+        }
+
+        let mut target = Table::new();
+        target.insert("name", toml_edit::value(name));
+        target.insert("path", toml_edit::value(path));
+        targets.push(target);
+        Ok(true)
+    }
+
+    /// Adds `member` to `[workspace] members`, creating the workspace
+    /// table when the manifest has none
+    pub fn add_workspace_member(&mut self, member: &str) -> Result<bool> {
+        let workspace = self.table_mut("workspace")?;
+        let members = workspace
+            .entry("members")
+            .or_insert(toml_edit::value(Array::new()))
+            .as_array_mut()
+            .ok_or_else(|| anyhow!("workspace.members in Cargo.toml is not an array"))?;
+        if members.iter().any(|m| m.as_str() == Some(member)) {
+            return Ok(false);
+        }
+        members.push(member);
+        Ok(true)
+    }
+
+    /// `package.<key>` when it is a plain string, e.g. `name` or `edition`
+    pub fn package_field(&self, key: &str) -> Option<&str> {
+        self.document.get("package")?.get(key)?.as_str()
+    }
+
+    fn table_mut(&mut self, section: &str) -> Result<&mut dyn TableLike> {
+        self.document
+            .entry(section)
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("[{}] in Cargo.toml is not a table", section))
+    }
+}
+
+fn string_array(values: &[String]) -> Array {
+    values.iter().map(String::as_str).collect()
+}
+
+/// Versions of `name` in the local cargo registry cache, oldest first.
+/// These are the versions an offline build can use.
+pub fn offline_versions(name: &str) -> Vec<String> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+    match cargo_home {
+        Some(cargo_home) => cached_versions(&cargo_home.join("registry").join("cache"), name),
+        None => Vec::new(),
+    }
+}
+
+fn cached_versions(cache: &Path, name: &str) -> Vec<String> {
+    let mut versions = Vec::new();
+    let Ok(registries) = fs::read_dir(cache) else {
+        return versions;
+    };
+    for registry in registries.flatten() {
+        let Ok(files) = fs::read_dir(registry.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let file_name = file.file_name().to_string_lossy().to_string();
+            let version = file_name
+                .strip_suffix(".crate")
+                .and_then(|stem| stem.strip_prefix(name))
+                .and_then(|rest| rest.strip_prefix('-'))
+                // `serde-json-1.0.crate` must not count as a version of `serde`
+                .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()));
+            if let Some(version) = version {
+                if !versions.iter().any(|v| v == version) {
+                    versions.push(version.to_string());
+                }
+            }
+        }
+    }
+    versions.sort_by_key(|version| version_key(version));
+    versions
+}
+
+/// Numeric `major.minor.patch` and whether it is a full release, for sorting
+fn version_key(version: &str) -> (Vec<u64>, bool) {
+    let (numbers, pre) = match version.split('+').next().unwrap_or(version).split_once('-') {
+        Some((numbers, _)) => (numbers, true),
+        None => (version.split('+').next().unwrap_or(version), false),
+    };
+    (
+        numbers.split('.').map(|n| n.parse().unwrap_or(0)).collect(),
+        !pre,
+    )
+}
+
+/// The newest cached release, the default requirement for `dep add`
+pub fn newest_release(versions: &[String]) -> Option<&String> {
+    versions.iter().rev().find(|version| version_key(version).1)
+}
+
+/// Whether a cached `version` satisfies a caret requirement like `1.0` or
+/// `0.22`. Requirements with operators are not checked and count as met.
+pub fn satisfies(requirement: &str, version: &str) -> bool {
+    let requirement = requirement.trim().trim_start_matches('^');
+    if !requirement.starts_with(|c: char| c.is_ascii_digit()) {
+        return true;
+    }
+    let wanted = version_key(requirement).0;
+    let (found, release) = version_key(version);
+    if !release || found < wanted {
+        return false;
+    }
+    // Caret: everything up to and including the first non-zero part is fixed
+    let fixed = wanted
+        .iter()
+        .position(|n| *n != 0)
+        .map_or(wanted.len(), |i| i + 1);
+    wanted[..fixed.min(wanted.len())] == found[..fixed.min(found.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_edits_keep_comments() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"game\"\n\n# Runtime crates\n[dependencies]\n# Rendering\nwgpu = \"0.19\"\nrand = \"0.8\"\nlog = \"0.4\" # keep in sync with tracing\n",
+        )
+        .unwrap();
+
+        let mut manifest = Manifest::load(temp_dir.path()).unwrap();
+        manifest
+            .add_dependency(&DependencySpec::parse("serde@1.0 --features derive").unwrap())
+            .unwrap();
+        manifest
+            .add_dependency(&DependencySpec::parse("rand --features small_rng").unwrap())
+            .unwrap();
+        manifest.remove_dependency("wgpu").unwrap();
+        manifest
+            .add_feature("net", &["dep:serde".to_string()])
+            .unwrap();
+        manifest.save().unwrap();
+
+        let content = fs::read_to_string(temp_dir.path().join("Cargo.toml")).unwrap();
+        assert_eq!(
+            content,
+            "[package]\nname = \"game\"\n\n# Runtime crates\n[dependencies]\nrand = { version = \"0.8\", features = [\"small_rng\"] }\nlog = \"0.4\" # keep in sync with tracing\nserde = { version = \"1.0\", features = [\"derive\"] }\n\n[features]\nnet = [\"dep:serde\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_cached_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = temp_dir.path().join("index.crates.io-0000");
+        fs::create_dir_all(&registry).unwrap();
+        for file in [
+            "serde-1.0.9.crate",
+            "serde-1.0.10.crate",
+            "serde-2.0.0-rc.1.crate",
+            "serde_json-1.0.0.crate",
+        ] {
+            fs::write(registry.join(file), "").unwrap();
+        }
+
+        let versions = cached_versions(temp_dir.path(), "serde");
+        assert_eq!(versions, vec!["1.0.9", "1.0.10", "2.0.0-rc.1"]);
+        assert_eq!(
+            newest_release(&versions).map(String::as_str),
+            Some("1.0.10")
+        );
+        assert!(satisfies("1.0", "1.0.10"));
+        assert!(!satisfies("0.22", "0.23.1"));
+    }
+}
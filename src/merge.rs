@@ -0,0 +1,184 @@
+/// Outcome of a line-based three-way merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub content: String,
+    /// Regions changed on both sides, written with conflict markers
+    pub conflicts: usize,
+}
+
+/// Merges two edits of `base`: `ours` (the tool's change) and `theirs`
+/// (what is on disk now). Regions only one side touched take that side;
+/// regions both touched differently get `<<<<<<<` markers. Lines keep
+/// their own `\n` or `\r\n`, and a missing final newline stays missing.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let on_disk = theirs;
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let to_ours = matching_lines(&base, &ours);
+    let to_theirs = matching_lines(&base, &theirs);
+
+    // Markers follow the file on disk
+    let eol = if on_disk.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut content = String::new();
+    let mut conflicts = 0;
+    let (mut i, mut a, mut b) = (0, 0, 0);
+
+    loop {
+        // The next base line both sides kept is where the sides agree again
+        let sync = (i..base.len()).find_map(|k| Some((k, to_ours[k]?, to_theirs[k]?)));
+        let (k, a_end, b_end) = sync.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        let (base_chunk, our_chunk, their_chunk) =
+            (&base[i..k], &ours[a..a_end], &theirs[b..b_end]);
+        if same(our_chunk, base_chunk) {
+            content.extend(their_chunk.iter().copied());
+        } else if same(their_chunk, base_chunk) || same(our_chunk, their_chunk) {
+            content.extend(our_chunk.iter().copied());
+        } else {
+            conflicts += 1;
+            let sections = [
+                ("<<<<<<< generated", our_chunk),
+                ("||||||| last viewed", base_chunk),
+                ("=======", their_chunk),
+            ];
+            for (marker, chunk) in sections {
+                content.push_str(marker);
+                content.push_str(eol);
+                for line in chunk {
+                    content.push_str(line);
+                    if !line.ends_with('\n') {
+                        content.push_str(eol);
+                    }
+                }
+            }
+            content.push_str(">>>>>>> on disk");
+            content.push_str(eol);
+        }
+
+        if sync.is_none() {
+            break;
+        }
+        // The line is the same on all sides, take it as it is on disk
+        content.push_str(theirs[b_end]);
+        (i, a, b) = (k + 1, a_end + 1, b_end + 1);
+    }
+
+    MergeResult { content, conflicts }
+}
+
+/// A line without its terminator
+fn text(line: &str) -> &str {
+    line.strip_suffix('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .unwrap_or(line)
+}
+
+/// Whether two runs of lines read the same, whatever their line endings
+fn same(x: &[&str], y: &[&str]) -> bool {
+    x.len() == y.len() && x.iter().zip(y).all(|(x, y)| text(x) == text(y))
+}
+
+/// For each line of `base`, the line of `other` it is matched with in a
+/// longest common subsequence
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; base.len()];
+
+    // Common prefix and suffix first keeps the table small for local edits
+    let prefix = base
+        .iter()
+        .zip(other)
+        .take_while(|(x, y)| text(x) == text(y))
+        .count();
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(other[prefix..].iter().rev())
+        .take_while(|(x, y)| text(x) == text(y))
+        .count();
+    for (index, slot) in matched.iter_mut().enumerate().take(prefix) {
+        *slot = Some(index);
+    }
+    for offset in 1..=suffix {
+        matched[base.len() - offset] = Some(other.len() - offset);
+    }
+
+    let base_middle = &base[prefix..base.len() - suffix];
+    let other_middle = &other[prefix..other.len() - suffix];
+    let (n, m) = (base_middle.len(), other_middle.len());
+    let mut table = vec![vec![0u32; m + 1]; n + 1];
+    for x in (0..n).rev() {
+        for y in (0..m).rev() {
+            table[x][y] = if text(base_middle[x]) == text(other_middle[y]) {
+                table[x + 1][y + 1] + 1
+            } else {
+                table[x + 1][y].max(table[x][y + 1])
+            };
+        }
+    }
+
+    let (mut x, mut y) = (0, 0);
+    while x < n && y < m {
+        if text(base_middle[x]) == text(other_middle[y]) {
+            matched[prefix + x] = Some(prefix + y);
+            x += 1;
+            y += 1;
+        } else if table[x + 1][y] >= table[x][y + 1] {
+            x += 1;
+        } else {
+            y += 1;
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separate_edits_merge_cleanly() {
+        let base = "fn a() {}\n\nfn b() {}\n";
+        let ours = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+        let theirs = "fn a() { println!(); }\n\nfn b() {}\n";
+        let result = merge3(base, ours, theirs);
+        assert_eq!(result.conflicts, 0);
+        assert_eq!(
+            result.content,
+            "fn a() { println!(); }\n\nfn b() {}\n\nfn c() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_edits_conflict() {
+        let base = "fn a() {}\nfn b() {}\n";
+        let ours = "fn a() -> u8 { 1 }\nfn b() {}\n";
+        let theirs = "fn a() -> u16 { 2 }\nfn b() {}\n";
+        let result = merge3(base, ours, theirs);
+        assert_eq!(result.conflicts, 1);
+        assert_eq!(
+            result.content,
+            "<<<<<<< generated\nfn a() -> u8 { 1 }\n||||||| last viewed\nfn a() {}\n=======\nfn a() -> u16 { 2 }\n>>>>>>> on disk\nfn b() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_line_endings_are_kept() {
+        let result = merge3("a\r\nb\r\n", "a\r\nb\r\nc\r\n", "x\r\nb\r\n");
+        assert_eq!(result.content, "x\r\nb\r\nc\r\n");
+
+        let result = merge3("a\nb", "a\nb", "a\nc");
+        assert_eq!(result.content, "a\nc");
+
+        let result = merge3("a\r\nb", "a\r\nx", "a\r\ny");
+        assert_eq!(result.conflicts, 1);
+        assert_eq!(
+            result.content,
+            "a\r\n<<<<<<< generated\r\nx\r\n||||||| last viewed\r\nb\r\n=======\r\ny\r\n>>>>>>> on disk\r\n"
+        );
+    }
+}
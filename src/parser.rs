@@ -1,8 +1,24 @@
 use anyhow::{anyhow, Result};
+use syn::spanned::Spanned;
 use syn::{visit::Visit, File, Item, ItemFn, ItemStruct, ItemEnum, ItemImpl};
 
 pub struct RustParser;
 
+/// Where a function or method sits in a file, lines 1-based and inclusive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSpan {
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// A named struct field as read by `RustParser::struct_fields`
+#[derive(Debug, Clone)]
+pub struct StructField {
+    pub name: String,
+    pub ty: syn::Type,
+}
+
 impl RustParser {
     pub fn new() -> Self {
         Self
@@ -26,6 +42,32 @@ impl RustParser {
         Ok(visitor.structs)
     }
 
+    /// Named fields of the struct `name`, in declaration order
+    pub fn struct_fields(&self, content: &str, name: &str) -> Result<Vec<StructField>> {
+        let ast = self.parse_file(content)?;
+        let mut visitor = StructVisitor::new();
+        visitor.visit_file(&ast);
+
+        let node = visitor
+            .nodes
+            .into_iter()
+            .find(|node| node.ident == name)
+            .ok_or_else(|| anyhow!("Struct '{}' not found", name))?;
+        match node.fields {
+            syn::Fields::Named(fields) => Ok(fields
+                .named
+                .into_iter()
+                .filter_map(|field| {
+                    Some(StructField {
+                        name: field.ident?.to_string(),
+                        ty: field.ty,
+                    })
+                })
+                .collect()),
+            _ => Err(anyhow!("Struct '{}' has no named fields", name)),
+        }
+    }
+
     pub fn list_enums(&self, content: &str) -> Result<Vec<String>> {
         let ast = self.parse_file(content)?;
         let mut visitor = EnumVisitor::new();
@@ -55,6 +97,15 @@ impl RustParser {
         Err(anyhow!("Function '{}' not found", function_name))
     }
 
+    /// The innermost function, method or default trait method containing
+    /// `line`
+    pub fn enclosing_function(&self, content: &str, line: usize) -> Result<Option<FunctionSpan>> {
+        let ast = self.parse_file(content)?;
+        let mut visitor = EnclosingVisitor { line, found: None };
+        visitor.visit_file(&ast);
+        Ok(visitor.found)
+    }
+
     pub fn find_item_location(&self, content: &str, item_name: &str) -> Result<(usize, usize)> {
         let lines: Vec<&str> = content.lines().collect();
         
@@ -118,14 +169,56 @@ impl<'ast> Visit<'ast> for FunctionVisitor {
     }
 }
 
+struct EnclosingVisitor {
+    line: usize,
+    found: Option<FunctionSpan>,
+}
+
+impl EnclosingVisitor {
+    fn consider(&mut self, name: &syn::Ident, node: &dyn Spanned) {
+        let span = node.span();
+        let (start_line, end_line) = (span.start().line, span.end().line);
+        if !(start_line..=end_line).contains(&self.line) {
+            return;
+        }
+        // Visiting goes outside in, so a later match is nested in an earlier one
+        self.found = Some(FunctionSpan {
+            name: name.to_string(),
+            start_line,
+            end_line,
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for EnclosingVisitor {
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        self.consider(&node.sig.ident, node);
+        syn::visit::visit_item_fn(self, node);
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast syn::ImplItemFn) {
+        self.consider(&node.sig.ident, node);
+        syn::visit::visit_impl_item_fn(self, node);
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast syn::TraitItemFn) {
+        if node.default.is_some() {
+            self.consider(&node.sig.ident, node);
+        }
+        syn::visit::visit_trait_item_fn(self, node);
+    }
+}
+
 struct StructVisitor {
     structs: Vec<String>,
+    nodes: Vec<ItemStruct>,
 }
 
 impl StructVisitor {
     fn new() -> Self {
         Self {
             structs: Vec::new(),
+            nodes: Vec::new(),
         }
     }
 }
@@ -134,6 +227,7 @@ impl<'ast> Visit<'ast> for StructVisitor {
     fn visit_item_struct(&mut self, node: &'ast ItemStruct) {
         let name = node.ident.to_string();
         self.structs.push(name);
+        self.nodes.push(node.clone());
     }
 }
 
@@ -190,4 +284,21 @@ mod tests {
         let func = parser.extract_function(code, "add").unwrap();
         assert!(func.contains("add"));
     }
+
+    #[test]
+    fn test_enclosing_function() {
+        let code = "struct Npc;\n\nimpl Npc {\n    /// Heals\n    fn heal(&mut self) {\n        let hp = 1;\n    }\n}\n";
+
+        let parser = RustParser::new();
+        let found = parser.enclosing_function(code, 6).unwrap().unwrap();
+        assert_eq!(
+            found,
+            FunctionSpan {
+                name: "heal".to_string(),
+                start_line: 4,
+                end_line: 7,
+            }
+        );
+        assert_eq!(parser.enclosing_function(code, 1).unwrap(), None);
+    }
 }
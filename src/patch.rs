@@ -0,0 +1,1454 @@
+use crate::imports::{self, UseLeaf};
+use anyhow::{anyhow, Result};
+use proc_macro2::LineColumn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::OnceLock;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Attribute, Fields, File, ImplItem, Item, ItemImpl, ItemUse, Path, Token};
+
+/// How inserted code is pretty-printed before it lands in the file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatMode {
+    /// Use rustfmt when it is on PATH, prettyplease otherwise
+    #[default]
+    Auto,
+    /// Pipe each item through rustfmt (keeps comments)
+    Rustfmt,
+    /// Re-print each item from its syntax tree (drops plain `//` comments)
+    Prettyplease,
+    /// Insert the code exactly as pasted
+    Off,
+}
+
+/// Where `add into <file>::<target>` puts the code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertTarget {
+    /// `impl Type` or `impl Trait for Type`: methods, consts and associated types
+    Impl {
+        trait_name: Option<String>,
+        self_ty: String,
+    },
+    /// `mod name`: any items, created as `#[cfg(test)]` for `mod tests`
+    Module(String),
+    /// `Type`: struct fields, enum variants and `#[derive(..)]` attributes
+    Type(String),
+}
+
+impl InsertTarget {
+    pub fn parse(target: &str) -> Result<Self> {
+        let target = target.trim();
+
+        if let Some(rest) = target.strip_prefix("impl ") {
+            let (trait_name, self_ty) = match rest.split_once(" for ") {
+                Some((trait_name, self_ty)) => (Some(trait_name.trim()), self_ty.trim()),
+                None => (None, rest.trim()),
+            };
+            if let Some(trait_name) = trait_name {
+                syn::parse_str::<Path>(trait_name)
+                    .map_err(|_| anyhow!("Invalid trait name: {}", trait_name))?;
+            }
+            syn::parse_str::<syn::Type>(self_ty).map_err(|_| anyhow!("Invalid type: {}", self_ty))?;
+            return Ok(InsertTarget::Impl {
+                trait_name: trait_name.map(String::from),
+                self_ty: self_ty.to_string(),
+            });
+        }
+
+        if let Some(name) = target.strip_prefix("mod ") {
+            let name = name.trim();
+            syn::parse_str::<syn::Ident>(name).map_err(|_| anyhow!("Invalid module name: {}", name))?;
+            return Ok(InsertTarget::Module(name.to_string()));
+        }
+
+        syn::parse_str::<syn::Ident>(target).map_err(|_| {
+            anyhow!(
+                "Unknown target '{}'. Expected 'impl Type', 'impl Trait for Type', 'mod name' or 'Type'",
+                target
+            )
+        })?;
+        Ok(InsertTarget::Type(target.to_string()))
+    }
+
+    fn matches_impl(&self, item: &ItemImpl) -> bool {
+        let InsertTarget::Impl { trait_name, self_ty } = self else {
+            return false;
+        };
+        let Ok(self_ty) = syn::parse_str::<syn::Type>(self_ty) else {
+            return false;
+        };
+        if type_name(&item.self_ty) != type_name(&self_ty) {
+            return false;
+        }
+
+        match (trait_name, &item.trait_) {
+            (None, None) => true,
+            (Some(name), Some((_, path, _))) => syn::parse_str::<Path>(name)
+                .map(|wanted| last_segment(&wanted) == last_segment(path))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for InsertTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertTarget::Impl {
+                trait_name: Some(trait_name),
+                self_ty,
+            } => write!(f, "impl {} for {}", trait_name, self_ty),
+            InsertTarget::Impl {
+                trait_name: None,
+                self_ty,
+            } => write!(f, "impl {}", self_ty),
+            InsertTarget::Module(name) => write!(f, "mod {}", name),
+            InsertTarget::Type(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A pending text replacement in the existing file. Edits at the same
+/// offset end up in ascending `order`.
+struct Edit {
+    start: usize,
+    end: usize,
+    text: String,
+    order: usize,
+}
+
+/// The items merged code can join: the file itself or the body of an
+/// inline module
+struct Scope<'a> {
+    items: &'a [Item],
+    inner_attrs: Vec<&'a Attribute>,
+    /// Indentation of the scope's items
+    indent: String,
+    /// Offsets just after `{` and at `}`; `None` for the file itself
+    braces: Option<(usize, usize)>,
+}
+
+/// Edits produced while merging, applied together at the end
+#[derive(Default)]
+struct Merge {
+    edits: Vec<Edit>,
+    /// Snippets appended at the end of the file
+    appended: Vec<String>,
+    introduced: Vec<UseLeaf>,
+}
+
+impl Merge {
+    fn finish(mut self, existing: &str) -> Insertion {
+        // Apply back to front so earlier offsets stay valid
+        self.edits
+            .sort_by(|a, b| b.start.cmp(&a.start).then(b.order.cmp(&a.order)));
+        let eol = line_ending(existing);
+        let mut content = existing.to_string();
+        for edit in self.edits {
+            content.replace_range(edit.start..edit.end, &with_line_ending(&edit.text, eol));
+        }
+        if !self.appended.is_empty() {
+            content = append(&content, &self.appended);
+        }
+
+        let unused_imports = imports::unused_imports(&content, &self.introduced);
+        Insertion {
+            content,
+            unused_imports,
+        }
+    }
+}
+
+/// The result of merging a snippet into a file
+#[derive(Debug)]
+pub struct Insertion {
+    pub content: String,
+    /// Imports added by the snippet that nothing in the file refers to
+    pub unused_imports: Vec<String>,
+}
+
+impl Insertion {
+    fn unchanged(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            unused_imports: Vec::new(),
+        }
+    }
+}
+
+pub struct CodeInserter {
+    format_mode: FormatMode,
+}
+
+impl CodeInserter {
+    pub fn new() -> Self {
+        Self {
+            format_mode: FormatMode::Auto,
+        }
+    }
+
+    pub fn with_format_mode(mut self, format_mode: FormatMode) -> Self {
+        self.format_mode = format_mode;
+        self
+    }
+
+    /// Merges `code` into `existing`, returning only the new file content
+    pub fn insert_code(&self, existing: &str, code: &str) -> Result<String> {
+        self.insert(existing, code).map(|insertion| insertion.content)
+    }
+
+    /// Merges `code` into `existing`.
+    ///
+    /// Items that already exist (same kind and name) are replaced in place,
+    /// `use` trees are merged into the existing imports without duplicates,
+    /// `mod` declarations join their existing group, new impls go next to
+    /// their type and everything else is appended. Only the inserted items
+    /// are formatted; the rest of the file keeps its exact layout.
+    pub fn insert(&self, existing: &str, code: &str) -> Result<Insertion> {
+        if code.trim().is_empty() {
+            return Ok(Insertion::unchanged(existing));
+        }
+
+        // Code that does not parse can't be formatted or merged, so it is appended verbatim
+        let incoming = match syn::parse_file(code) {
+            Ok(file) => file,
+            Err(_) => return Ok(Insertion::unchanged(&append(existing, &[code.trim().to_string()]))),
+        };
+        let snippets = self.format_items(code, &incoming);
+
+        let current = match syn::parse_file(existing) {
+            Ok(file) => file,
+            Err(_) => return Ok(Insertion::unchanged(&append(existing, &snippets))),
+        };
+
+        let mut merge = Merge::default();
+        merge_items(existing, &file_scope(&current), &incoming.items, snippets, &mut merge);
+        Ok(merge.finish(existing))
+    }
+
+    /// Merges `code` into a block of `existing` instead of the file itself,
+    /// creating the block when it does not exist yet
+    pub fn insert_into(&self, existing: &str, target: &InsertTarget, code: &str) -> Result<Insertion> {
+        if code.trim().is_empty() {
+            return Ok(Insertion::unchanged(existing));
+        }
+
+        let current = syn::parse_file(existing)
+            .map_err(|e| anyhow!("Cannot locate '{}' because the file does not parse: {}", target, e))?;
+
+        match target {
+            InsertTarget::Module(name) => {
+                let module = current.items.iter().find_map(|item| match item {
+                    Item::Mod(module) if module.ident == name.as_str() && module.content.is_some() => {
+                        Some(module)
+                    }
+                    _ => None,
+                });
+                let Some(module) = module else {
+                    return self.insert(existing, &new_module(name, code));
+                };
+
+                let incoming = syn::parse_file(code)
+                    .map_err(|e| anyhow!("Code for '{}' does not parse: {}", target, e))?;
+                let snippets = self.format_items(code, &incoming);
+                let (brace, items) = module.content.as_ref().expect("inline module");
+                let scope = Scope {
+                    items,
+                    inner_attrs: module
+                        .attrs
+                        .iter()
+                        .filter(|attr| matches!(attr.style, syn::AttrStyle::Inner(_)))
+                        .collect(),
+                    indent: format!("{}    ", line_indent(existing, span_range(existing, module).0)),
+                    braces: Some((
+                        offset_of(existing, brace.span.open().end()),
+                        offset_of(existing, brace.span.close().start()),
+                    )),
+                };
+
+                let mut merge = Merge::default();
+                merge_items(existing, &scope, &incoming.items, snippets, &mut merge);
+                Ok(merge.finish(existing))
+            }
+            InsertTarget::Impl { .. } => {
+                let block = current.items.iter().find_map(|item| match item {
+                    Item::Impl(block) if target.matches_impl(block) => Some(block),
+                    _ => None,
+                });
+                let Some(block) = block else {
+                    return self.insert(existing, &format!("{} {{\n{}\n}}", target, code));
+                };
+
+                // `use` lines can't live inside an impl, so they go to the file's imports
+                let (uses, body) = split_uses(code);
+                let mut merge = Merge::default();
+                if !uses.is_empty() {
+                    merge_items(existing, &file_scope(&current), &uses, vec![String::new(); uses.len()], &mut merge);
+                }
+                self.merge_impl(existing, block, &body, &mut merge)?;
+                Ok(merge.finish(existing))
+            }
+            InsertTarget::Type(name) => {
+                let mut merge = Merge::default();
+                self.merge_type(existing, &current, name, code, &mut merge)?;
+                Ok(merge.finish(existing))
+            }
+        }
+    }
+
+    /// Adds `mod <name>;` among the module declarations of `existing`,
+    /// unless the module is already declared with any visibility
+    pub fn declare_module(&self, existing: &str, name: &str, visibility: &str, sorted: bool) -> Result<String> {
+        let declaration = format!("{}mod {};", visibility, name);
+        let current = match syn::parse_file(existing) {
+            Ok(file) => file,
+            Err(_) => return self.insert_code(existing, &declaration),
+        };
+
+        let mut declarations = current.items.iter().filter_map(|item| match item {
+            Item::Mod(module) => Some(module),
+            _ => None,
+        });
+        if declarations.clone().any(|module| module.ident == name) {
+            return Ok(existing.to_string());
+        }
+
+        let next = declarations.find(|module| module.content.is_none() && module.ident.to_string().as_str() > name);
+        match next {
+            Some(next) if sorted => {
+                let (start, _) = span_range(existing, next);
+                let mut content = existing.to_string();
+                let eol = line_ending(existing);
+                content.insert_str(start, &format!("{}{}{}", declaration, eol, line_indent(existing, start)));
+                Ok(content)
+            }
+            _ => self.insert_code(existing, &declaration),
+        }
+    }
+
+    /// Replaces exactly one item, addressed by a path such as `spawn_npc`,
+    /// `Npc::new`, `systems::tick` or `impl Display for Npc`. Doc comments
+    /// of the old item are kept when the new code brings none of its own.
+    pub fn replace_item(&self, existing: &str, path: &str, code: &str) -> Result<Insertion> {
+        let current = syn::parse_file(existing)
+            .map_err(|e| anyhow!("Cannot locate '{}' because the file does not parse: {}", path, e))?;
+        let located = locate(&current, path)?;
+        let (uses, body) = split_uses(code);
+
+        let mut merge = Merge::default();
+        if !uses.is_empty() {
+            merge_items(existing, &file_scope(&current), &uses, vec![String::new(); uses.len()], &mut merge);
+        }
+
+        let (snippet, has_docs) = match located {
+            Located::Item(_) => {
+                let incoming = syn::parse_file(&body)
+                    .map_err(|e| anyhow!("Replacement for '{}' does not parse: {}", path, e))?;
+                if incoming.items.len() != 1 {
+                    return Err(anyhow!(
+                        "Replacement for '{}' must contain exactly one item, found {}",
+                        path,
+                        incoming.items.len()
+                    ));
+                }
+                let has_docs = item_attrs(&incoming.items[0]).iter().any(is_doc);
+                (self.format_items(&body, &incoming).remove(0), has_docs)
+            }
+            Located::ImplItem(_) => {
+                let wrapper = format!("impl __Vibe {{\n{}\n}}", body);
+                let incoming = syn::parse_str::<ItemImpl>(&wrapper)
+                    .map_err(|e| anyhow!("Replacement for '{}' does not parse: {}", path, e))?;
+                if incoming.items.len() != 1 {
+                    return Err(anyhow!(
+                        "Replacement for '{}' must contain exactly one item, found {}",
+                        path,
+                        incoming.items.len()
+                    ));
+                }
+                let has_docs = impl_item_attrs(&incoming.items[0]).iter().any(is_doc);
+                (self.format_member("impl __Vibe", body.trim(), &Item::Impl(incoming)), has_docs)
+            }
+        };
+
+        let (mut start, end) = located.range(existing);
+        if !has_docs {
+            // Keep the old doc comments, replace from the first token after them
+            if let Some(last_doc) = located.attrs().iter().take_while(|attr| is_doc(attr)).last() {
+                let doc_end = span_range(existing, last_doc).1;
+                start = doc_end + (existing[doc_end..].len() - existing[doc_end..].trim_start().len());
+            }
+        }
+
+        merge.edits.push(Edit {
+            start,
+            end,
+            text: indent_tail(&snippet, &line_indent(existing, start)),
+            order: 0,
+        });
+        Ok(merge.finish(existing))
+    }
+
+    /// Removes exactly one item, addressed like in `replace_item`, together
+    /// with its doc comments and the blank line it leaves behind
+    pub fn delete_item(&self, existing: &str, path: &str) -> Result<String> {
+        let current = syn::parse_file(existing)
+            .map_err(|e| anyhow!("Cannot locate '{}' because the file does not parse: {}", path, e))?;
+        let (start, end) = located_lines(existing, locate(&current, path)?.range(existing));
+
+        let mut content = existing.to_string();
+        remove_lines(&mut content, start, end);
+        Ok(content)
+    }
+
+    fn merge_impl(&self, existing: &str, block: &ItemImpl, body: &str, merge: &mut Merge) -> Result<()> {
+        let wrapper = format!("impl __Vibe {{\n{}\n}}", body);
+        let incoming = syn::parse_str::<ItemImpl>(&wrapper)
+            .map_err(|e| anyhow!("Code for the impl block does not parse: {}", e))?;
+
+        let indent = format!("{}    ", line_indent(existing, span_range(existing, block).0));
+        let mut previous_end = offset_of(&wrapper, incoming.brace_token.span.open().end());
+        let mut appended = Vec::new();
+
+        for item in &incoming.items {
+            let (start, end) = span_range(&wrapper, item);
+            let raw = wrapper[previous_end.min(start)..end].trim();
+            previous_end = end;
+
+            let mut single = incoming.clone();
+            single.items = vec![item.clone()];
+            let snippet = self.format_member("impl __Vibe", raw, &Item::Impl(single));
+
+            let name = impl_item_name(item);
+            let current = block
+                .items
+                .iter()
+                .find(|existing_item| name.is_some() && impl_item_name(existing_item) == name);
+            match current {
+                Some(current) => {
+                    let (start, end) = span_range(existing, current);
+                    merge.edits.push(Edit {
+                        start,
+                        end,
+                        text: indent_tail(&snippet, &indent),
+                        order: 0,
+                    });
+                }
+                None => appended.push(snippet),
+            }
+        }
+
+        if !appended.is_empty() {
+            let last_end = block.items.last().map(|item| span_range(existing, item).1);
+            let open = offset_of(existing, block.brace_token.span.open().end());
+            let close = offset_of(existing, block.brace_token.span.close().start());
+            merge.edits.push(block_append(existing, last_end, (open, close), &appended, &indent, "\n\n", 2));
+        }
+        Ok(())
+    }
+
+    fn merge_type(&self, existing: &str, current: &File, name: &str, code: &str, merge: &mut Merge) -> Result<()> {
+        let item = current
+            .items
+            .iter()
+            .find(|item| match item {
+                Item::Struct(s) => s.ident == name,
+                Item::Enum(e) => e.ident == name,
+                _ => false,
+            })
+            .ok_or_else(|| anyhow!("Struct or enum '{}' not found", name))?;
+
+        let (derives, body) = split_derives(code)?;
+        let (attrs, start) = match item {
+            Item::Struct(s) => (&s.attrs, visibility_or(existing, &s.vis, s.struct_token.span())),
+            Item::Enum(e) => (&e.attrs, visibility_or(existing, &e.vis, e.enum_token.span())),
+            _ => unreachable!(),
+        };
+        let item_indent = line_indent(existing, span_range(existing, item).0);
+
+        if !derives.is_empty() {
+            let current_derive = attrs.iter().find(|attr| attr.path().is_ident("derive"));
+            let mut paths: Vec<String> = match current_derive {
+                Some(attr) => derive_paths(attr)?,
+                None => Vec::new(),
+            };
+            for derive in derives {
+                if !paths.contains(&derive) {
+                    paths.push(derive);
+                }
+            }
+            let rendered = format!("#[derive({})]", paths.join(", "));
+
+            match current_derive {
+                Some(attr) => {
+                    let (start, end) = span_range(existing, attr);
+                    merge.edits.push(Edit {
+                        start,
+                        end,
+                        text: rendered,
+                        order: 0,
+                    });
+                }
+                None => merge.edits.push(Edit {
+                    start,
+                    end: start,
+                    text: format!("{}\n{}", rendered, item_indent),
+                    order: 0,
+                }),
+            }
+        }
+
+        if body.trim().is_empty() {
+            return Ok(());
+        }
+
+        let indent = format!("{}    ", item_indent);
+        match item {
+            Item::Struct(s) => {
+                let Fields::Named(fields) = &s.fields else {
+                    return Err(anyhow!("'{}' has no named fields to add to", name));
+                };
+                let wrapper = format!("struct __Vibe {{\n{}\n}}", body);
+                let incoming = syn::parse_str::<syn::ItemStruct>(&wrapper)
+                    .map_err(|e| anyhow!("Fields for '{}' do not parse: {}", name, e))?;
+                let Fields::Named(new_fields) = &incoming.fields else {
+                    unreachable!()
+                };
+
+                let mut snippets = Vec::new();
+                let mut previous_end = offset_of(&wrapper, new_fields.brace_token.span.open().end());
+                for field in &new_fields.named {
+                    let (start, end) = span_range(&wrapper, field);
+                    let raw = wrapper[previous_end.min(start)..end].trim_start_matches([',', ' ', '\n', '\t', '\r']);
+                    previous_end = end;
+
+                    let mut single = incoming.clone();
+                    single.fields = Fields::Named(syn::FieldsNamed {
+                        brace_token: new_fields.brace_token,
+                        named: std::iter::once(field.clone()).collect(),
+                    });
+                    let snippet = self.format_member("struct __Vibe", raw, &Item::Struct(single));
+                    snippets.push((field.ident.as_ref().map(|i| i.to_string()), snippet));
+                }
+
+                let existing_fields: Vec<_> = fields
+                    .named
+                    .iter()
+                    .map(|f| (f.ident.as_ref().map(|i| i.to_string()), span_range(existing, f)))
+                    .collect();
+                let braces = (
+                    offset_of(existing, fields.brace_token.span.open().end()),
+                    offset_of(existing, fields.brace_token.span.close().start()),
+                );
+                merge_list(existing, &existing_fields, fields.named.trailing_punct(), braces, snippets, &indent, merge);
+            }
+            Item::Enum(e) => {
+                let wrapper = format!("enum __Vibe {{\n{}\n}}", body);
+                let incoming = syn::parse_str::<syn::ItemEnum>(&wrapper)
+                    .map_err(|err| anyhow!("Variants for '{}' do not parse: {}", name, err))?;
+
+                let mut snippets = Vec::new();
+                let mut previous_end = offset_of(&wrapper, incoming.brace_token.span.open().end());
+                for variant in &incoming.variants {
+                    let (start, end) = span_range(&wrapper, variant);
+                    let raw = wrapper[previous_end.min(start)..end].trim_start_matches([',', ' ', '\n', '\t', '\r']);
+                    previous_end = end;
+
+                    let mut single = incoming.clone();
+                    single.variants = std::iter::once(variant.clone()).collect();
+                    let snippet = self.format_member("enum __Vibe", raw, &Item::Enum(single));
+                    snippets.push((Some(variant.ident.to_string()), snippet));
+                }
+
+                let existing_variants: Vec<_> = e
+                    .variants
+                    .iter()
+                    .map(|v| (Some(v.ident.to_string()), span_range(existing, v)))
+                    .collect();
+                let braces = (
+                    offset_of(existing, e.brace_token.span.open().end()),
+                    offset_of(existing, e.brace_token.span.close().start()),
+                );
+                merge_list(existing, &existing_variants, e.variants.trailing_punct(), braces, snippets, &indent, merge);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn resolved_mode(&self) -> FormatMode {
+        match self.format_mode {
+            FormatMode::Auto if rustfmt_available() => FormatMode::Rustfmt,
+            FormatMode::Auto => FormatMode::Prettyplease,
+            mode => mode,
+        }
+    }
+
+    /// Formats every top-level item of `incoming`, returning one snippet per item
+    fn format_items(&self, source: &str, incoming: &File) -> Vec<String> {
+        let mode = self.resolved_mode();
+
+        let mut previous_end = 0;
+        let mut snippets = Vec::new();
+        for item in &incoming.items {
+            let (start, end) = span_range(source, item);
+            // Comments directly above an item belong to it
+            let raw = source[previous_end.min(start)..end].trim();
+            previous_end = end;
+
+            let snippet = match mode {
+                FormatMode::Off => raw.to_string(),
+                FormatMode::Rustfmt => run_rustfmt(raw).unwrap_or_else(|_| unparse_item(item)),
+                _ => unparse_item(item),
+            };
+            snippets.push(snippet);
+        }
+        snippets
+    }
+
+    /// Formats a single member (method, field, variant) by printing it inside
+    /// `wrapper`, a copy of its container holding only that member
+    fn format_member(&self, header: &str, raw: &str, wrapper: &Item) -> String {
+        let printed = match self.resolved_mode() {
+            FormatMode::Off => return raw.trim().to_string(),
+            FormatMode::Rustfmt => run_rustfmt(&format!("{} {{\n{}\n}}", header, raw))
+                .unwrap_or_else(|_| unparse_item(wrapper)),
+            _ => unparse_item(wrapper),
+        };
+
+        let lines: Vec<&str> = printed.lines().collect();
+        let inner = &lines[1.min(lines.len())..lines.len().saturating_sub(1)];
+        inner
+            .iter()
+            .map(|line| line.strip_prefix("    ").unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .trim_end_matches(',')
+            .to_string()
+    }
+}
+
+fn file_scope(file: &File) -> Scope<'_> {
+    Scope {
+        items: &file.items,
+        inner_attrs: file.attrs.iter().collect(),
+        indent: String::new(),
+        braces: None,
+    }
+}
+
+/// Merges top-level `incoming` items (with their formatted `snippets`) into `scope`
+fn merge_items(existing: &str, scope: &Scope, incoming: &[Item], snippets: Vec<String>, merge: &mut Merge) {
+    let incoming_uses: Vec<&ItemUse> = incoming
+        .iter()
+        .filter_map(|item| match item {
+            Item::Use(item) => Some(item),
+            _ => None,
+        })
+        .collect();
+    let import_plan = imports::plan(scope.items, &incoming_uses);
+    merge.introduced.extend(import_plan.introduced);
+
+    for (index, text) in &import_plan.replacements {
+        let (start, end) = span_range(existing, &scope.items[*index]);
+        merge.edits.push(Edit {
+            start,
+            end,
+            text: indent_tail(text, &scope.indent),
+            order: 0,
+        });
+    }
+
+    let mut mods = Vec::new();
+    let mut appended = Vec::new();
+    for (item, snippet) in incoming.iter().zip(snippets) {
+        match item {
+            Item::Use(_) => {}
+            Item::Mod(m) if m.content.is_none() => {
+                let exists = scope
+                    .items
+                    .iter()
+                    .any(|i| matches!(i, Item::Mod(existing) if existing.ident == m.ident));
+                if !exists {
+                    mods.push(snippet);
+                }
+            }
+            _ => match find_matching_item(scope.items, item) {
+                Some(target) => {
+                    let (start, end) = span_range(existing, target);
+                    merge.edits.push(Edit {
+                        start,
+                        end,
+                        text: indent_tail(&snippet, &scope.indent),
+                        order: 0,
+                    });
+                }
+                None => match impl_anchor(scope.items, item) {
+                    // New impls go right after their type or its last impl
+                    Some(anchor) => {
+                        let (_, end) = span_range(existing, anchor);
+                        merge.edits.push(Edit {
+                            start: end,
+                            end,
+                            text: format!("\n\n{}", indent_all(&snippet, &scope.indent)),
+                            order: 3 + merge.edits.len(),
+                        });
+                    }
+                    None => appended.push(snippet),
+                },
+            },
+        }
+    }
+
+    if scope.items.is_empty() {
+        // Nothing to line up with, so everything goes in as one block
+        let mut blocks = Vec::new();
+        if !import_plan.additions.is_empty() {
+            blocks.push(import_plan.additions.join("\n"));
+        }
+        if !mods.is_empty() {
+            blocks.push(mods.join("\n"));
+        }
+        blocks.extend(appended);
+
+        match scope.braces {
+            None => merge.appended.extend(blocks),
+            Some(braces) => merge
+                .edits
+                .push(block_append(existing, None, braces, &blocks, &scope.indent, "\n\n", 2)),
+        }
+        return;
+    }
+
+    if !import_plan.additions.is_empty() {
+        merge.edits.push(group_insertion(
+            existing,
+            scope,
+            &import_plan.additions,
+            |i| matches!(i, Item::Use(_)),
+            0,
+        ));
+    }
+    if !mods.is_empty() {
+        merge.edits.push(group_insertion(
+            existing,
+            scope,
+            &mods,
+            |i| matches!(i, Item::Mod(m) if m.content.is_none()),
+            1,
+        ));
+    }
+    if !appended.is_empty() {
+        match scope.braces {
+            None => merge.appended.extend(appended),
+            Some(braces) => {
+                let last_end = scope.items.last().map(|item| span_range(existing, item).1);
+                merge
+                    .edits
+                    .push(block_append(existing, last_end, braces, &appended, &scope.indent, "\n\n", 2));
+            }
+        }
+    }
+}
+
+/// Merges comma-separated members (fields or variants), replacing those
+/// with the same name and appending the rest after the last member
+fn merge_list(
+    existing: &str,
+    current: &[(Option<String>, (usize, usize))],
+    trailing_comma: bool,
+    braces: (usize, usize),
+    snippets: Vec<(Option<String>, String)>,
+    indent: &str,
+    merge: &mut Merge,
+) {
+    let mut appended = Vec::new();
+    for (name, snippet) in snippets {
+        match current.iter().find(|(existing_name, _)| name.is_some() && *existing_name == name) {
+            Some((_, (start, end))) => merge.edits.push(Edit {
+                start: *start,
+                end: *end,
+                text: indent_tail(&snippet, indent),
+                order: 0,
+            }),
+            None => appended.push(format!("{},", snippet)),
+        }
+    }
+    if appended.is_empty() {
+        return;
+    }
+
+    match current.last() {
+        Some((_, (_, last_end))) => {
+            // Step over the existing trailing comma, or add one
+            let (offset, prefix) = if trailing_comma {
+                let comma = existing[*last_end..].find(',').map(|i| last_end + i + 1);
+                (comma.unwrap_or(*last_end), "")
+            } else {
+                (*last_end, ",")
+            };
+            let text: String = appended
+                .iter()
+                .map(|snippet| format!("\n{}", indent_all(snippet, indent)))
+                .collect();
+            merge.edits.push(Edit {
+                start: offset,
+                end: offset,
+                text: format!("{}{}", prefix, text),
+                order: 2,
+            });
+        }
+        None => merge
+            .edits
+            .push(block_append(existing, None, braces, &appended, indent, "\n", 2)),
+    }
+}
+
+/// Builds the edit that adds `blocks` at the end of a braced block, after
+/// `last_end` when the block has members or filling it when it is empty
+fn block_append(
+    existing: &str,
+    last_end: Option<usize>,
+    (open, close): (usize, usize),
+    blocks: &[String],
+    indent: &str,
+    separator: &str,
+    order: usize,
+) -> Edit {
+    let body = blocks
+        .iter()
+        .map(|block| indent_all(block, indent))
+        .collect::<Vec<_>>()
+        .join(separator);
+
+    if let Some(end) = last_end {
+        return Edit {
+            start: end,
+            end,
+            text: format!("{}{}", separator, body),
+            order,
+        };
+    }
+
+    // Keep comments already inside the block, drop trailing blank space
+    let kept = existing[open..close].trim_end().len();
+    let outer_indent = indent.strip_suffix("    ").unwrap_or("");
+    Edit {
+        start: open + kept,
+        end: close,
+        text: format!(
+            "{}\n{}\n{}",
+            if kept > 0 { "\n" } else { "" },
+            body,
+            outer_indent
+        ),
+        order,
+    }
+}
+
+/// Builds the edit that adds `snippets` after the last item matching
+/// `in_group`, or at the top of the scope when the group is empty
+fn group_insertion(
+    existing: &str,
+    scope: &Scope,
+    snippets: &[String],
+    in_group: impl Fn(&Item) -> bool,
+    order: usize,
+) -> Edit {
+    let block = indent_all(&snippets.join("\n"), &scope.indent);
+
+    if let Some(last) = scope.items.iter().rev().find(|i| in_group(i)) {
+        let (_, end) = span_range(existing, last);
+        return Edit {
+            start: end,
+            end,
+            text: format!("\n{}", block),
+            order,
+        };
+    }
+
+    // Keep inner attributes and `//!` docs at the very top
+    let top = scope
+        .inner_attrs
+        .iter()
+        .map(|attr| offset_of(existing, attr.span().end()))
+        .max();
+    match (top, scope.braces) {
+        (Some(offset), _) => Edit {
+            start: offset,
+            end: offset,
+            text: format!("\n\n{}", block),
+            order,
+        },
+        (None, Some((open, _))) => Edit {
+            start: open,
+            end: open,
+            text: format!("\n{}\n", block),
+            order,
+        },
+        (None, None) => Edit {
+            start: 0,
+            end: 0,
+            text: format!("{}\n\n", block),
+            order,
+        },
+    }
+}
+
+/// An item found by `locate`
+enum Located<'a> {
+    Item(&'a Item),
+    ImplItem(&'a ImplItem),
+}
+
+impl Located<'_> {
+    fn range(&self, source: &str) -> (usize, usize) {
+        match self {
+            Located::Item(item) => span_range(source, *item),
+            Located::ImplItem(item) => span_range(source, *item),
+        }
+    }
+
+    fn attrs(&self) -> &[Attribute] {
+        match self {
+            Located::Item(item) => item_attrs(item),
+            Located::ImplItem(item) => impl_item_attrs(item),
+        }
+    }
+}
+
+/// Resolves `name`, `module::name`, `Type::method` or `impl [Trait for] Type`
+fn locate<'a>(file: &'a File, path: &str) -> Result<Located<'a>> {
+    let path = path.trim();
+    if path.starts_with("impl ") {
+        let target = InsertTarget::parse(path)?;
+        return file
+            .items
+            .iter()
+            .find(|item| matches!(item, Item::Impl(block) if target.matches_impl(block)))
+            .map(Located::Item)
+            .ok_or_else(|| anyhow!("'{}' not found", path));
+    }
+
+    let segments: Vec<&str> = path.split("::").map(str::trim).collect();
+    let mut items = &file.items;
+    for (index, segment) in segments.iter().enumerate() {
+        if index == segments.len() - 1 {
+            return items
+                .iter()
+                .find(|item| item_name(item).as_deref() == Some(*segment))
+                .map(Located::Item)
+                .ok_or_else(|| anyhow!("'{}' not found", path));
+        }
+
+        let module = items.iter().find_map(|item| match item {
+            Item::Mod(module) if module.ident == segment => module.content.as_ref(),
+            _ => None,
+        });
+        if let Some((_, module_items)) = module {
+            items = module_items;
+            continue;
+        }
+
+        if index == segments.len() - 2 {
+            let member = segments[index + 1];
+            return items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Impl(block) if type_name(&block.self_ty).as_deref() == Some(*segment) => Some(block),
+                    _ => None,
+                })
+                .flat_map(|block| block.items.iter())
+                .find(|item| impl_item_name(item).as_deref() == Some(member))
+                .map(Located::ImplItem)
+                .ok_or_else(|| anyhow!("'{}' not found", path));
+        }
+        return Err(anyhow!("Module '{}' not found in '{}'", segment, path));
+    }
+    Err(anyhow!("Empty item path"))
+}
+
+/// Widens an item range to whole lines when nothing else shares them
+pub(crate) fn located_lines(source: &str, (start, end): (usize, usize)) -> (usize, usize) {
+    let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let start = if source[line_start..start].trim().is_empty() {
+        line_start
+    } else {
+        start
+    };
+
+    let rest = &source[end..];
+    let line_end = rest.find('\n').map(|i| end + i + 1).unwrap_or(source.len());
+    let end = if source[end..line_end].trim().is_empty() {
+        line_end
+    } else {
+        end
+    };
+    (start, end)
+}
+
+/// Removes `start..end` and collapses the double blank line it leaves
+/// between the neighbours
+pub(crate) fn remove_lines(content: &mut String, start: usize, end: usize) {
+    content.replace_range(start..end, "");
+    let before = &content[..start];
+    if (before.is_empty() || before.ends_with("\n\n")) && content[start..].starts_with('\n') {
+        content.remove(start);
+    }
+}
+
+fn item_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Impl(_) => None,
+        item => item_key(item).and_then(|key| key.split_once(' ').map(|(_, name)| name.to_string())),
+    }
+}
+
+fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(i) => &i.attrs,
+        Item::Enum(i) => &i.attrs,
+        Item::Fn(i) => &i.attrs,
+        Item::Impl(i) => &i.attrs,
+        Item::Macro(i) => &i.attrs,
+        Item::Mod(i) => &i.attrs,
+        Item::Static(i) => &i.attrs,
+        Item::Struct(i) => &i.attrs,
+        Item::Trait(i) => &i.attrs,
+        Item::Type(i) => &i.attrs,
+        Item::Union(i) => &i.attrs,
+        Item::Use(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+fn impl_item_attrs(item: &ImplItem) -> &[Attribute] {
+    match item {
+        ImplItem::Const(i) => &i.attrs,
+        ImplItem::Fn(i) => &i.attrs,
+        ImplItem::Type(i) => &i.attrs,
+        ImplItem::Macro(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+fn is_doc(attr: &Attribute) -> bool {
+    attr.path().is_ident("doc")
+}
+
+/// Returns the item in `items` that `item` would replace, if any
+fn find_matching_item<'a>(items: &'a [Item], item: &Item) -> Option<&'a Item> {
+    let key = item_key(item)?;
+    items
+        .iter()
+        .find(|existing| item_key(existing).as_deref() == Some(key.as_str()))
+}
+
+/// For a new impl, the last impl of the same type or else the type itself
+fn impl_anchor<'a>(items: &'a [Item], item: &Item) -> Option<&'a Item> {
+    let Item::Impl(block) = item else {
+        return None;
+    };
+    let name = type_name(&block.self_ty)?;
+
+    items
+        .iter()
+        .rev()
+        .find(|i| matches!(i, Item::Impl(existing) if type_name(&existing.self_ty).as_deref() == Some(name.as_str())))
+        .or_else(|| {
+            items.iter().find(|i| match i {
+                Item::Struct(s) => s.ident == name,
+                Item::Enum(e) => e.ident == name,
+                Item::Union(u) => u.ident == name,
+                Item::Type(t) => t.ident == name,
+                _ => false,
+            })
+        })
+}
+
+/// Identifies an item by kind and name. Inherent impls have no key since
+/// several `impl Type` blocks may legitimately coexist.
+pub(crate) fn item_key(item: &Item) -> Option<String> {
+    match item {
+        Item::Fn(i) => Some(format!("fn {}", i.sig.ident)),
+        Item::Struct(i) => Some(format!("struct {}", i.ident)),
+        Item::Enum(i) => Some(format!("enum {}", i.ident)),
+        Item::Union(i) => Some(format!("union {}", i.ident)),
+        Item::Trait(i) => Some(format!("trait {}", i.ident)),
+        Item::Type(i) => Some(format!("type {}", i.ident)),
+        Item::Const(i) => Some(format!("const {}", i.ident)),
+        Item::Static(i) => Some(format!("static {}", i.ident)),
+        Item::Mod(i) => Some(format!("mod {}", i.ident)),
+        Item::Macro(i) => i.ident.as_ref().map(|ident| format!("macro {}", ident)),
+        Item::Impl(i) => i.trait_.as_ref().map(|(_, path, _)| {
+            let self_ty = &i.self_ty;
+            format!(
+                "impl {} for {}",
+                quote::quote!(#path),
+                quote::quote!(#self_ty)
+            )
+        }),
+        _ => None,
+    }
+}
+
+fn impl_item_name(item: &ImplItem) -> Option<String> {
+    match item {
+        ImplItem::Fn(f) => Some(f.sig.ident.to_string()),
+        ImplItem::Const(c) => Some(c.ident.to_string()),
+        ImplItem::Type(t) => Some(t.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// The last path segment of a type, e.g. `Npc` for `crate::npc::Npc<T>`
+pub(crate) fn type_name(ty: &syn::Type) -> Option<String> {
+    match ty {
+        syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+        syn::Type::Reference(reference) => type_name(&reference.elem),
+        _ => None,
+    }
+}
+
+fn last_segment(path: &Path) -> Option<String> {
+    path.segments.last().map(|segment| quote::quote!(#segment).to_string())
+}
+
+/// Byte range of a syntax node in `source`, including attributes and doc comments
+pub(crate) fn span_range<T: Spanned>(source: &str, node: &T) -> (usize, usize) {
+    let span = node.span();
+    (
+        offset_of(source, span.start()),
+        offset_of(source, span.end()),
+    )
+}
+
+/// Converts a span location (1-based line, 0-based char column) into a byte offset
+pub(crate) fn offset_of(source: &str, location: LineColumn) -> usize {
+    let mut offset = 0;
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        if index + 1 == location.line {
+            return offset
+                + line
+                    .char_indices()
+                    .nth(location.column)
+                    .map(|(i, _)| i)
+                    .unwrap_or(line.len());
+        }
+        offset += line.len();
+    }
+    source.len()
+}
+
+/// Leading whitespace of the line containing `offset`
+fn line_indent(source: &str, offset: usize) -> String {
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    source[line_start..]
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect()
+}
+
+fn visibility_or(source: &str, vis: &syn::Visibility, keyword: proc_macro2::Span) -> usize {
+    match vis {
+        syn::Visibility::Inherited => offset_of(source, keyword.start()),
+        vis => span_range(source, vis).0,
+    }
+}
+
+/// Prefixes every non-empty line with `indent`
+fn indent_all(text: &str, indent: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indent, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Like `indent_all`, for text placed where the first line is already indented
+fn indent_tail(text: &str, indent: &str) -> String {
+    let indented = indent_all(text, indent);
+    indented.strip_prefix(indent).unwrap_or(&indented).to_string()
+}
+
+/// Wraps code in a new inline module; `mod tests` gets the usual test boilerplate
+fn new_module(name: &str, code: &str) -> String {
+    if name == "tests" {
+        let prelude = if code.contains("use super::*") {
+            ""
+        } else {
+            "use super::*;\n\n"
+        };
+        format!("#[cfg(test)]\nmod tests {{\n{}{}\n}}", prelude, code)
+    } else {
+        format!("mod {} {{\n{}\n}}", name, code)
+    }
+}
+
+/// Separates leading `use` items from the rest of an impl body
+fn split_uses(code: &str) -> (Vec<Item>, String) {
+    let Ok(file) = syn::parse_file(code) else {
+        return (Vec::new(), code.to_string());
+    };
+
+    let mut uses = Vec::new();
+    let mut body = code.to_string();
+    for item in file.items.iter().rev() {
+        if let Item::Use(_) = item {
+            let (start, end) = span_range(code, item);
+            body.replace_range(start..end, "");
+            uses.insert(0, item.clone());
+        }
+    }
+    (uses, body)
+}
+
+/// Separates `#[derive(..)]` lines from field or variant definitions
+fn split_derives(code: &str) -> Result<(Vec<String>, String)> {
+    let mut derives = Vec::new();
+    let mut body = Vec::new();
+
+    for line in code.lines() {
+        if line.trim_start().starts_with("#[derive(") {
+            let parsed = syn::parse_str::<syn::DeriveInput>(&format!("{} struct __Vibe;", line.trim()))
+                .map_err(|e| anyhow!("Invalid derive attribute '{}': {}", line.trim(), e))?;
+            for attr in &parsed.attrs {
+                derives.extend(derive_paths(attr)?);
+            }
+        } else {
+            body.push(line);
+        }
+    }
+    Ok((derives, body.join("\n")))
+}
+
+fn derive_paths(attr: &Attribute) -> Result<Vec<String>> {
+    let paths = attr
+        .parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated)
+        .map_err(|e| anyhow!("Invalid derive attribute: {}", e))?;
+    Ok(paths
+        .iter()
+        .map(|path| quote::quote!(#path).to_string().replace(' ', ""))
+        .collect())
+}
+
+/// Appends snippets at the end of `existing`, separated by blank lines
+fn append(existing: &str, snippets: &[String]) -> String {
+    let eol = line_ending(existing);
+    let mut result = existing.trim_end().to_string();
+    for snippet in snippets {
+        if !result.is_empty() {
+            result.push_str(eol);
+            result.push_str(eol);
+        }
+        result.push_str(&with_line_ending(snippet, eol));
+    }
+    result.push_str(eol);
+    result
+}
+
+/// `\r\n` for files written with it, `\n` otherwise
+fn line_ending(existing: &str) -> &'static str {
+    if existing.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// `text` with its lines ended by `eol`
+fn with_line_ending(text: &str, eol: &str) -> String {
+    if eol == "\n" {
+        return text.to_string();
+    }
+    text.replace("\r\n", "\n").replace('\n', eol)
+}
+
+fn unparse_item(item: &Item) -> String {
+    let file = File {
+        shebang: None,
+        attrs: Vec::new(),
+        items: vec![item.clone()],
+    };
+    prettyplease::unparse(&file).trim_end().to_string()
+}
+
+/// Whether rustfmt is on PATH, checked once per run
+fn rustfmt_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        ProcessCommand::new("rustfmt")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    })
+}
+
+fn run_rustfmt(code: &str) -> Result<String> {
+    let mut child = ProcessCommand::new("rustfmt")
+        .args(["--edition", "2021", "--emit", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("Failed to open rustfmt stdin"))?
+        .write_all(code.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!("rustfmt failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inserter() -> CodeInserter {
+        CodeInserter::new().with_format_mode(FormatMode::Prettyplease)
+    }
+
+    #[test]
+    fn test_formats_inserted_code() {
+        let result = inserter()
+            .insert_code("", "fn add(a: i32,b:i32)->i32{\na+b}")
+            .unwrap();
+        assert_eq!(result, "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n");
+    }
+
+    #[test]
+    fn test_untouched_code_keeps_layout() {
+        let existing = "fn keep( ) {\n  let x=1;\n}\n\nfn old() {}\n";
+        let result = inserter()
+            .insert_code(existing, "fn old(){println!(\"new\");}")
+            .unwrap();
+        assert!(result.starts_with("fn keep( ) {\n  let x=1;\n}\n\n"));
+        assert!(result.contains("fn old() {\n    println!(\"new\");\n}"));
+        assert_eq!(result.matches("fn old").count(), 1);
+    }
+
+    #[test]
+    fn test_mod_declaration_joins_group() {
+        let existing = "mod app;\nmod command;\n\nfn main() {}\n";
+        let result = inserter().insert_code(existing, "mod npc;").unwrap();
+        assert_eq!(result, "mod app;\nmod command;\nmod npc;\n\nfn main() {}\n");
+    }
+
+    #[test]
+    fn test_module_declaration_is_sorted() {
+        let existing = "pub mod app;\nmod parser;\n\nfn main() {}\n";
+        let inserter = inserter();
+        let result = inserter.declare_module(existing, "npc", "pub ", true).unwrap();
+        assert_eq!(result, "pub mod app;\npub mod npc;\nmod parser;\n\nfn main() {}\n");
+        assert_eq!(inserter.declare_module(existing, "app", "", true).unwrap(), existing);
+    }
+
+    #[test]
+    fn test_imports_are_merged() {
+        let existing = "use std::collections::{HashMap, HashSet};\n\nfn main() {}\n";
+        let code = "use std::collections::HashMap;\nuse std::fmt;\n\nfn show(f: &mut fmt::Formatter) {}";
+        let insertion = inserter().insert(existing, code).unwrap();
+        assert!(insertion
+            .content
+            .starts_with("use std::collections::{HashMap, HashSet};\nuse std::fmt;\n\nfn main() {}"));
+        assert!(insertion.unused_imports.is_empty());
+    }
+
+    #[test]
+    fn test_method_lands_in_existing_impl() {
+        let existing = "struct Npc;\n\nimpl Npc {\n    fn a(&self) {}\n}\n\nfn main() {}\n";
+        let target = InsertTarget::parse("impl Npc").unwrap();
+        let result = inserter()
+            .insert_into(existing, &target, "fn b(&self)->u8{1}")
+            .unwrap();
+        assert_eq!(
+            result.content,
+            "struct Npc;\n\nimpl Npc {\n    fn a(&self) {}\n\n    fn b(&self) -> u8 {\n        1\n    }\n}\n\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_tests_module_is_created() {
+        let target = InsertTarget::parse("mod tests").unwrap();
+        let result = inserter()
+            .insert_into("fn f() {}\n", &target, "#[test]\nfn t() {}")
+            .unwrap();
+        assert_eq!(
+            result.content,
+            "fn f() {}\n\n#[cfg(test)]\nmod tests {\n    use super::*;\n    #[test]\n    fn t() {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_fields_and_derives_land_in_struct() {
+        let existing = "pub struct Npc {\n    name: String,\n}\n";
+        let target = InsertTarget::parse("Npc").unwrap();
+        let result = inserter()
+            .insert_into(existing, &target, "#[derive(Debug, Clone)]\nhealth: u32")
+            .unwrap();
+        assert_eq!(
+            result.content,
+            "#[derive(Debug, Clone)]\npub struct Npc {\n    name: String,\n    health: u32,\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_replace_method_keeps_docs_and_neighbours() {
+        let existing = "impl Npc {\n    // helper\n    /// Creates one\n    fn new() -> Self { Npc }\n\n    fn other() {}\n}\n";
+        let result = inserter()
+            .replace_item(existing, "Npc::new", "fn new()->Self{Npc::default()}")
+            .unwrap();
+        assert_eq!(
+            result.content,
+            "impl Npc {\n    // helper\n    /// Creates one\n    fn new() -> Self {\n        Npc::default()\n    }\n\n    fn other() {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_crlf_files_stay_crlf() {
+        let existing = "impl Npc {\r\n    fn a(&self) {}\r\n}\r\n";
+        let target = InsertTarget::parse("impl Npc").unwrap();
+        let result = inserter()
+            .insert_into(existing, &target, "fn b(&self)->u8{1}")
+            .unwrap();
+        assert_eq!(
+            result.content,
+            "impl Npc {\r\n    fn a(&self) {}\r\n\r\n    fn b(&self) -> u8 {\r\n        1\r\n    }\r\n}\r\n"
+        );
+
+        let result = inserter().insert_code(existing, "fn f(){}").unwrap();
+        assert_eq!(result, format!("{}\r\nfn f() {{}}\r\n", existing));
+    }
+
+    #[test]
+    fn test_delete_item() {
+        let existing = "fn a() {}\n\n/// Doc\nfn b() {}\n\nfn c() {}\n";
+        let result = inserter().delete_item(existing, "b").unwrap();
+        assert_eq!(result, "fn a() {}\n\nfn c() {}\n");
+        assert!(inserter().delete_item(existing, "missing").is_err());
+    }
+
+    #[test]
+    fn test_offset_of_counts_chars() {
+        let source = "// é\nfn a() {}";
+        let location = LineColumn { line: 2, column: 3 };
+        assert_eq!(&source[offset_of(source, location)..], "a() {}");
+    }
+}
@@ -2,11 +2,80 @@ use anyhow::{anyhow, Result};
 use ignore::WalkBuilder;
 use std::fs;
 use std::path::{Path, PathBuf};
-use crate::patch::CodeInserter;
+use crate::build_history::BuildHistory;
+use crate::config::ProjectConfig;
+use crate::diagnostics::{Diagnostic, Span, Suggestion};
+use crate::fix_prompt::{self, FixPrompt, PromptOptions};
+use crate::generate::{self, Generated, Generator, TestStubs, TraitSource};
+use crate::manifest::Manifest;
+use crate::merge;
+use crate::parser::RustParser;
+use crate::patch::{CodeInserter, InsertTarget, Insertion};
+use crate::refactor::{self, RefactorPlan};
+use crate::scaffold::{self, Scaffold};
+use crate::validate;
+use crate::workspace::{Scope, Workspace};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+
+/// What `Project::move_item` touched besides the two files
+pub struct MoveReport {
+    /// Other files whose paths were rewritten
+    pub updated: Vec<String>,
+    /// Imports the source file no longer needs
+    pub stale_imports: Vec<String>,
+    /// Imports brought into the target that look unused there
+    pub unused_imports: Vec<String>,
+}
+
+/// A file as the tool last showed or wrote it
+struct Snapshot {
+    hash: u64,
+    modified: Option<SystemTime>,
+    content: String,
+}
+
+/// A change that was not written because its file was edited elsewhere,
+/// merged three ways with the on-disk version
+pub struct PendingMerge {
+    pub file: String,
+    path: PathBuf,
+    on_disk: String,
+    pub merged: String,
+    /// Regions both sides changed, marked with `<<<<<<<` in `merged`
+    pub conflicts: usize,
+}
+
+/// What `Project::apply_suggestions` did
+pub struct FixReport {
+    /// Indices of the suggestions that went in, into the ones given
+    pub applied: Vec<usize>,
+    pub files: Vec<String>,
+    /// Why some suggestions were left out
+    pub skipped: Vec<String>,
+}
+
+/// Files as they were before and after one `fix`, for `undo`
+struct UndoStep {
+    description: String,
+    files: Vec<(PathBuf, String, String)>,
+}
 
 pub struct Project {
     pub root_path: PathBuf,
+    pub config: ProjectConfig,
+    /// Packages and targets from `cargo metadata`; `None` outside cargo
+    /// projects or when cargo could not read the manifest
+    pub workspace: Option<Workspace>,
+    /// Logged build, check, test and profile runs
+    pub builds: BuildHistory,
+    scope: Scope,
     rust_files: Vec<PathBuf>,
+    snapshots: HashMap<PathBuf, Snapshot>,
+    pending_merge: Option<PendingMerge>,
+    undo_stack: Vec<UndoStep>,
 }
 
 impl Project {
@@ -16,14 +85,101 @@ impl Project {
         }
 
         let mut project = Self {
+            config: ProjectConfig::load(&root_path)?,
+            workspace: None,
+            builds: BuildHistory::load(&root_path),
+            scope: Scope::default(),
             root_path: root_path.clone(),
             rust_files: Vec::new(),
+            snapshots: HashMap::new(),
+            pending_merge: None,
+            undo_stack: Vec::new(),
         };
 
+        project.load_workspace();
         project.scan_rust_files()?;
         Ok(project)
     }
 
+    /// Re-reads the workspace model, dropping a scope that no longer exists
+    pub fn load_workspace(&mut self) {
+        self.workspace = if self.root_path.join("Cargo.toml").exists() {
+            Workspace::load(&self.root_path).ok()
+        } else {
+            None
+        };
+        let package_exists = match (&self.scope.package, &self.workspace) {
+            (Some(package), Some(workspace)) => workspace.package(package).is_some(),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        if !package_exists {
+            self.scope = Scope::default();
+        }
+    }
+
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    /// Limits build, run, test, search and listing to `package` and,
+    /// within it, the target `target` (`bin:server`, `lib`, or a name).
+    /// No package means the whole workspace.
+    pub fn set_scope(&mut self, package: Option<&str>, target: Option<&str>) -> Result<()> {
+        let Some(package) = package else {
+            self.scope = Scope::default();
+            return Ok(());
+        };
+        let workspace = self
+            .workspace
+            .as_ref()
+            .ok_or_else(|| anyhow!("No cargo workspace loaded"))?;
+        let found = workspace.package(package).ok_or_else(|| {
+            anyhow!(
+                "No package '{}' in the workspace (packages: {})",
+                package,
+                workspace
+                    .packages
+                    .iter()
+                    .map(|package| package.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+        let target = target.map(|target| found.find_target(target)).transpose()?.cloned();
+        self.scope = Scope {
+            package: Some(found.name.clone()),
+            target,
+        };
+        Ok(())
+    }
+
+    /// Rust files of the scoped package or, with a scoped target, of that
+    /// target's module tree; all of them without a scope
+    fn scoped_files(&self) -> Vec<&PathBuf> {
+        let tree = self
+            .scope
+            .target
+            .as_ref()
+            .map(|target| module_tree(&self.root_path.join(&target.src_path)));
+        self.rust_files
+            .iter()
+            .filter(|file| self.in_package_scope(file))
+            .filter(|file| tree.as_ref().is_none_or(|tree| tree.contains(*file)))
+            .collect()
+    }
+
+    /// Whether `file` belongs to the scoped package
+    fn in_package_scope(&self, file: &Path) -> bool {
+        let (Some(package), Some(workspace)) = (&self.scope.package, &self.workspace) else {
+            return true;
+        };
+        let relative = file.strip_prefix(&self.root_path).unwrap_or(file);
+        workspace
+            .package_of(relative)
+            .is_some_and(|owner| &owner.name == package)
+    }
+
     fn scan_rust_files(&mut self) -> Result<()> {
         self.rust_files.clear();
 
@@ -46,9 +202,10 @@ impl Project {
     pub fn search(&self, query: &str) -> Result<Vec<String>> {
         let mut results = Vec::new();
         let query_lower = query.to_lowercase();
+        let files = self.scoped_files();
 
         // Search in file names
-        for file in &self.rust_files {
+        for file in &files {
             if let Some(file_name) = file.file_name().and_then(|s| s.to_str()) {
                 if file_name.to_lowercase().contains(&query_lower) {
                     results.push(format!("File: {}", self.relative_path(file)?));
@@ -57,7 +214,7 @@ impl Project {
         }
 
         // Search in file contents
-        for file in &self.rust_files {
+        for file in &files {
             if let Ok(content) = fs::read_to_string(file) {
                 for (line_num, line) in content.lines().enumerate() {
                     if line.to_lowercase().contains(&query_lower) {
@@ -75,7 +232,15 @@ impl Project {
         Ok(results)
     }
 
-    pub fn add_code(&mut self, file_path: &str, code: &str) -> Result<()> {
+    /// Merges `code` into `file_path`. Unless `force` is set, nothing is
+    /// written when the result would not parse.
+    pub fn add_code(
+        &mut self,
+        file_path: &str,
+        target: Option<&InsertTarget>,
+        code: &str,
+        force: bool,
+    ) -> Result<Insertion> {
         let full_path = self.root_path.join(file_path);
 
         // Create parent directories if they don't exist
@@ -84,75 +249,711 @@ impl Project {
         }
 
         // Use CodeInserter for intelligent code insertion
-        let inserter = CodeInserter::new();
+        let inserter = self.inserter();
         let mut existing_content = String::new();
         let file_existed = full_path.exists();
 
         if file_existed {
-            existing_content = fs::read_to_string(&full_path)?;
+            existing_content = self.edit_base(&full_path)?;
         }
 
-        let new_content = inserter.insert_code(&existing_content, code)?;
-        fs::write(&full_path, new_content)?;
+        let insertion = match target {
+            Some(target) => inserter.insert_into(&existing_content, target, code)?,
+            None => inserter.insert(&existing_content, code)?,
+        };
+        if !force {
+            validate::check_merge(&existing_content, &insertion.content, code, target)?;
+        }
+        self.write_checked(&full_path, &insertion.content)?;
 
-        // If this is a new module file, try to add module declaration to main.rs or lib.rs
-        if !file_existed && file_path.starts_with("src/") && file_path.ends_with(".rs") {
+        // A new module file needs a `mod` declaration in its parent module
+        if !file_existed {
             self.add_module_declaration(file_path)?;
         }
 
         // Rescan files
         self.scan_rust_files()?;
 
+        Ok(insertion)
+    }
+
+    /// Replaces one item of an existing file, see `CodeInserter::replace_item`
+    pub fn replace_item(&mut self, file_path: &str, item_path: &str, code: &str, force: bool) -> Result<Insertion> {
+        let full_path = self.existing_file(file_path)?;
+        let content = self.edit_base(&full_path)?;
+        let insertion = self.inserter().replace_item(&content, item_path, code)?;
+        if !force {
+            validate::check_merge(&content, &insertion.content, code, None)?;
+        }
+        self.write_checked(&full_path, &insertion.content)?;
+        Ok(insertion)
+    }
+
+    /// Deletes one item of an existing file, see `CodeInserter::delete_item`
+    pub fn delete_item(&mut self, file_path: &str, item_path: &str) -> Result<()> {
+        let full_path = self.existing_file(file_path)?;
+        let content = self.edit_base(&full_path)?;
+        let updated = self.inserter().delete_item(&content, item_path)?;
+        self.write_checked(&full_path, &updated)?;
         Ok(())
     }
 
+    /// Plans a project-wide rename of `<file>` (the module itself),
+    /// `<file>::<item>` or `<file>::<Type>::<member>`
+    pub fn plan_rename(&self, path: &str, new_name: &str) -> Result<RefactorPlan> {
+        let (file_path, item_path) = match path.split_once("::") {
+            Some((file_path, item_path)) => (file_path.trim(), Some(item_path)),
+            None => (path.trim(), None),
+        };
+        let full_path = self.existing_file(file_path)?;
+        refactor::plan_rename(&self.root_path, &self.rust_files, &full_path, item_path, new_name)
+    }
+
+    /// Writes a previewed plan, refusing if any file changed since the preview
+    pub fn apply_refactor(&mut self, plan: &RefactorPlan) -> Result<()> {
+        for file in &plan.files {
+            if fs::read_to_string(&file.path)? != file.original {
+                return Err(anyhow!(
+                    "{} changed since the preview, plan the change again",
+                    self.relative_path(&file.path)?
+                ));
+            }
+        }
+
+        for file in &plan.files {
+            self.write_file(&file.path, &file.apply())?;
+        }
+        for (from, to) in &plan.moves {
+            fs::rename(from, to)?;
+        }
+
+        self.scan_rust_files()?;
+        Ok(())
+    }
+
+    /// Moves `item` and its impls from `file_path` into `target_path`,
+    /// creating and declaring the target module when needed and pointing
+    /// every `use` and qualified path in the project at the new location
+    pub fn move_item(&mut self, file_path: &str, item: &str, target_path: &str) -> Result<MoveReport> {
+        let source_file = self.existing_file(file_path)?;
+        let target_file = self.root_path.join(target_path);
+        if source_file == target_file {
+            return Err(anyhow!("'{}' is already in {}", item, target_path));
+        }
+        let source = refactor::module_path(&self.root_path, &source_file)
+            .ok_or_else(|| anyhow!("{} is not part of the src/ module tree", file_path))?;
+        let target = refactor::module_path(&self.root_path, &target_file)
+            .filter(|target| !target.is_empty())
+            .ok_or_else(|| anyhow!("{} is not a module under src/", target_path))?;
+        for file in [&source_file, &target_file] {
+            if self.external_change(file)?.is_some() {
+                return Err(anyhow!(
+                    "{} changed on disk since it was last shown, 'show' it again before moving",
+                    self.relative_path(file)?
+                ));
+            }
+        }
+
+        let mut used_elsewhere = HashSet::new();
+        let mut others = Vec::new();
+        for file in &self.rust_files {
+            if *file == source_file {
+                continue;
+            }
+            let content = fs::read_to_string(file)?;
+            if *file != target_file {
+                used_elsewhere.extend(refactor::identifiers(&content));
+            }
+            others.push((file.clone(), content));
+        }
+
+        let content = fs::read_to_string(&source_file)?;
+        let cut = refactor::cut_item(&content, item, &source, &target, &used_elsewhere)?;
+
+        // Every new content is worked out and checked before anything is
+        // written, so a failure leaves the item where it was
+        let mut rewrites = Vec::new();
+        for (file, content) in others {
+            let Some(module) = refactor::module_path(&self.root_path, &file) else {
+                continue;
+            };
+            if let Some(rewritten) = refactor::retarget_paths(&content, &module, item, &source, &target)? {
+                if self.external_change(&file)?.is_some() {
+                    return Err(anyhow!(
+                        "{} changed on disk since it was last shown, 'show' it again before moving",
+                        self.relative_path(&file)?
+                    ));
+                }
+                rewrites.push((file, rewritten));
+            }
+        }
+
+        let target_existed = target_file.exists();
+        let target_rewrite = rewrites.iter().position(|(file, _)| *file == target_file);
+        let target_content = match target_rewrite {
+            Some(index) => rewrites.remove(index).1,
+            None if target_existed => fs::read_to_string(&target_file)?,
+            None => String::new(),
+        };
+        let insertion = self.inserter().insert(&target_content, &cut.code)?;
+        validate::check_merge(&target_content, &insertion.content, &cut.code, None)?;
+
+        let mut updated = Vec::new();
+        for (file, rewritten) in &rewrites {
+            self.write_checked(file, rewritten)?;
+            updated.push(self.relative_path(file)?);
+        }
+        if target_rewrite.is_some() {
+            updated.push(self.relative_path(&target_file)?);
+        }
+
+        // The source goes first: declaring a new target module may edit it
+        self.write_checked(&source_file, &cut.remaining)?;
+        if let Some(parent) = target_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.write_checked(&target_file, &insertion.content)?;
+        if !target_existed {
+            self.add_module_declaration(target_path)?;
+        }
+        self.scan_rust_files()?;
+
+        Ok(MoveReport {
+            updated,
+            stale_imports: cut.stale_imports,
+            unused_imports: insertion.unused_imports,
+        })
+    }
+
+    /// Adds `impl <trait_spec> for <type_name>` with every required item
+    /// stubbed, next to the type's other impls. Returns the file it went into.
+    pub fn implement_trait(&mut self, trait_spec: &str, type_name: &str) -> Result<(String, Insertion)> {
+        let ty = generate::find_type(&self.rust_files, type_name)?;
+        if !ty.inline_modules.is_empty() {
+            return Err(anyhow!(
+                "'{}' is declared inside 'mod {}', use 'add into' with that module instead",
+                type_name,
+                ty.inline_modules.join("::")
+            ));
+        }
+
+        let trait_name = generate::trait_ident(trait_spec)?;
+        let content = self.edit_base(&ty.file)?;
+        if generate::has_trait_impl(&content, &trait_name, type_name) {
+            return Err(anyhow!("'{}' already implements '{}'", type_name, trait_name));
+        }
+
+        let source = match generate::find_trait(&self.rust_files, &trait_name) {
+            Some(found) => {
+                let use_path = if found.file == ty.file && found.inline_modules.is_empty() {
+                    None
+                } else {
+                    generate::use_declaration(&self.root_path, &found.file, &found.inline_modules, &trait_name)
+                };
+                TraitSource::Project {
+                    declaration: Box::new(found.item),
+                    use_path,
+                }
+            }
+            None => TraitSource::std(&trait_name).ok_or_else(|| {
+                anyhow!("Trait '{}' is neither declared in the project nor a known std trait", trait_name)
+            })?,
+        };
+
+        let code = generate::trait_impl(&source, trait_spec, &ty.item)?;
+        let insertion = self.inserter().insert(&content, &code)?;
+        self.write_checked(&ty.file, &insertion.content)?;
+        Ok((self.relative_path(&ty.file)?, insertion))
+    }
+
+    /// Runs a struct generator for `type_name` and merges its methods into
+    /// the type's impl, creating the impl next to the type if needed
+    pub fn generate_for_type(
+        &mut self,
+        generator: Generator,
+        type_name: &str,
+    ) -> Result<(String, Generated, Insertion)> {
+        let ty = generate::find_type(&self.rust_files, type_name)?;
+        if !ty.inline_modules.is_empty() {
+            return Err(anyhow!(
+                "'{}' is declared inside 'mod {}', which gen does not support",
+                type_name,
+                ty.inline_modules.join("::")
+            ));
+        }
+
+        let content = self.edit_base(&ty.file)?;
+        let fields = RustParser::new().struct_fields(&content, type_name)?;
+        let generated = generate::for_struct(generator, &content, &ty.item, &fields)?;
+        if generated.methods.is_empty() && generated.builder.is_none() {
+            return Err(anyhow!("'{}' already has every generated method", type_name));
+        }
+
+        let inserter = self.inserter();
+        let mut insertion = if generated.methods.is_empty() {
+            Insertion {
+                content,
+                unused_imports: Vec::new(),
+            }
+        } else if generate::has_inherent_impl(&content, type_name) {
+            let target = InsertTarget::Impl {
+                trait_name: None,
+                self_ty: type_name.to_string(),
+            };
+            inserter.insert_into(&content, &target, &generated.methods.join("\n\n"))?
+        } else {
+            inserter.insert(&content, &generate::inherent_impl(&ty.item, &generated.methods)?)?
+        };
+        if let Some(builder) = &generated.builder {
+            // Formatted on its own so the setters keep a blank line between them
+            let declaration = inserter.insert("", &format!("{}\n\nimpl {} {{}}", builder.declaration, builder.name))?;
+            let target = InsertTarget::Impl {
+                trait_name: None,
+                self_ty: builder.name.clone(),
+            };
+            let code = inserter.insert_into(&declaration.content, &target, &builder.methods.join("\n\n"))?;
+
+            let content = &mut insertion.content;
+            let offset = generate::after_type(content, type_name).unwrap_or(content.len());
+            content.insert_str(offset, &format!("\n{}", code.content.trim_start()));
+        }
+
+        self.write_checked(&ty.file, &insertion.content)?;
+        Ok((self.relative_path(&ty.file)?, generated, insertion))
+    }
+
+    /// Adds a stub test for every untested public function of `file_path`
+    /// to its `mod tests`, creating the module when there is none
+    pub fn generate_tests(&mut self, file_path: &str) -> Result<(TestStubs, Insertion)> {
+        let full_path = self.existing_file(file_path)?;
+        let content = self.edit_base(&full_path)?;
+        let stubs = generate::test_stubs(&content)?;
+        if stubs.tests.is_empty() {
+            return Ok((stubs, Insertion { content, unused_imports: Vec::new() }));
+        }
+
+        let target = InsertTarget::Module("tests".to_string());
+        // Existing test modules don't always import the parent module
+        let code = format!("use super::*;\n\n{}", stubs.tests.join("\n\n"));
+        let insertion = self.inserter().insert_into(&content, &target, &code)?;
+        self.write_checked(&full_path, &insertion.content)?;
+        Ok((stubs, insertion))
+    }
+
+    /// Creates the files for `new <kind>` from their templates and registers
+    /// them in `Cargo.toml` or the module tree. Returns one line per step.
+    pub fn scaffold(&mut self, scaffold: &Scaffold) -> Result<Vec<String>> {
+        let file = scaffold.file();
+        let full_path = self.root_path.join(&file);
+        if full_path.exists() {
+            return Err(anyhow!("{} already exists", file));
+        }
+
+        let mut report = Vec::new();
+        let mut manifest = match scaffold {
+            Scaffold::Module(_) | Scaffold::Test(_) => None,
+            _ => Some(Manifest::load(&self.root_path)?),
+        };
+        if let (Scaffold::Crate { path, .. }, Some(manifest)) = (scaffold, &manifest) {
+            let member_manifest = self.root_path.join(path).join("Cargo.toml");
+            if member_manifest.exists() {
+                return Err(anyhow!("{}/Cargo.toml already exists", path));
+            }
+            let edition = manifest.package_field("edition").unwrap_or("2021");
+            fs::create_dir_all(self.root_path.join(path))?;
+            fs::write(&member_manifest, scaffold::crate_manifest(path, edition))?;
+            report.push(format!("Created {}/Cargo.toml", path));
+        }
+
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.write_file(&full_path, &scaffold.template())?;
+        report.push(format!("Created {}", file));
+
+        let registered = match (scaffold, manifest.as_mut()) {
+            (Scaffold::Module(chain), _) => {
+                self.add_module_declaration(&file)?;
+                Some(format!("Declared mod {} in its parent module", chain[chain.len() - 1]))
+            }
+            (Scaffold::Bin(name), Some(manifest)) | (Scaffold::Example(name), Some(manifest)) => {
+                let section = if matches!(scaffold, Scaffold::Bin(_)) { "bin" } else { "example" };
+                manifest
+                    .add_target(section, name, &file)?
+                    .then(|| format!("Registered [[{}]] {} in Cargo.toml", section, name))
+            }
+            (Scaffold::Crate { path, .. }, Some(manifest)) => manifest
+                .add_workspace_member(path)?
+                .then(|| format!("Added \"{}\" to [workspace] members", path)),
+            _ => None,
+        };
+        if let Some(registered) = registered {
+            if let Some(manifest) = &manifest {
+                manifest.save()?;
+            }
+            report.push(registered);
+        }
+
+        if !matches!(scaffold, Scaffold::Module(_)) {
+            self.load_workspace();
+        }
+        self.scan_rust_files()?;
+        Ok(report)
+    }
+
+    /// Applies compiler suggestions to the files they point at, each with
+    /// all of its edits or none. Suggestions whose spans no longer match
+    /// the file, that overlap an earlier one or that would break a file that
+    /// parses are skipped.
+    pub fn apply_suggestions(&mut self, suggestions: &[&Suggestion]) -> Result<FixReport> {
+        let mut originals: HashMap<&str, String> = HashMap::new();
+        for part in suggestions.iter().flat_map(|suggestion| &suggestion.parts) {
+            let file = part.span.file.as_str();
+            if !originals.contains_key(file) {
+                let content = fs::read_to_string(self.root_path.join(file))
+                    .map_err(|e| anyhow!("Failed to read {}: {}", file, e))?;
+                originals.insert(file, content);
+            }
+        }
+        let location = |suggestion: &Suggestion| {
+            let span = &suggestion.parts[0].span;
+            format!("{}:{}", span.file, span.line_start)
+        };
+
+        // Earliest first, so of two overlapping suggestions the first wins
+        let mut order: Vec<usize> = (0..suggestions.len())
+            .filter(|&index| !suggestions[index].parts.is_empty())
+            .collect();
+        order.sort_by_key(|&index| {
+            let span = &suggestions[index].parts[0].span;
+            (span.file.as_str(), span.byte_start, span.byte_end)
+        });
+
+        let mut report = FixReport {
+            applied: Vec::new(),
+            files: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let mut accepted: Vec<usize> = Vec::new();
+        // The same edits suggested again, e.g. by a second diagnostic
+        let mut repeats: Vec<(usize, usize)> = Vec::new();
+        for index in order {
+            let suggestion = suggestions[index];
+            if !suggestion.parts.iter().all(|part| span_matches(&originals[part.span.file.as_str()], &part.span)) {
+                report.skipped.push(format!("{} changed since the build", location(suggestion)));
+                continue;
+            }
+            if let Some(&same) = accepted.iter().find(|&&other| suggestions[other].parts == suggestion.parts) {
+                repeats.push((index, same));
+                continue;
+            }
+            if accepted.iter().any(|&other| suggestions[other].overlaps(suggestion)) {
+                report.skipped.push(format!("{} overlaps another suggestion", location(suggestion)));
+                continue;
+            }
+            accepted.push(index);
+        }
+
+        // A file that would stop parsing takes every suggestion touching it out
+        let updated = loop {
+            let mut updated: BTreeMap<&str, String> = BTreeMap::new();
+            let mut parts: Vec<_> = accepted.iter().flat_map(|&index| &suggestions[index].parts).collect();
+            parts.sort_by_key(|part| std::cmp::Reverse(part.span.byte_start));
+            for part in parts {
+                let file = part.span.file.as_str();
+                updated
+                    .entry(file)
+                    .or_insert_with(|| originals[file].clone())
+                    .replace_range(part.span.byte_start..part.span.byte_end, &part.replacement);
+            }
+            let broken = updated
+                .iter()
+                .find(|(file, content)| syn::parse_file(&originals[**file]).is_ok() && syn::parse_file(content).is_err())
+                .map(|(file, _)| *file);
+            match broken {
+                Some(file) => {
+                    report.skipped.push(format!("{}: the suggestions would not parse", file));
+                    accepted.retain(|&index| suggestions[index].parts.iter().all(|part| part.span.file != file));
+                }
+                None => break updated,
+            }
+        };
+
+        let mut undo = Vec::new();
+        for (file, content) in updated {
+            let path = self.root_path.join(file);
+            self.write_file(&path, &content)?;
+            report.files.push(file.to_string());
+            undo.push((path, originals[file].clone(), content));
+        }
+        report.applied = accepted.clone();
+        report.applied.extend(
+            repeats
+                .into_iter()
+                .filter(|(_, same)| accepted.contains(same))
+                .map(|(index, _)| index),
+        );
+        report.applied.sort_unstable();
+
+        if !undo.is_empty() {
+            self.undo_stack.push(UndoStep {
+                description: format!("{} suggestion(s) in {}", accepted.len(), report.files.join(", ")),
+                files: undo,
+            });
+        }
+        Ok(report)
+    }
+
+    /// Bundles diagnostics with their code and the project types they
+    /// mention into a prompt asking for a fix
+    pub fn fix_prompt(&self, diagnostics: &[&Diagnostic], options: PromptOptions) -> Result<FixPrompt> {
+        fix_prompt::build(&self.root_path, &self.rust_files, diagnostics, options)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Restores the files of the last `apply_suggestions`, unless they were
+    /// edited since
+    pub fn undo(&mut self) -> Result<String> {
+        let step = self.undo_stack.last().ok_or_else(|| anyhow!("Nothing to undo"))?;
+        for (path, _, written) in &step.files {
+            if fs::read_to_string(path)? != *written {
+                return Err(anyhow!(
+                    "{} was edited after the fix, undo it by hand",
+                    self.relative_path(path)?
+                ));
+            }
+        }
+
+        let step = self.undo_stack.pop().expect("checked above");
+        for (path, before, _) in &step.files {
+            self.write_file(path, before)?;
+        }
+        Ok(step.description)
+    }
+
+    /// Resolves a file path the same way `read_file` does
+    fn existing_file(&self, file_path: &str) -> Result<PathBuf> {
+        let full_path = self.root_path.join(file_path);
+        if full_path.exists() {
+            return Ok(full_path);
+        }
+        self.rust_files
+            .iter()
+            .find(|rust_file| rust_file.ends_with(file_path))
+            .cloned()
+            .ok_or_else(|| anyhow!("File not found: {}", file_path))
+    }
+
+    /// Declares the module for `file_path` in its parent, walking the whole
+    /// module chain so `src/systems/ai.rs` gets `mod ai;` in `src/systems.rs`
+    /// (or `src/systems/mod.rs`) and `mod systems;` in the crate root.
+    /// Missing parent module files are created. Below the crate root a
+    /// private setting becomes `pub(crate)`, so the new module stays
+    /// reachable. Binaries, tests, examples and benches are their own crate
+    /// roots and need no declaration.
     fn add_module_declaration(&mut self, file_path: &str) -> Result<()> {
-        // Extract module name from file path
-        if let Some(module_name) = file_path
+        let normalized = file_path.replace('\\', "/");
+        let Some(module_path) = normalized
+            .trim_start_matches("./")
             .strip_prefix("src/")
             .and_then(|s| s.strip_suffix(".rs"))
-            .and_then(|s| s.split('/').last())
-        {
-            // Try to add to main.rs first, then lib.rs
-            for root_file in ["src/main.rs", "src/lib.rs"] {
-                let root_path = self.root_path.join(root_file);
-                if root_path.exists() {
-                    let content = fs::read_to_string(&root_path)?;
-
-                    // Check if module is already declared
-                    if !content.contains(&format!("mod {};", module_name)) {
-                        let inserter = CodeInserter::new();
-                        let module_decl = format!("mod {};", module_name);
-                        let updated_content = inserter.insert_code(&content, &module_decl)?;
-                        fs::write(&root_path, updated_content)?;
-                    }
-                    break;
-                }
+        else {
+            return Ok(());
+        };
+        if module_path.starts_with("bin/") {
+            return Ok(());
+        }
+
+        let mut chain: Vec<&str> = module_path.split('/').collect();
+        if chain.last() == Some(&"mod") {
+            chain.pop();
+        }
+        if chain.is_empty() || chain == ["main"] || chain == ["lib"] {
+            return Ok(());
+        }
+
+        for depth in 0..chain.len() {
+            let parent = match depth {
+                0 => match self.crate_root(chain[0])? {
+                    Some(root) => root,
+                    None => return Ok(()),
+                },
+                _ => self.module_file(&chain[..depth])?,
+            };
+
+            let visibility = match depth {
+                0 => self.config.module_visibility,
+                _ => self.config.module_visibility.nested(),
+            };
+            let content = fs::read_to_string(&parent)?;
+            let updated = self.inserter().declare_module(
+                &content,
+                chain[depth],
+                visibility.prefix(),
+                self.config.sort_modules,
+            )?;
+            if updated != content {
+                self.write_file(&parent, &updated)?;
             }
         }
         Ok(())
     }
 
-    pub fn read_file(&self, file_path: &str) -> Result<String> {
-        let full_path = self.root_path.join(file_path);
-        
-        if !full_path.exists() {
-            // Try to find the file in rust_files
-            for rust_file in &self.rust_files {
-                if rust_file.ends_with(file_path) {
-                    return fs::read_to_string(rust_file)
-                        .map_err(|e| anyhow!("Failed to read file: {}", e));
+    /// The crate root that should declare the top-level module `name`: the
+    /// one that already does, otherwise `src/lib.rs` before `src/main.rs`
+    fn crate_root(&self, name: &str) -> Result<Option<PathBuf>> {
+        let roots: Vec<PathBuf> = ["src/lib.rs", "src/main.rs"]
+            .iter()
+            .map(|root| self.root_path.join(root))
+            .filter(|root| root.exists())
+            .collect();
+
+        for root in &roots {
+            let content = fs::read_to_string(root)?;
+            if let Ok(file) = syn::parse_file(&content) {
+                let declared = file
+                    .items
+                    .iter()
+                    .any(|item| matches!(item, syn::Item::Mod(module) if module.ident == name));
+                if declared {
+                    return Ok(Some(root.clone()));
                 }
             }
-            return Err(anyhow!("File not found: {}", file_path));
         }
+        Ok(roots.into_iter().next())
+    }
+
+    /// The file of the module at `chain`, creating `src/<chain>.rs` if
+    /// neither it nor `src/<chain>/mod.rs` exists
+    fn module_file(&self, chain: &[&str]) -> Result<PathBuf> {
+        let base = self.root_path.join("src").join(chain.join("/"));
+        let mod_rs = base.join("mod.rs");
+        if mod_rs.exists() {
+            return Ok(mod_rs);
+        }
+
+        let file = base.with_extension("rs");
+        if !file.exists() {
+            fs::write(&file, "")?;
+        }
+        Ok(file)
+    }
 
-        fs::read_to_string(&full_path).map_err(|e| anyhow!("Failed to read file: {}", e))
+    fn inserter(&self) -> CodeInserter {
+        CodeInserter::new().with_format_mode(self.config.format)
     }
 
+    /// Reads a file for display and remembers it as the version later
+    /// edits are based on
+    pub fn read_file(&mut self, file_path: &str) -> Result<String> {
+        let full_path = self.existing_file(file_path)?;
+        let content = fs::read_to_string(&full_path).map_err(|e| anyhow!("Failed to read file: {}", e))?;
+        self.remember(&full_path, &content);
+        Ok(content)
+    }
+
+    /// The change held back by the last conflicting write, if any
+    pub fn pending_merge(&self) -> Option<&PendingMerge> {
+        self.pending_merge.as_ref()
+    }
+
+    pub fn cancel_merge(&mut self) -> Option<PendingMerge> {
+        self.pending_merge.take()
+    }
+
+    /// Writes the pending three-way merge, unless the file changed again
+    pub fn apply_merge(&mut self) -> Result<PendingMerge> {
+        let merge = self
+            .pending_merge
+            .take()
+            .ok_or_else(|| anyhow!("No merge pending"))?;
+        if fs::read_to_string(&merge.path)? != merge.on_disk {
+            return Err(anyhow!("{} changed again, repeat the command to merge anew", merge.file));
+        }
+        self.write_file(&merge.path, &merge.merged)?;
+        self.scan_rust_files()?;
+        Ok(merge)
+    }
+
+    /// The content a mutating command starts from: the version last shown
+    /// or written when the file has since been edited elsewhere, so that
+    /// `write_checked` can merge the edit instead of overwriting it
+    fn edit_base(&self, path: &Path) -> Result<String> {
+        if self.external_change(path)?.is_some() {
+            if let Some(snapshot) = self.snapshots.get(&snapshot_key(path)) {
+                return Ok(snapshot.content.clone());
+            }
+        }
+        Ok(fs::read_to_string(path)?)
+    }
+
+    /// The on-disk content of `path` when it differs from the version last
+    /// shown or written. Files never shown have nothing to compare with.
+    fn external_change(&self, path: &Path) -> Result<Option<String>> {
+        let Some(snapshot) = self.snapshots.get(&snapshot_key(path)) else {
+            return Ok(None);
+        };
+        let Ok(metadata) = fs::metadata(path) else {
+            return Ok(None);
+        };
+        if metadata.modified().ok() == snapshot.modified {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        Ok((content_hash(&content) != snapshot.hash).then_some(content))
+    }
+
+    /// Writes `content` unless the file was edited elsewhere since it was
+    /// last shown; then a three-way merge is kept for `apply_merge` instead
+    fn write_checked(&mut self, path: &Path, content: &str) -> Result<()> {
+        let Some(on_disk) = self.external_change(path)? else {
+            return self.write_file(path, content);
+        };
+        let base = &self.snapshots[&snapshot_key(path)].content;
+        let result = merge::merge3(base, content, &on_disk);
+        let file = self.relative_path(path)?;
+        let outcome = match result.conflicts {
+            0 => "merges cleanly".to_string(),
+            n => format!("has {} conflicting region(s) marked with <<<<<<<", n),
+        };
+        self.pending_merge = Some(PendingMerge {
+            file: file.clone(),
+            path: path.to_path_buf(),
+            on_disk,
+            merged: result.content,
+            conflicts: result.conflicts,
+        });
+        Err(anyhow!(
+            "{} changed on disk since it was last shown. Nothing was written.\n\
+             A three-way merge of the version last shown, the file on disk and this change {}.\n\
+             Use 'merge show' to review it, 'merge apply' to write it or 'merge cancel' to drop it.",
+            file,
+            outcome
+        ))
+    }
+
+    fn write_file(&mut self, path: &Path, content: &str) -> Result<()> {
+        fs::write(path, content)?;
+        self.remember(path, content);
+        Ok(())
+    }
+
+    fn remember(&mut self, path: &Path, content: &str) {
+        let snapshot = Snapshot {
+            hash: content_hash(content),
+            modified: fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+            content: content.to_string(),
+        };
+        self.snapshots.insert(snapshot_key(path), snapshot);
+    }
+
+    /// Rust files of the scoped package or target, all of them without a scope
     pub fn list_rust_files(&self) -> Result<Vec<String>> {
         let mut files = Vec::new();
-        for file in &self.rust_files {
+        for file in self.scoped_files() {
             files.push(self.relative_path(file)?);
         }
         Ok(files)
@@ -197,6 +998,74 @@ impl Project {
     }
 }
 
+/// Whether `span` still points where the compiler saw it: the byte offsets
+/// land on the reported line and column
+fn span_matches(content: &str, span: &Span) -> bool {
+    if span.byte_end > content.len()
+        || span.byte_start > span.byte_end
+        || !content.is_char_boundary(span.byte_start)
+        || !content.is_char_boundary(span.byte_end)
+    {
+        return false;
+    }
+    let before = &content[..span.byte_start];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    line == span.line_start && column == span.column_start
+}
+
+/// The files of the module tree rooted at `root_file`, following `mod x;`
+/// declarations; modules with a `#[path]` attribute are not followed
+fn module_tree(root_file: &Path) -> HashSet<PathBuf> {
+    let mut files = HashSet::new();
+    let dir = root_file.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut pending = vec![(root_file.to_path_buf(), dir)];
+    while let Some((file, dir)) = pending.pop() {
+        if !files.insert(file.clone()) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        if let Ok(parsed) = syn::parse_file(&content) {
+            declared_modules(&parsed.items, &dir, &mut pending);
+        }
+    }
+    files
+}
+
+/// Queues the file and module directory of each out-of-line module in
+/// `items`, descending into inline ones
+fn declared_modules(items: &[syn::Item], dir: &Path, pending: &mut Vec<(PathBuf, PathBuf)>) {
+    for item in items {
+        let syn::Item::Mod(module) = item else {
+            continue;
+        };
+        if module.attrs.iter().any(|attr| attr.path().is_ident("path")) {
+            continue;
+        }
+        let child_dir = dir.join(module.ident.to_string());
+        match &module.content {
+            Some((_, items)) => declared_modules(items, &child_dir, pending),
+            None => {
+                let file = child_dir.with_extension("rs");
+                let file = if file.exists() { file } else { child_dir.join("mod.rs") };
+                pending.push((file, child_dir));
+            }
+        }
+    }
+}
+
+fn snapshot_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -210,6 +1079,197 @@ mod tests {
         assert!(project.is_ok());
     }
 
+    #[test]
+    fn test_nested_module_declaration() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "pub mod app;\n\nfn main() {}\n").unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        project.add_code("src/systems/ai.rs", None, "pub fn think() {}", false).unwrap();
+        project.add_code("src/bin/tool.rs", None, "fn main() {}", false).unwrap();
+
+        let main = fs::read_to_string(temp_dir.path().join("src/main.rs")).unwrap();
+        let systems = fs::read_to_string(temp_dir.path().join("src/systems.rs")).unwrap();
+        assert_eq!(main, "pub mod app;\nmod systems;\n\nfn main() {}\n");
+        assert_eq!(systems, "pub(crate) mod ai;\n");
+    }
+
+    #[test]
+    fn test_move_item_to_new_module() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::write(
+            temp_dir.path().join("src/main.rs"),
+            "mod world;\nuse std::fmt;\n\n#[derive(Debug)]\nstruct Npc {\n    hp: u32,\n}\n\nimpl fmt::Display for Npc {\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {\n        write!(f, \"{}\", self.hp)\n    }\n}\n\nfn main() {\n    let npc = Npc { hp: 3 };\n    println!(\"{}\", npc);\n}\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("src/world.rs"), "use crate::Npc;\n\npub fn spawn() -> Npc {\n    crate::Npc { hp: 1 }\n}\n").unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        project.config.format = crate::patch::FormatMode::Prettyplease;
+        let report = project.move_item("src/main.rs", "Npc", "src/npc.rs").unwrap();
+        assert_eq!(report.updated, vec!["src/world.rs".to_string()]);
+        assert_eq!(report.stale_imports, vec!["std::fmt".to_string()]);
+
+        let main = fs::read_to_string(temp_dir.path().join("src/main.rs")).unwrap();
+        let npc = fs::read_to_string(temp_dir.path().join("src/npc.rs")).unwrap();
+        let world = fs::read_to_string(temp_dir.path().join("src/world.rs")).unwrap();
+        assert_eq!(
+            main,
+            "mod npc;\nmod world;\nuse std::fmt;\nuse crate::npc::Npc;\n\nfn main() {\n    let npc = Npc { hp: 3 };\n    println!(\"{}\", npc);\n}\n"
+        );
+        assert!(npc.starts_with("use std::fmt;\n\n#[derive(Debug)]\npub(crate) struct Npc {\n    pub(crate) hp: u32,\n}\n"));
+        assert!(npc.contains("impl fmt::Display for Npc"));
+        assert_eq!(
+            world,
+            "use crate::npc::Npc;\n\npub fn spawn() -> Npc {\n    crate::npc::Npc { hp: 1 }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_failed_move_writes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = "mod npc;\nmod world;\n\nstruct Npc {\n    hp: u32,\n}\n\nfn main() {}\n";
+        let world = "pub fn spawn() -> crate::Npc {\n    crate::Npc { hp: 1 }\n}\n";
+        let npc = "pub fn broken( {\n";
+        fs::write(temp_dir.path().join("src/main.rs"), main).unwrap();
+        fs::write(temp_dir.path().join("src/world.rs"), world).unwrap();
+        fs::write(temp_dir.path().join("src/npc.rs"), npc).unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        assert!(project.move_item("src/main.rs", "Npc", "src/npc.rs").is_err());
+        let read = |file: &str| fs::read_to_string(temp_dir.path().join(file)).unwrap();
+        assert_eq!(read("src/main.rs"), main);
+        assert_eq!(read("src/world.rs"), world);
+        assert_eq!(read("src/npc.rs"), npc);
+    }
+
+    #[test]
+    fn test_broken_snippet_is_not_written() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("lib.rs");
+        fs::write(&file, "fn a() {}\n").unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        assert!(project.add_code("lib.rs", None, "fn b() {", false).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn a() {}\n");
+
+        project.add_code("lib.rs", None, "fn b() {", true).unwrap();
+        assert!(fs::read_to_string(&file).unwrap().contains("fn b() {"));
+    }
+
+    #[test]
+    fn test_external_edit_is_merged() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("lib.rs");
+        fs::write(&file, "fn a() {}\n\nfn c() {}\n").unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        project.read_file("lib.rs").unwrap();
+        fs::write(&file, "fn a() {\n    edited();\n}\n\nfn c() {}\n").unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(later).unwrap();
+
+        let error = project.add_code("lib.rs", None, "fn b() {}", false).unwrap_err();
+        assert!(error.to_string().contains("changed on disk"));
+        assert!(!fs::read_to_string(&file).unwrap().contains("fn b"));
+
+        let merge = project.apply_merge().unwrap();
+        assert_eq!(merge.conflicts, 0);
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.contains("edited();") && content.contains("fn b() {}"), "{}", content);
+    }
+
+    #[test]
+    fn test_scaffold() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"game\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        project.scaffold(&Scaffold::parse("module systems::ai").unwrap()).unwrap();
+        project.scaffold(&Scaffold::parse("bin level-editor").unwrap()).unwrap();
+        project.scaffold(&Scaffold::parse("crate crates/physics").unwrap()).unwrap();
+        assert!(project.scaffold(&Scaffold::parse("bin level-editor").unwrap()).is_err());
+
+        let main = fs::read_to_string(temp_dir.path().join("src/main.rs")).unwrap();
+        let manifest = fs::read_to_string(temp_dir.path().join("Cargo.toml")).unwrap();
+        let member = fs::read_to_string(temp_dir.path().join("crates/physics/Cargo.toml")).unwrap();
+        assert_eq!(main, "mod systems;\n\nfn main() {}\n");
+        assert!(temp_dir.path().join("src/systems/ai.rs").exists());
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("src/systems.rs")).unwrap(),
+            "pub(crate) mod ai;\n"
+        );
+        assert_eq!(
+            manifest,
+            "[package]\nname = \"game\"\nedition = \"2021\"\n\n[[bin]]\nname = \"level-editor\"\npath = \"src/bin/level-editor.rs\"\n\n[workspace]\nmembers = [\"crates/physics\"]\n"
+        );
+        assert!(member.starts_with("[package]\nname = \"physics\""));
+        assert!(project.list_rust_files().unwrap().contains(&"crates/physics/src/lib.rs".to_string()));
+    }
+
+    #[test]
+    fn test_apply_suggestions_and_undo() {
+        use crate::diagnostics::{Applicability, SuggestionPart};
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let file = temp_dir.path().join("src/main.rs");
+        let original = "fn main() {\n    let mut x = 1;\n    println!(\"{}\", x);\n}\n";
+        fs::write(&file, original).unwrap();
+
+        let part = |byte_start, line, column_start, replacement: &str| SuggestionPart {
+            span: Span {
+                file: "src/main.rs".to_string(),
+                byte_start,
+                byte_end: byte_start + 4,
+                line_start: line,
+                line_end: line,
+                column_start,
+                column_end: column_start + 4,
+                is_primary: true,
+                label: None,
+            },
+            replacement: replacement.to_string(),
+        };
+        let suggestion = |parts| Suggestion {
+            message: "remove this `mut`".to_string(),
+            parts,
+            applicability: Applicability::MachineApplicable,
+        };
+        let fresh = suggestion(vec![part(20, 2, 9, "")]);
+        let stale = suggestion(vec![part(21, 2, 9, "")]);
+        // Its second edit no longer matches, so the first must not go in either
+        let half_stale = suggestion(vec![part(3, 1, 4, "game"), part(45, 3, 20, "y")]);
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        let report = project.apply_suggestions(&[&half_stale, &fresh, &stale]).unwrap();
+        assert_eq!(report.applied, vec![1]);
+        assert_eq!(
+            report.skipped,
+            vec![
+                "src/main.rs:1 changed since the build".to_string(),
+                "src/main.rs:2 changed since the build".to_string()
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            original.replace("let mut x", "let x")
+        );
+
+        project.undo().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), original);
+        assert!(!project.can_undo());
+    }
+
     #[test]
     fn test_search() {
         let temp_dir = TempDir::new().unwrap();
@@ -220,4 +1280,46 @@ mod tests {
         let results = project.search("main").unwrap();
         assert!(!results.is_empty());
     }
+
+    #[test]
+    fn test_workspace_scope() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("crates/physics/src")).unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"game\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\nmembers = [\"crates/physics\"]\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "mod world;\nfn main() {}\n").unwrap();
+        fs::write(temp_dir.path().join("src/world.rs"), "pub struct World;\n").unwrap();
+        fs::create_dir_all(temp_dir.path().join("src/bin/editor")).unwrap();
+        fs::write(temp_dir.path().join("src/bin/editor/main.rs"), "mod ui;\nfn run() {}\n").unwrap();
+        fs::write(temp_dir.path().join("src/bin/editor/ui.rs"), "pub fn draw() {}\n").unwrap();
+        fs::write(
+            temp_dir.path().join("crates/physics/Cargo.toml"),
+            "[package]\nname = \"physics\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("crates/physics/src/lib.rs"), "pub fn step() {}\n").unwrap();
+
+        let mut project = Project::load(temp_dir.path().to_path_buf()).unwrap();
+        let workspace = project.workspace.as_ref().unwrap();
+        assert_eq!(workspace.packages.len(), 2);
+        assert_eq!(workspace.package("physics").unwrap().dir, Path::new("crates/physics"));
+
+        project.set_scope(Some("physics"), Some("lib")).unwrap();
+        assert_eq!(project.list_rust_files().unwrap(), vec!["crates/physics/src/lib.rs"]);
+        assert!(project.search("main").unwrap().is_empty());
+        project.set_scope(Some("game"), Some("bin:editor")).unwrap();
+        let mut files = project.list_rust_files().unwrap();
+        files.sort();
+        assert_eq!(files, vec!["src/bin/editor/main.rs", "src/bin/editor/ui.rs"]);
+        assert!(project.search("World").unwrap().is_empty());
+        assert!(project.set_scope(Some("physics"), Some("bin:physics")).is_err());
+        assert!(project.set_scope(Some("audio"), None).is_err());
+
+        project.set_scope(None, None).unwrap();
+        assert_eq!(project.list_rust_files().unwrap().len(), 5);
+    }
 }
@@ -0,0 +1,1669 @@
+use crate::imports::{leaves_of, render_use, unused_imports};
+use crate::patch::{
+    located_lines, offset_of, remove_lines, span_range, type_name, CodeInserter, FormatMode,
+};
+use anyhow::{anyhow, Result};
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{Fields, ImplItem, Item};
+
+/// What a rename applies to; decides which occurrences of the old name count
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Symbol {
+    Function,
+    Type,
+    Module,
+    Method { owner: String },
+    Field { owner: String },
+    Variant { owner: String },
+}
+
+impl Symbol {
+    fn describe(&self) -> &'static str {
+        match self {
+            Symbol::Function => "function",
+            Symbol::Type => "type",
+            Symbol::Module => "module",
+            Symbol::Method { .. } => "method",
+            Symbol::Field { .. } => "field",
+            Symbol::Variant { .. } => "variant",
+        }
+    }
+}
+
+/// A text replacement in one file
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// All edits for one file, with the content they were computed against
+#[derive(Debug, Clone)]
+pub struct FileEdits {
+    pub path: PathBuf,
+    pub original: String,
+    pub edits: Vec<TextEdit>,
+}
+
+impl FileEdits {
+    pub fn apply(&self) -> String {
+        let mut edits = self.edits.clone();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+        let mut content = self.original.clone();
+        for edit in edits {
+            content.replace_range(edit.start..edit.end, &edit.text);
+        }
+        content
+    }
+}
+
+/// A multi-file change that is previewed before it is applied
+#[derive(Debug, Clone)]
+pub struct RefactorPlan {
+    pub description: String,
+    pub files: Vec<FileEdits>,
+    /// Files or directories to move after the edits are written
+    pub moves: Vec<(PathBuf, PathBuf)>,
+    /// `file:line` of member accesses left alone because the type of their
+    /// receiver could not be worked out
+    pub unresolved: Vec<String>,
+}
+
+impl RefactorPlan {
+    pub fn change_count(&self) -> usize {
+        self.files.iter().map(|file| file.edits.len()).sum()
+    }
+
+    /// Every changed line, before and after, grouped by file
+    pub fn preview(&self, root: &Path) -> String {
+        let mut output = format!(
+            "{}: {} change(s) in {} file(s)\n",
+            self.description,
+            self.change_count(),
+            self.files.len()
+        );
+
+        for file in &self.files {
+            let relative = file.path.strip_prefix(root).unwrap_or(&file.path);
+            output.push_str(&format!("\n{}\n", relative.display()));
+
+            let mut lines: BTreeMap<usize, Vec<&TextEdit>> = BTreeMap::new();
+            for edit in &file.edits {
+                let line = file.original[..edit.start].matches('\n').count();
+                lines.entry(line).or_default().push(edit);
+            }
+
+            for (line, mut edits) in lines {
+                // Offsets come from the text itself, so `\r\n` endings count
+                let line_start = file.original[..edits[0].start]
+                    .rfind('\n')
+                    .map_or(0, |i| i + 1);
+                let before = file.original[line_start..].lines().next().unwrap_or("");
+                let mut after = before.to_string();
+                edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+                for edit in edits {
+                    let end = (edit.end - line_start).min(after.len());
+                    after.replace_range(edit.start - line_start..end, &edit.text);
+                }
+                output.push_str(&format!("  {:>4} - {}\n", line + 1, before.trim()));
+                output.push_str(&format!("       + {}\n", after.trim()));
+            }
+        }
+
+        for (from, to) in &self.moves {
+            output.push_str(&format!(
+                "\nMove {} -> {}\n",
+                from.strip_prefix(root).unwrap_or(from).display(),
+                to.strip_prefix(root).unwrap_or(to).display()
+            ));
+        }
+
+        if !self.unresolved.is_empty() {
+            output.push_str("\nLeft alone, the receiver's type is unknown (check by hand):\n");
+            for location in &self.unresolved {
+                output.push_str(&format!("  {}\n", location));
+            }
+        }
+        output
+    }
+}
+
+/// A flattened token with its byte range and enclosing group
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+    /// Index of the `Open` token of the enclosing group
+    parent: Option<usize>,
+    in_use: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Punct(char, Spacing),
+    Open(Delimiter),
+    Close,
+    Literal,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let stream: TokenStream = source
+        .parse()
+        .map_err(|e| anyhow!("Failed to tokenize: {:?}", e))?;
+    let mut tokens = Vec::new();
+    flatten(source, stream, None, &mut tokens);
+
+    // Mark everything from `use` to its `;` so module renames reach use trees
+    let mut use_parent = None;
+    for token in tokens.iter_mut() {
+        if let Some(parent) = use_parent {
+            token.in_use = true;
+            if token.kind == TokenKind::Punct(';', Spacing::Alone) && token.parent == parent {
+                use_parent = None;
+            }
+        } else if token.kind == TokenKind::Ident("use".to_string()) {
+            token.in_use = true;
+            use_parent = Some(token.parent);
+        }
+    }
+    Ok(tokens)
+}
+
+fn flatten(source: &str, stream: TokenStream, parent: Option<usize>, tokens: &mut Vec<Token>) {
+    for tree in stream {
+        let range = |span: proc_macro2::Span| {
+            (
+                offset_of(source, span.start()),
+                offset_of(source, span.end()),
+            )
+        };
+        match tree {
+            TokenTree::Ident(ident) => {
+                let (start, end) = range(ident.span());
+                tokens.push(Token {
+                    kind: TokenKind::Ident(ident.to_string()),
+                    start,
+                    end,
+                    parent,
+                    in_use: false,
+                });
+            }
+            TokenTree::Punct(punct) => {
+                let (start, end) = range(punct.span());
+                tokens.push(Token {
+                    kind: TokenKind::Punct(punct.as_char(), punct.spacing()),
+                    start,
+                    end,
+                    parent,
+                    in_use: false,
+                });
+            }
+            TokenTree::Literal(literal) => {
+                let (start, end) = range(literal.span());
+                tokens.push(Token {
+                    kind: TokenKind::Literal,
+                    start,
+                    end,
+                    parent,
+                    in_use: false,
+                });
+            }
+            TokenTree::Group(group) => {
+                let (start, end) = range(group.span_open());
+                let open = tokens.len();
+                tokens.push(Token {
+                    kind: TokenKind::Open(group.delimiter()),
+                    start,
+                    end,
+                    parent,
+                    in_use: false,
+                });
+                flatten(source, group.stream(), Some(open), tokens);
+                let (start, end) = range(group.span_close());
+                tokens.push(Token {
+                    kind: TokenKind::Close,
+                    start,
+                    end,
+                    parent,
+                    in_use: false,
+                });
+            }
+        }
+    }
+}
+
+fn ident_at(tokens: &[Token], index: usize) -> Option<&str> {
+    match &tokens.get(index)?.kind {
+        TokenKind::Ident(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn punct_at(tokens: &[Token], index: usize) -> Option<(char, Spacing)> {
+    match tokens.get(index)?.kind {
+        TokenKind::Punct(c, spacing) => Some((c, spacing)),
+        _ => None,
+    }
+}
+
+/// `::` directly before the token at `index`
+fn path_sep_before(tokens: &[Token], index: usize) -> bool {
+    index >= 2
+        && punct_at(tokens, index - 1) == Some((':', Spacing::Alone))
+        && punct_at(tokens, index - 2) == Some((':', Spacing::Joint))
+}
+
+/// `::` directly after the token at `index`
+fn path_sep_after(tokens: &[Token], index: usize) -> bool {
+    punct_at(tokens, index + 1) == Some((':', Spacing::Joint))
+        && punct_at(tokens, index + 2) == Some((':', Spacing::Alone))
+}
+
+/// A lone `:` after the token, as in `name: Type` or `field: value`
+fn colon_after(tokens: &[Token], index: usize) -> bool {
+    punct_at(tokens, index + 1) == Some((':', Spacing::Alone))
+}
+
+/// The identifier naming the struct when the token at `index` is a field
+/// name in `Owner { .. }`, a struct literal or pattern
+fn struct_brace_owner(tokens: &[Token], index: usize) -> Option<usize> {
+    let open = tokens[index].parent?;
+    if tokens[open].kind != TokenKind::Open(Delimiter::Brace) || open == 0 {
+        return None;
+    }
+    let at_field_start =
+        index == open + 1 || punct_at(tokens, index - 1) == Some((',', Spacing::Alone));
+    (at_field_start && ident_at(tokens, open - 1).is_some()).then_some(open - 1)
+}
+
+/// The path segments written before the token at `index`, `a::b` in
+/// `a::b::name`, and the index of the first one
+fn path_before(tokens: &[Token], index: usize) -> (usize, Vec<String>) {
+    let mut first = index;
+    let mut segments = Vec::new();
+    while path_sep_before(tokens, first) {
+        match ident_at(tokens, first - 3) {
+            Some(segment) => {
+                segments.insert(0, segment.to_string());
+                first -= 3;
+            }
+            None => break,
+        }
+    }
+    (first, segments)
+}
+
+/// The path starting at `index`, `a::b::c`, and the index after it
+fn path_from(tokens: &[Token], index: usize) -> (Vec<String>, usize) {
+    let mut segments = Vec::new();
+    let mut next = index;
+    while let Some(segment) = ident_at(tokens, next) {
+        segments.push(segment.to_string());
+        if !path_sep_after(tokens, next) {
+            return (segments, next + 1);
+        }
+        next += 3;
+    }
+    (segments, next)
+}
+
+/// Whether the token at `index` sits inside the group opened at `group`
+fn within(tokens: &[Token], index: usize, group: Option<usize>) -> bool {
+    let Some(group) = group else {
+        return true;
+    };
+    let mut parent = tokens[index].parent;
+    while let Some(open) = parent {
+        if open == group {
+            return true;
+        }
+        parent = tokens[open].parent;
+    }
+    false
+}
+
+/// Whether the token at `index` is the name in a declaration, `fn name`,
+/// `struct name`, `let mut name`, `for name in`, ...
+fn declared_at(tokens: &[Token], index: usize) -> bool {
+    const KEYWORDS: [&str; 9] = [
+        "fn", "struct", "enum", "union", "trait", "type", "static", "mod", "let",
+    ];
+    let Some(before) = index
+        .checked_sub(1)
+        .and_then(|before| ident_at(tokens, before))
+    else {
+        return false;
+    };
+    match before {
+        // `const NAME: T`, not `*const T`
+        "const" => colon_after(tokens, index),
+        // `let mut name` or `static mut NAME`, not `&mut T` or `*mut T`
+        "mut" => index >= 2 && matches!(ident_at(tokens, index - 2), Some("let" | "static")),
+        "for" => loop_binding(tokens, index),
+        _ => KEYWORDS.contains(&before),
+    }
+}
+
+/// Whether the token at `index` is the pattern of `for name in ..`, not
+/// the type of `impl Trait for Type`
+fn loop_binding(tokens: &[Token], index: usize) -> bool {
+    ident_at(tokens, index + 1) == Some("in")
+}
+
+/// A local variable or parameter
+struct Binding {
+    index: usize,
+    /// The token its scope starts after
+    from: usize,
+    /// The group it is visible in, `None` for the whole file
+    group: Option<usize>,
+}
+
+/// Every `let`, `for`, closure and function parameter binding of `name`
+fn bindings(tokens: &[Token], name: &str) -> Vec<Binding> {
+    let mut found = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Ident(name.to_string()) || index == 0 {
+            continue;
+        }
+        let name_at = match ident_at(tokens, index - 1) {
+            Some("mut") => index - 1,
+            _ => index,
+        };
+        let before = name_at.checked_sub(1);
+        let keyword = before.and_then(|before| ident_at(tokens, before));
+        let closure = before
+            .and_then(|before| punct_at(tokens, before))
+            .map(|(c, _)| c)
+            == Some('|')
+            && matches!(punct_at(tokens, index + 1), Some(('|' | ',' | ':', _)));
+        let for_loop = keyword == Some("for") && loop_binding(tokens, index);
+        if keyword == Some("let") || for_loop || closure {
+            found.push(Binding {
+                index,
+                from: index,
+                group: token.parent,
+            });
+        } else if let Some(body) = parameter_body(tokens, index, name_at) {
+            found.push(Binding {
+                index,
+                from: body,
+                group: Some(body),
+            });
+        }
+    }
+    found
+}
+
+/// The body of the function when the token at `index` is one of its
+/// parameters (written from `name_at`, before a `mut`)
+fn parameter_body(tokens: &[Token], index: usize, name_at: usize) -> Option<usize> {
+    let open = tokens[index].parent?;
+    if tokens[open].kind != TokenKind::Open(Delimiter::Parenthesis) || !colon_after(tokens, index) {
+        return None;
+    }
+    if name_at != open + 1 && punct_at(tokens, name_at - 1) != Some((',', Spacing::Alone)) {
+        return None;
+    }
+    let level = tokens[open].parent;
+    let is_fn = tokens[..open]
+        .iter()
+        .rev()
+        .take_while(|token| {
+            token.parent == level
+                && !matches!(
+                    token.kind,
+                    TokenKind::Open(Delimiter::Brace) | TokenKind::Punct(';', _)
+                )
+        })
+        .any(|token| token.kind == TokenKind::Ident("fn".to_string()));
+    if !is_fn {
+        return None;
+    }
+    let close = (open + 1..tokens.len())
+        .find(|&i| tokens[i].kind == TokenKind::Close && tokens[i].parent == level)?;
+    (close + 1..tokens.len())
+        .find(|&i| {
+            tokens[i].parent == level
+                && matches!(
+                    tokens[i].kind,
+                    TokenKind::Open(Delimiter::Brace) | TokenKind::Punct(';', _)
+                )
+        })
+        .filter(|&i| tokens[i].kind == TokenKind::Open(Delimiter::Brace))
+}
+
+/// Whether a local binding hides the item named by the token at `index`
+fn shadowed(tokens: &[Token], index: usize, name: &str) -> bool {
+    bindings(tokens, name).iter().any(|binding| {
+        binding.index == index || (binding.from < index && within(tokens, index, binding.group))
+    })
+}
+
+/// The type a local binding was declared with, `x: &mut Npc`, or built
+/// from, `let x = Npc::new()` or `let x = Npc { .. }`
+fn binding_type(tokens: &[Token], scope: &FileScope, binding: &Binding) -> Option<String> {
+    let index = binding.index;
+    let ty = if colon_after(tokens, index) {
+        let mut next = index + 2;
+        loop {
+            match &tokens.get(next)?.kind {
+                TokenKind::Punct('&', _) => next += 1,
+                TokenKind::Punct('\'', _) => next += 2,
+                TokenKind::Ident(word) if word == "mut" => next += 1,
+                _ => break,
+            }
+        }
+        let (segments, next) = path_from(tokens, next);
+        if punct_at(tokens, next).map(|(c, _)| c) == Some('<') {
+            return None;
+        }
+        segments.last()?.clone()
+    } else if punct_at(tokens, index + 1) == Some(('=', Spacing::Alone)) {
+        let (segments, next) = path_from(tokens, index + 2);
+        match tokens.get(next).map(|token| &token.kind) {
+            Some(TokenKind::Open(Delimiter::Brace)) => segments.last()?.clone(),
+            Some(TokenKind::Open(Delimiter::Parenthesis)) if segments.len() >= 2 => {
+                segments[segments.len() - 2].clone()
+            }
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+    if ty == "Self" {
+        return scope.impl_type_at(tokens[index].start).map(String::from);
+    }
+    ty.starts_with(char::is_uppercase).then_some(ty)
+}
+
+/// What an identifier equal to the old name turns out to be
+#[derive(Debug, PartialEq)]
+enum Occurrence {
+    Renamed(String),
+    /// A method call or field access whose receiver type is unknown
+    Unresolved,
+    Other,
+}
+
+impl Occurrence {
+    fn when(refers: bool, new: &str) -> Self {
+        if refers {
+            Occurrence::Renamed(new.to_string())
+        } else {
+            Occurrence::Other
+        }
+    }
+}
+
+/// A crate-relative path, `["systems", "ai", "think"]`
+type ItemPath = Vec<String>;
+
+/// What a file's names refer to, as far as a rename needs to know
+struct FileScope {
+    /// Crate-relative module of the file; `None` for bins, tests and
+    /// examples, whose paths into the crate start with the library's name
+    module: Option<Vec<String>>,
+    /// Byte ranges of inline modules and their module paths
+    inline_modules: Vec<((usize, usize), Vec<String>)>,
+    /// Names brought in by `use`, with the crate-relative path they stand for
+    imports: Vec<(String, Vec<String>)>,
+    /// Modules imported with `use path::*`
+    globs: Vec<Vec<String>>,
+    /// Byte ranges of `use` items and the crate-relative paths they import
+    uses: Vec<((usize, usize), Vec<ItemPath>)>,
+    /// Byte ranges of impl blocks and the type they are for
+    impls: Vec<((usize, usize), String)>,
+}
+
+impl FileScope {
+    fn new(content: &str, module: Option<Vec<String>>) -> Result<Self> {
+        let file = syn::parse_file(content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+        let mut scope = FileScope {
+            module,
+            inline_modules: Vec::new(),
+            imports: Vec::new(),
+            globs: Vec::new(),
+            uses: Vec::new(),
+            impls: Vec::new(),
+        };
+        scope.collect(content, &file.items);
+        Ok(scope)
+    }
+
+    fn collect(&mut self, content: &str, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Use(item) => {
+                    let range = span_range(content, item);
+                    let mut targets = Vec::new();
+                    for leaf in leaves_of(item) {
+                        match leaf.target() {
+                            None => {
+                                if let Some(module) = self.absolute(range.0, leaf.path()) {
+                                    self.globs.push(module);
+                                }
+                            }
+                            Some(target) => {
+                                let Some(target) = self.absolute(range.0, &target) else {
+                                    continue;
+                                };
+                                if let Some(binding) = leaf.binding() {
+                                    self.imports.push((binding.to_string(), target.clone()));
+                                }
+                                targets.push(target);
+                            }
+                        }
+                    }
+                    self.uses.push((range, targets));
+                }
+                Item::Impl(block) => {
+                    if let Some(name) = type_name(&block.self_ty) {
+                        self.impls.push((span_range(content, block), name));
+                    }
+                }
+                Item::Mod(module) => {
+                    if let (Some((_, items)), Some(parent)) = (
+                        &module.content,
+                        self.module_at(span_range(content, module).0),
+                    ) {
+                        let mut path = parent;
+                        path.push(module.ident.to_string());
+                        self.inline_modules
+                            .push((span_range(content, module), path));
+                        self.collect(content, items);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The module the code at `offset` belongs to
+    fn module_at(&self, offset: usize) -> Option<Vec<String>> {
+        self.inline_modules
+            .iter()
+            .rev()
+            .find(|((start, end), _)| (*start..*end).contains(&offset))
+            .map(|(_, path)| path.clone())
+            .or_else(|| self.module.clone())
+    }
+
+    /// The crate-relative path `path`, written at `offset`, stands for
+    /// without looking at imports
+    fn absolute(&self, offset: usize, path: &[String]) -> Option<Vec<String>> {
+        match self.module_at(offset) {
+            Some(module) => Some(absolute_path(path, &module)),
+            None => match path.first().map(String::as_str) {
+                None | Some("crate" | "self" | "super") => None,
+                Some(_) => Some(path[1..].to_vec()),
+            },
+        }
+    }
+
+    /// Like `absolute`, but a first segment brought in by a `use` is
+    /// replaced with the path it imports
+    fn resolve(&self, offset: usize, path: &[String]) -> Option<Vec<String>> {
+        let first = path.first()?;
+        if let Some((_, target)) = self.imports.iter().find(|(binding, _)| binding == first) {
+            return Some(target.iter().chain(&path[1..]).cloned().collect());
+        }
+        self.absolute(offset, path)
+    }
+
+    /// Whether a bare `name` at `offset` refers to `name` in `module`
+    fn sees(&self, offset: usize, module: &[String], name: &str) -> bool {
+        if self.module_at(offset).as_deref() == Some(module) {
+            return true;
+        }
+        match self.imports.iter().find(|(binding, _)| binding == name) {
+            Some((_, target)) => target.len() == module.len() + 1 && target.starts_with(module),
+            None => self.globs.iter().any(|glob| glob == module),
+        }
+    }
+
+    /// The paths imported by the `use` item covering `offset`
+    fn use_at(&self, offset: usize) -> Option<&[ItemPath]> {
+        self.uses
+            .iter()
+            .find(|((start, end), _)| (*start..*end).contains(&offset))
+            .map(|(_, targets)| targets.as_slice())
+    }
+
+    /// The type of the innermost impl block covering `offset`
+    fn impl_type_at(&self, offset: usize) -> Option<&str> {
+        self.impls
+            .iter()
+            .filter(|((start, end), _)| (*start..*end).contains(&offset))
+            .min_by_key(|((start, end), _)| end - start)
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+/// The item being renamed
+struct RenameTarget<'a> {
+    symbol: &'a Symbol,
+    old: &'a str,
+    new: &'a str,
+    /// Crate-relative module defining a function or type, or the path of
+    /// the module itself for a module rename
+    module: &'a [String],
+}
+
+impl RenameTarget<'_> {
+    /// Whether the identifier at `index` names `owner`, or is `Self` in
+    /// one of its impls
+    fn is_owner(&self, tokens: &[Token], scope: &FileScope, index: usize, owner: &str) -> bool {
+        match ident_at(tokens, index) {
+            Some("Self") => scope.impl_type_at(tokens[index].start) == Some(owner),
+            Some(name) => name == owner,
+            None => false,
+        }
+    }
+
+    /// `Owner::name` or `Self::name` inside an impl of the owner
+    fn owner_before(&self, tokens: &[Token], scope: &FileScope, index: usize, owner: &str) -> bool {
+        path_sep_before(tokens, index) && self.is_owner(tokens, scope, index - 3, owner)
+    }
+
+    /// Whether the receiver of `.name` at `index` has the owner's type:
+    /// `self` in an impl of it, or a local declared or built as one
+    fn receiver(
+        &self,
+        tokens: &[Token],
+        scope: &FileScope,
+        index: usize,
+        owner: &str,
+    ) -> Occurrence {
+        let Some(receiver) = index.checked_sub(2) else {
+            return Occurrence::Unresolved;
+        };
+        let Some(name) = ident_at(tokens, receiver) else {
+            return Occurrence::Unresolved;
+        };
+        let chained = receiver > 0 && punct_at(tokens, receiver - 1) == Some(('.', Spacing::Alone));
+        if chained || path_sep_before(tokens, receiver) {
+            return Occurrence::Unresolved;
+        }
+        let ty = if name == "self" {
+            scope.impl_type_at(tokens[receiver].start).map(String::from)
+        } else {
+            bindings(tokens, name)
+                .into_iter()
+                .filter(|binding| {
+                    binding.from < receiver && within(tokens, receiver, binding.group)
+                })
+                .max_by_key(|binding| binding.from)
+                .and_then(|binding| binding_type(tokens, scope, &binding))
+        };
+        match ty {
+            Some(ty) => Occurrence::when(ty == owner, self.new),
+            None => Occurrence::Unresolved,
+        }
+    }
+
+    /// Decides what the token at `index` (an identifier equal to the old
+    /// name) becomes
+    fn occurrence(&self, tokens: &[Token], scope: &FileScope, index: usize) -> Occurrence {
+        let token = &tokens[index];
+        let after_dot = index > 0 && punct_at(tokens, index - 1) == Some(('.', Spacing::Alone));
+        let call = matches!(
+            tokens.get(index + 1).map(|t| &t.kind),
+            Some(TokenKind::Open(Delimiter::Parenthesis))
+        ) || path_sep_after(tokens, index);
+
+        match self.symbol {
+            Symbol::Function | Symbol::Type => {
+                let item: Vec<String> = self
+                    .module
+                    .iter()
+                    .cloned()
+                    .chain([self.old.to_string()])
+                    .collect();
+                if token.in_use {
+                    let imported = scope
+                        .use_at(token.start)
+                        .is_some_and(|targets| targets.contains(&item));
+                    return Occurrence::when(imported && !path_sep_after(tokens, index), self.new);
+                }
+                if after_dot || colon_after(tokens, index) || declared_at(tokens, index) {
+                    return Occurrence::Other;
+                }
+                let (_, path) = path_before(tokens, index);
+                if !path.is_empty() {
+                    let resolved = scope.resolve(token.start, &path);
+                    return Occurrence::when(resolved.as_deref() == Some(self.module), self.new);
+                }
+                Occurrence::when(
+                    !shadowed(tokens, index, self.old)
+                        && scope.sees(token.start, self.module, self.old),
+                    self.new,
+                )
+            }
+            Symbol::Module => {
+                if token.in_use {
+                    let reaches = scope.use_at(token.start).is_some_and(|targets| {
+                        targets.iter().any(|target| target.starts_with(self.module))
+                    });
+                    return Occurrence::when(reaches, self.new);
+                }
+                if after_dot {
+                    return Occurrence::Other;
+                }
+                if index > 0 && ident_at(tokens, index - 1) == Some("mod") {
+                    let parent = &self.module[..self.module.len() - 1];
+                    return Occurrence::when(
+                        scope.module_at(token.start).as_deref() == Some(parent),
+                        self.new,
+                    );
+                }
+                if !path_sep_after(tokens, index) {
+                    return Occurrence::Other;
+                }
+                let (_, mut path) = path_before(tokens, index);
+                path.push(self.old.to_string());
+                let resolved = scope.resolve(token.start, &path);
+                Occurrence::when(resolved.as_deref() == Some(self.module), self.new)
+            }
+            Symbol::Method { owner } => {
+                if after_dot {
+                    return match call {
+                        true => self.receiver(tokens, scope, index, owner),
+                        false => Occurrence::Other,
+                    };
+                }
+                Occurrence::when(self.owner_before(tokens, scope, index, owner), self.new)
+            }
+            Symbol::Field { owner } => {
+                if after_dot {
+                    return match call {
+                        true => Occurrence::Other,
+                        false => self.receiver(tokens, scope, index, owner),
+                    };
+                }
+                match struct_brace_owner(tokens, index) {
+                    Some(open) if self.is_owner(tokens, scope, open, owner) => {
+                        // Shorthand `Owner { field }` keeps its binding name
+                        Occurrence::Renamed(if colon_after(tokens, index) {
+                            self.new.to_string()
+                        } else {
+                            format!("{}: {}", self.new, self.old)
+                        })
+                    }
+                    _ => Occurrence::Other,
+                }
+            }
+            Symbol::Variant { owner } => {
+                Occurrence::when(self.owner_before(tokens, scope, index, owner), self.new)
+            }
+        }
+    }
+}
+
+/// Finds the symbol `item_path` names inside `content` and the byte range
+/// of its defining identifier
+fn resolve(content: &str, item_path: &str) -> Result<(Symbol, String, (usize, usize))> {
+    let file = syn::parse_file(content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+    let segments: Vec<&str> = item_path.split("::").map(str::trim).collect();
+
+    match segments.as_slice() {
+        [name] => {
+            for item in &file.items {
+                let found = match item {
+                    Item::Fn(i) if i.sig.ident == name => {
+                        Some((Symbol::Function, span_range(content, &i.sig.ident)))
+                    }
+                    Item::Struct(i) if i.ident == name => {
+                        Some((Symbol::Type, span_range(content, &i.ident)))
+                    }
+                    Item::Enum(i) if i.ident == name => {
+                        Some((Symbol::Type, span_range(content, &i.ident)))
+                    }
+                    Item::Trait(i) if i.ident == name => {
+                        Some((Symbol::Type, span_range(content, &i.ident)))
+                    }
+                    Item::Type(i) if i.ident == name => {
+                        Some((Symbol::Type, span_range(content, &i.ident)))
+                    }
+                    Item::Union(i) if i.ident == name => {
+                        Some((Symbol::Type, span_range(content, &i.ident)))
+                    }
+                    Item::Const(i) if i.ident == name => {
+                        Some((Symbol::Function, span_range(content, &i.ident)))
+                    }
+                    Item::Static(i) if i.ident == name => {
+                        Some((Symbol::Function, span_range(content, &i.ident)))
+                    }
+                    Item::Mod(i) if i.ident == name => {
+                        Some((Symbol::Module, span_range(content, &i.ident)))
+                    }
+                    _ => None,
+                };
+                if let Some((symbol, range)) = found {
+                    return Ok((symbol, name.to_string(), range));
+                }
+            }
+            Err(anyhow!("'{}' not found", item_path))
+        }
+        [owner, member] => {
+            for item in &file.items {
+                match item {
+                    Item::Struct(s) if s.ident == owner => {
+                        if let Fields::Named(fields) = &s.fields {
+                            if let Some(ident) = fields
+                                .named
+                                .iter()
+                                .filter_map(|f| f.ident.as_ref())
+                                .find(|i| *i == member)
+                            {
+                                let symbol = Symbol::Field {
+                                    owner: owner.to_string(),
+                                };
+                                return Ok((
+                                    symbol,
+                                    member.to_string(),
+                                    span_range(content, ident),
+                                ));
+                            }
+                        }
+                    }
+                    Item::Enum(e) if e.ident == owner => {
+                        if let Some(variant) = e.variants.iter().find(|v| v.ident == member) {
+                            let symbol = Symbol::Variant {
+                                owner: owner.to_string(),
+                            };
+                            return Ok((
+                                symbol,
+                                member.to_string(),
+                                span_range(content, &variant.ident),
+                            ));
+                        }
+                    }
+                    Item::Impl(block)
+                        if block.trait_.is_none()
+                            && type_name(&block.self_ty).as_deref() == Some(*owner) =>
+                    {
+                        for impl_item in &block.items {
+                            if let ImplItem::Fn(method) = impl_item {
+                                if method.sig.ident == member {
+                                    let symbol = Symbol::Method {
+                                        owner: owner.to_string(),
+                                    };
+                                    return Ok((
+                                        symbol,
+                                        member.to_string(),
+                                        span_range(content, &method.sig.ident),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Err(anyhow!("'{}' not found", item_path))
+        }
+        _ => Err(anyhow!(
+            "Expected '<item>' or '<Type>::<member>', got '{}'",
+            item_path
+        )),
+    }
+}
+
+/// Module name of a source file, e.g. `ai` for `src/systems/ai.rs` or
+/// `src/systems/ai/mod.rs`
+fn module_name(file: &Path) -> Option<String> {
+    let stem = file.file_stem()?.to_str()?;
+    if stem == "mod" {
+        file.parent()?.file_name()?.to_str().map(String::from)
+    } else if stem == "main" || stem == "lib" {
+        None
+    } else {
+        Some(stem.to_string())
+    }
+}
+
+/// Plans renaming the item at `item_path` in `file` (or the module `file`
+/// itself when `item_path` is `None`) to `new_name` across `files`. Only
+/// paths, imports and receivers that resolve to the item are renamed.
+pub fn plan_rename(
+    root: &Path,
+    files: &[PathBuf],
+    file: &Path,
+    item_path: Option<&str>,
+    new_name: &str,
+) -> Result<RefactorPlan> {
+    let new_name = new_name.trim();
+    syn::parse_str::<syn::Ident>(new_name)
+        .map_err(|_| anyhow!("'{}' is not a valid identifier", new_name))?;
+
+    let content = fs::read_to_string(file)
+        .map_err(|e| anyhow!("Failed to read {}: {}", file.display(), e))?;
+    let (symbol, old_name, definition) = match item_path {
+        Some(item_path) => {
+            let (symbol, name, range) = resolve(&content, item_path)?;
+            (symbol, name, Some(range))
+        }
+        None => {
+            let name = module_name(file)
+                .ok_or_else(|| anyhow!("{} is a crate root, not a module", file.display()))?;
+            (Symbol::Module, name, None)
+        }
+    };
+    if old_name == new_name {
+        return Err(anyhow!("'{}' already has that name", old_name));
+    }
+
+    let mut plan = RefactorPlan {
+        description: format!(
+            "Rename {} '{}' to '{}'",
+            symbol.describe(),
+            old_name,
+            new_name
+        ),
+        files: Vec::new(),
+        moves: Vec::new(),
+        unresolved: Vec::new(),
+    };
+
+    // A file outside the module tree only renames within itself
+    let home = module_path(root, file);
+    let mut module = home.clone().unwrap_or_default();
+    if symbol == Symbol::Module {
+        if home.is_none() {
+            return Err(anyhow!(
+                "{} is not part of the crate's module tree",
+                file.display()
+            ));
+        }
+        if item_path.is_some() {
+            module.push(old_name.clone());
+        }
+    }
+    let target = RenameTarget {
+        symbol: &symbol,
+        old: &old_name,
+        new: new_name,
+        module: &module,
+    };
+
+    for path in files {
+        let file_module = match &home {
+            Some(_) => module_path(root, path),
+            None if path == file => Some(Vec::new()),
+            None => continue,
+        };
+        let Ok(original) = fs::read_to_string(path) else {
+            continue;
+        };
+        let (Ok(tokens), Ok(scope)) = (tokenize(&original), FileScope::new(&original, file_module))
+        else {
+            continue;
+        };
+
+        let mut edits: Vec<TextEdit> = Vec::new();
+        for (index, token) in tokens.iter().enumerate() {
+            if token.kind != TokenKind::Ident(old_name.clone()) {
+                continue;
+            }
+            match target.occurrence(&tokens, &scope, index) {
+                Occurrence::Renamed(text) => edits.push(TextEdit {
+                    start: token.start,
+                    end: token.end,
+                    text,
+                }),
+                Occurrence::Unresolved => plan.unresolved.push(format!(
+                    "{}:{}",
+                    path.strip_prefix(root).unwrap_or(path).display(),
+                    original[..token.start].matches('\n').count() + 1
+                )),
+                Occurrence::Other => {}
+            }
+        }
+
+        if let Some((start, end)) = definition {
+            if path.as_path() == file && !edits.iter().any(|edit| edit.start == start) {
+                edits.push(TextEdit {
+                    start,
+                    end,
+                    text: new_name.to_string(),
+                });
+            }
+        }
+
+        if !edits.is_empty() {
+            edits.sort_by_key(|edit| edit.start);
+            plan.files.push(FileEdits {
+                path: path.clone(),
+                original,
+                edits,
+            });
+        }
+    }
+
+    if symbol == Symbol::Module {
+        plan.moves = module_moves(file, &old_name, new_name);
+        if let Some((_, target)) = plan.moves.iter().find(|(_, target)| target.exists()) {
+            return Err(anyhow!("{} already exists", target.display()));
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Files and directories that carry a module's name on disk
+fn module_moves(file: &Path, old: &str, new: &str) -> Vec<(PathBuf, PathBuf)> {
+    let mut moves = Vec::new();
+    let Some(parent) = file.parent() else {
+        return moves;
+    };
+
+    if file.file_stem().and_then(|s| s.to_str()) == Some("mod") {
+        if let Some(grandparent) = parent.parent() {
+            moves.push((parent.to_path_buf(), grandparent.join(new)));
+        }
+        return moves;
+    }
+
+    moves.push((file.to_path_buf(), parent.join(format!("{}.rs", new))));
+    let directory = parent.join(old);
+    if directory.is_dir() {
+        moves.push((directory, parent.join(new)));
+    }
+    moves
+}
+
+/// Module path of a file in the `src/` tree, e.g. `["systems", "ai"]` for
+/// `src/systems/ai.rs`; `None` for files that are their own crate
+pub fn module_path(root: &Path, file: &Path) -> Option<Vec<String>> {
+    let relative = file.strip_prefix(root.join("src")).ok()?;
+    let mut segments: Vec<String> = relative
+        .with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    if segments.first().map(String::as_str) == Some("bin") {
+        return None;
+    }
+    if segments.last().map(String::as_str) == Some("mod") {
+        segments.pop();
+    }
+    if segments == ["main"] || segments == ["lib"] {
+        segments.clear();
+    }
+    Some(segments)
+}
+
+/// Resolves a path written in `module` to a crate-relative module path;
+/// paths that start with an external crate come back unchanged behind the
+/// module prefix and so never match a crate module
+fn absolute_path(path: &[String], module: &[String]) -> Vec<String> {
+    match path.first().map(String::as_str) {
+        Some("crate") => path[1..].to_vec(),
+        Some("self") => module.iter().chain(&path[1..]).cloned().collect(),
+        Some("super") => {
+            let supers = path
+                .iter()
+                .take_while(|segment| *segment == "super")
+                .count();
+            let keep = module.len().saturating_sub(supers);
+            module[..keep]
+                .iter()
+                .chain(&path[supers..])
+                .cloned()
+                .collect()
+        }
+        _ => module.iter().chain(path).cloned().collect(),
+    }
+}
+
+fn crate_path(module: &[String]) -> Vec<String> {
+    std::iter::once("crate".to_string())
+        .chain(module.iter().cloned())
+        .collect()
+}
+
+/// Items that `move` can take, by name
+fn movable_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Struct(i) => Some(i.ident.to_string()),
+        Item::Enum(i) => Some(i.ident.to_string()),
+        Item::Union(i) => Some(i.ident.to_string()),
+        Item::Trait(i) => Some(i.ident.to_string()),
+        Item::Type(i) => Some(i.ident.to_string()),
+        Item::Fn(i) => Some(i.sig.ident.to_string()),
+        Item::Const(i) => Some(i.ident.to_string()),
+        Item::Static(i) => Some(i.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Where `pub(crate) ` goes on a private item, `None` if it has a visibility
+fn private_keyword(content: &str, item: &Item) -> Option<usize> {
+    let (vis, offset) = match item {
+        Item::Struct(i) => (&i.vis, offset_of(content, i.struct_token.span.start())),
+        Item::Enum(i) => (&i.vis, offset_of(content, i.enum_token.span.start())),
+        Item::Union(i) => (&i.vis, offset_of(content, i.union_token.span.start())),
+        Item::Trait(i) => (
+            &i.vis,
+            span_range(content, &i.trait_token).0.min(
+                i.unsafety
+                    .map(|u| offset_of(content, u.span.start()))
+                    .unwrap_or(usize::MAX),
+            ),
+        ),
+        Item::Type(i) => (&i.vis, offset_of(content, i.type_token.span.start())),
+        Item::Fn(i) => (&i.vis, span_range(content, &i.sig).0),
+        Item::Const(i) => (&i.vis, offset_of(content, i.const_token.span.start())),
+        Item::Static(i) => (&i.vis, offset_of(content, i.static_token.span.start())),
+        _ => return None,
+    };
+    matches!(vis, syn::Visibility::Inherited).then_some(offset)
+}
+
+/// Every identifier in `code`, including macro bodies
+pub fn identifiers(code: &str) -> HashSet<String> {
+    tokenize(code)
+        .map(|tokens| {
+            tokens
+                .into_iter()
+                .filter_map(|token| match token.kind {
+                    TokenKind::Ident(name) => Some(name),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// An item cut out of its module, ready to be added to another one
+#[derive(Debug)]
+pub struct Cut {
+    /// The item and its impls, preceded by the imports they need
+    pub code: String,
+    /// The source file without them
+    pub remaining: String,
+    /// Imports of the source file that nothing uses any more
+    pub stale_imports: Vec<String>,
+}
+
+/// Cuts `name` and every impl of it out of `content` (the file of module
+/// `source`) for a move to module `target`. Private items, fields and
+/// methods that are named anywhere outside the new module (`used_elsewhere`
+/// or the rest of the source file) become `pub(crate)`.
+pub fn cut_item(
+    content: &str,
+    name: &str,
+    source: &[String],
+    target: &[String],
+    used_elsewhere: &HashSet<String>,
+) -> Result<Cut> {
+    let pieces = |file: &syn::File, content: &str| -> Result<Vec<(usize, usize)>> {
+        let item = file
+            .items
+            .iter()
+            .find(|item| movable_name(item).as_deref() == Some(name))
+            .ok_or_else(|| anyhow!("'{}' not found", name))?;
+        let impls = file.items.iter().filter(
+            |item| matches!(item, Item::Impl(block) if type_name(&block.self_ty).as_deref() == Some(name)),
+        );
+        let mut ranges: Vec<(usize, usize)> = std::iter::once(item)
+            .chain(impls)
+            .map(|item| located_lines(content, span_range(content, item)))
+            .collect();
+        ranges.sort();
+        Ok(ranges)
+    };
+    let split = |content: &str, ranges: &[(usize, usize)]| {
+        let mut remaining = content.to_string();
+        for (start, end) in ranges.iter().rev() {
+            remove_lines(&mut remaining, *start, *end);
+        }
+        let moved: Vec<&str> = ranges
+            .iter()
+            .map(|(start, end)| content[*start..*end].trim_end())
+            .collect();
+        (moved.join("\n\n"), remaining)
+    };
+
+    let file =
+        syn::parse_file(content).map_err(|e| anyhow!("Failed to parse source file: {}", e))?;
+    let ranges = pieces(&file, content)?;
+    let (moved, remaining) = split(content, &ranges);
+    let moved_idents = identifiers(&moved);
+    let mut outside = identifiers(&remaining);
+    outside.extend(used_elsewhere.iter().cloned());
+
+    // First pass: widen visibility where the move would break access
+    let mut edits = Vec::new();
+    let mut publish = |offset: usize| {
+        edits.push(TextEdit {
+            start: offset,
+            end: offset,
+            text: "pub(crate) ".to_string(),
+        })
+    };
+    let within = |start: usize| ranges.iter().any(|(s, e)| (*s..*e).contains(&start));
+    let target_inside_source = target.starts_with(source);
+
+    for item in &file.items {
+        let (start, _) = span_range(content, item);
+        if !within(start) {
+            // Items of a parent module are only visible to its descendants
+            let needed = !target_inside_source
+                && movable_name(item).is_some_and(|n| moved_idents.contains(&n));
+            if let Some(offset) = private_keyword(content, item).filter(|_| needed) {
+                publish(offset);
+            }
+            continue;
+        }
+
+        match item {
+            Item::Impl(block) if block.trait_.is_none() => {
+                for member in &block.items {
+                    match member {
+                        ImplItem::Fn(method)
+                            if matches!(method.vis, syn::Visibility::Inherited)
+                                && outside.contains(&method.sig.ident.to_string()) =>
+                        {
+                            publish(span_range(content, &method.sig).0)
+                        }
+                        ImplItem::Const(constant)
+                            if matches!(constant.vis, syn::Visibility::Inherited)
+                                && outside.contains(&constant.ident.to_string()) =>
+                        {
+                            publish(offset_of(content, constant.const_token.span.start()))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            item => {
+                if let Some(offset) =
+                    private_keyword(content, item).filter(|_| outside.contains(name))
+                {
+                    publish(offset);
+                }
+                if let Item::Struct(structure) = item {
+                    for field in structure.fields.iter() {
+                        if !matches!(field.vis, syn::Visibility::Inherited) {
+                            continue;
+                        }
+                        match &field.ident {
+                            Some(ident) if outside.contains(&ident.to_string()) => {
+                                publish(span_range(content, ident).0)
+                            }
+                            None if outside.contains(name) => {
+                                publish(span_range(content, &field.ty).0)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let widened = FileEdits {
+        path: PathBuf::new(),
+        original: content.to_string(),
+        edits,
+    }
+    .apply();
+    let file =
+        syn::parse_file(&widened).map_err(|e| anyhow!("Failed to parse source file: {}", e))?;
+    let (moved, mut remaining) = split(&widened, &pieces(&file, &widened)?);
+
+    // Imports the moved code relied on in its old module
+    let local_modules: HashSet<String> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Mod(module) => Some(module.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut uses = Vec::new();
+    let mut source_leaves = Vec::new();
+    for item in &file.items {
+        match item {
+            Item::Use(item) => {
+                for leaf in leaves_of(item) {
+                    let relative = leaf.path().first().is_some_and(|first| {
+                        first == "self" || first == "super" || local_modules.contains(first)
+                    });
+                    if leaf
+                        .binding()
+                        .is_some_and(|binding| moved_idents.contains(binding))
+                    {
+                        let leaf = if relative {
+                            leaf.with_path(crate_path(&absolute_path(leaf.path(), source)))
+                        } else {
+                            leaf.clone()
+                        };
+                        uses.push(format!("use {};", leaf));
+                    }
+                    source_leaves.push(leaf);
+                }
+            }
+            Item::Mod(module) if moved_idents.contains(&module.ident.to_string()) => {
+                uses.push(format!(
+                    "use {}::{};",
+                    crate_path(source).join("::"),
+                    module.ident
+                ));
+            }
+            item => {
+                if let Some(other) =
+                    movable_name(item).filter(|n| n != name && moved_idents.contains(n))
+                {
+                    uses.push(format!("use {}::{};", crate_path(source).join("::"), other));
+                }
+            }
+        }
+    }
+
+    // The rest of the source file reaches the item through its new module
+    if identifiers(&remaining).contains(name) {
+        remaining = CodeInserter::new()
+            .with_format_mode(FormatMode::Off)
+            .insert_code(
+                &remaining,
+                &format!("use {}::{};", crate_path(target).join("::"), name),
+            )?;
+    }
+    let stale_imports = unused_imports(&remaining, &source_leaves);
+
+    let code = if uses.is_empty() {
+        moved
+    } else {
+        format!("{}\n\n{}", uses.join("\n"), moved)
+    };
+    Ok(Cut {
+        code,
+        remaining,
+        stale_imports,
+    })
+}
+
+/// Points `use` declarations and qualified paths that reach `name` in module
+/// `source` at module `target`. `module` is the module of `content`; when it
+/// is `target` itself the imports are dropped instead.
+pub fn retarget_paths(
+    content: &str,
+    module: &[String],
+    name: &str,
+    source: &[String],
+    target: &[String],
+) -> Result<Option<String>> {
+    let file = syn::parse_file(content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+    let mut edits = Vec::new();
+
+    for item in &file.items {
+        let Item::Use(item) = item else {
+            continue;
+        };
+        let leaves = leaves_of(item);
+        let mut changed = false;
+        let mut kept = Vec::new();
+        for leaf in leaves {
+            if leaf.imports(name) && absolute_path(leaf.path(), module) == source {
+                changed = true;
+                if module != target {
+                    kept.push(leaf.with_path(crate_path(target)));
+                }
+            } else {
+                kept.push(leaf);
+            }
+        }
+        if !changed {
+            continue;
+        }
+
+        let range = span_range(content, item);
+        if kept.is_empty() {
+            let (start, end) = located_lines(content, range);
+            edits.push(TextEdit {
+                start,
+                end,
+                text: String::new(),
+            });
+        } else {
+            edits.push(TextEdit {
+                start: range.0,
+                end: range.1,
+                text: render_use(item, &kept),
+            });
+        }
+    }
+
+    let tokens = tokenize(content)?;
+    for (index, token) in tokens.iter().enumerate() {
+        if token.in_use || token.kind != TokenKind::Ident(name.to_string()) {
+            continue;
+        }
+        let (first, segments) = path_before(&tokens, index);
+        if !segments.is_empty() && absolute_path(&segments, module) == source {
+            let text = if module == target {
+                String::new()
+            } else {
+                format!("{}::", crate_path(target).join("::"))
+            };
+            edits.push(TextEdit {
+                start: tokens[first].start,
+                end: token.start,
+                text,
+            });
+        }
+    }
+
+    if edits.is_empty() {
+        return Ok(None);
+    }
+    let mut updated = content.to_string();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+    for edit in edits {
+        if edit.text.is_empty()
+            && edit.end > edit.start
+            && updated[edit.start..edit.end].ends_with('\n')
+        {
+            remove_lines(&mut updated, edit.start, edit.end);
+        } else {
+            updated.replace_range(edit.start..edit.end, &edit.text);
+        }
+    }
+    Ok(Some(updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rename_function_skips_longer_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let main = temp_dir.path().join("main.rs");
+        fs::write(
+            &main,
+            "fn spawn() {}\nfn main() {\n    let spawn_rate = 2;\n    spawn();\n    println!(\"{}\", spawn_rate);\n}\n",
+        )
+        .unwrap();
+
+        let plan = plan_rename(
+            temp_dir.path(),
+            std::slice::from_ref(&main),
+            &main,
+            Some("spawn"),
+            "spawn_npc",
+        )
+        .unwrap();
+        assert_eq!(plan.change_count(), 2);
+        assert_eq!(
+            plan.files[0].apply(),
+            "fn spawn_npc() {}\nfn main() {\n    let spawn_rate = 2;\n    spawn_npc();\n    println!(\"{}\", spawn_rate);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_field_expands_shorthand() {
+        let temp_dir = TempDir::new().unwrap();
+        let npc = temp_dir.path().join("npc.rs");
+        fs::write(
+            &npc,
+            "struct Npc { hp: u32 }\nfn f(hp: u32) -> u32 {\n    let n = Npc { hp };\n    let Npc { hp: x } = n;\n    x + n.hp\n}\n",
+        )
+        .unwrap();
+
+        let plan = plan_rename(
+            temp_dir.path(),
+            std::slice::from_ref(&npc),
+            &npc,
+            Some("Npc::hp"),
+            "health",
+        )
+        .unwrap();
+        assert_eq!(
+            plan.files[0].apply(),
+            "struct Npc { health: u32 }\nfn f(hp: u32) -> u32 {\n    let n = Npc { health: hp };\n    let Npc { health: x } = n;\n    x + n.health\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_module_moves_file() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = temp_dir.path().join("src/main.rs");
+        let npc = temp_dir.path().join("src/npc.rs");
+        fs::write(
+            &main,
+            "mod npc;\nuse crate::npc::Npc;\nfn main() { let npc = npc::spawn(); }\n",
+        )
+        .unwrap();
+        fs::write(&npc, "pub fn spawn() {}\n").unwrap();
+
+        let plan = plan_rename(
+            temp_dir.path(),
+            &[main.clone(), npc.clone()],
+            &npc,
+            None,
+            "enemy",
+        )
+        .unwrap();
+        assert_eq!(
+            plan.files[0].apply(),
+            "mod enemy;\nuse crate::enemy::Npc;\nfn main() { let npc = enemy::spawn(); }\n"
+        );
+        assert_eq!(
+            plan.moves,
+            vec![(npc, temp_dir.path().join("src/enemy.rs"))]
+        );
+    }
+
+    #[test]
+    fn test_rename_function_leaves_other_items() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = temp_dir.path().join("src/main.rs");
+        let npc = temp_dir.path().join("src/npc.rs");
+        let other = temp_dir.path().join("src/other.rs");
+        fs::write(
+            &main,
+            "mod npc;\nmod other;\nuse npc::spawn;\nfn main() {\n    spawn();\n    other::spawn();\n    let spawn = 3;\n    println!(\"{}\", spawn);\n    npc::spawn();\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            &npc,
+            "pub fn spawn() {}\npub struct Npc;\nimpl Npc {\n    pub fn spawn() -> Npc {\n        Npc\n    }\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            &other,
+            "pub fn spawn() {}\npub fn make() {\n    crate::npc::Npc::spawn();\n    spawn();\n}\n",
+        )
+        .unwrap();
+
+        let files = [main.clone(), npc.clone(), other];
+        let plan = plan_rename(temp_dir.path(), &files, &npc, Some("spawn"), "spawn_npc").unwrap();
+        assert_eq!(plan.files.len(), 2);
+        assert_eq!(
+            plan.files[0].apply(),
+            "mod npc;\nmod other;\nuse npc::spawn_npc;\nfn main() {\n    spawn_npc();\n    other::spawn();\n    let spawn = 3;\n    println!(\"{}\", spawn);\n    npc::spawn_npc();\n}\n"
+        );
+        assert_eq!(
+            plan.files[1].apply(),
+            "pub fn spawn_npc() {}\npub struct Npc;\nimpl Npc {\n    pub fn spawn() -> Npc {\n        Npc\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_members_by_receiver_type() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = temp_dir.path().join("src/main.rs");
+        let code = "struct Npc { hp: u32 }\nstruct Tower { hp: u32 }\nimpl Npc {\n    fn heal(&mut self) { self.hp += 1; }\n}\nimpl Tower {\n    fn heal(&self) {}\n}\nfn hit(npc: &mut Npc, tower: &Tower, hp: u32) -> u32 {\n    npc.hp -= hp;\n    npc.heal();\n    tower.heal();\n    let t = Tower { hp: 5 };\n    tower.hp + t.hp + pick().hp\n}\n";
+        fs::write(&main, code).unwrap();
+        let files = [main.clone()];
+
+        let plan = plan_rename(temp_dir.path(), &files, &main, Some("Npc::hp"), "health").unwrap();
+        assert_eq!(
+            plan.files[0].apply(),
+            code.replacen("Npc { hp", "Npc { health", 1)
+                .replace("self.hp", "self.health")
+                .replace("npc.hp", "npc.health")
+        );
+        assert_eq!(plan.unresolved, vec!["src/main.rs:14"]);
+
+        let plan =
+            plan_rename(temp_dir.path(), &files, &main, Some("Npc::heal"), "restore").unwrap();
+        assert_eq!(
+            plan.files[0].apply(),
+            code.replacen("fn heal(&mut", "fn restore(&mut", 1)
+                .replace("npc.heal()", "npc.restore()")
+        );
+    }
+
+    #[test]
+    fn test_preview_crlf() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = temp_dir.path().join("src/main.rs");
+        fs::write(
+            &main,
+            "fn spawn() {}\r\n\r\nfn main() {\r\n    spawn();\r\n}\r\n",
+        )
+        .unwrap();
+        let files = [main.clone()];
+
+        let plan = plan_rename(temp_dir.path(), &files, &main, Some("spawn"), "spawn_npc").unwrap();
+        let preview = plan.preview(temp_dir.path());
+        assert!(
+            preview.contains("     4 - spawn();\n       + spawn_npc();\n"),
+            "{}",
+            preview
+        );
+        assert_eq!(
+            plan.files[0].apply(),
+            "fn spawn_npc() {}\r\n\r\nfn main() {\r\n    spawn_npc();\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_type_in_impls_and_pointers() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        let main = temp_dir.path().join("src/main.rs");
+        let code = "struct Npc;\nimpl Default for Npc {\n    fn default() -> Self { Npc }\n}\nfn f(n: &mut Npc, p: *const Npc, q: *mut Npc) {\n    for npc in [Npc] {}\n}\nconst NPC: Npc = Npc;\n";
+        fs::write(&main, code).unwrap();
+        let files = [main.clone()];
+
+        let plan = plan_rename(temp_dir.path(), &files, &main, Some("Npc"), "Actor").unwrap();
+        assert_eq!(plan.files[0].apply(), code.replace("Npc", "Actor"));
+    }
+}
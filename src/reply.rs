@@ -0,0 +1,194 @@
+use crate::patch::InsertTarget;
+use regex::Regex;
+use std::path::{Component, Path};
+use std::sync::OnceLock;
+
+/// Directories a block may name a new file in; anywhere else the file has
+/// to exist already
+const NEW_FILE_DIRS: [&str; 3] = ["src/", "tests/", "examples/"];
+
+/// A fenced code block from an assistant reply and where it should go
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// Target file, from a first line that is only `// src/npc.rs`, the
+    /// fence info string or a line `**src/npc.rs**` right above the fence
+    pub file: Option<String>,
+    /// Block inside the file, from a header like `src/npc.rs::impl Npc`
+    pub target: Option<InsertTarget>,
+    pub code: String,
+}
+
+impl CodeBlock {
+    /// Short description for listings, e.g. `src/npc.rs::impl Npc (12 lines)`
+    pub fn summary(&self) -> String {
+        let location = match (&self.file, &self.target) {
+            (Some(file), Some(target)) => format!("{}::{}", file, target),
+            (Some(file), None) => file.clone(),
+            (None, _) => "no file given".to_string(),
+        };
+        format!("{} ({} lines)", location, self.code.lines().count())
+    }
+}
+
+fn location_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^((?:[\w.-]+/)*[\w-]+\.rs)(?:::(.+))?$").expect("valid file path pattern")
+    })
+}
+
+/// Parses exactly `src/npc.rs` or `src/npc.rs::impl Npc`
+fn parse_location(text: &str) -> Option<(String, Option<InsertTarget>)> {
+    let captures = location_pattern().captures(text.trim())?;
+    let file = captures.get(1)?.as_str().to_string();
+    let target = captures
+        .get(2)
+        .and_then(|target| InsertTarget::parse(target.as_str().trim()).ok());
+    Some((file, target))
+}
+
+/// A first line that is only `// src/npc.rs`
+fn comment_location(line: &str) -> Option<(String, Option<InsertTarget>)> {
+    parse_location(line.trim().strip_prefix("//")?)
+}
+
+/// A line above the fence that is only `**src/npc.rs**`
+fn heading_location(line: &str) -> Option<(String, Option<InsertTarget>)> {
+    let line = line.trim();
+    let line = line.strip_suffix(':').unwrap_or(line);
+    parse_location(line.strip_prefix("**")?.strip_suffix("**")?)
+}
+
+/// A path in the fence info string: `rust src/npc.rs`,
+/// `rust title="src/npc.rs"`, `rust:src/npc.rs` or `src/npc.rs`
+fn info_location(info: &str) -> Option<(String, Option<InsertTarget>)> {
+    info.split(|c: char| c.is_whitespace() || c == ',').find_map(|word| {
+        let word = word
+            .strip_prefix("rust:")
+            .or_else(|| word.strip_prefix("rs:"))
+            .or_else(|| word.strip_prefix("title="))
+            .unwrap_or(word);
+        parse_location(word.trim_matches('"'))
+    })
+}
+
+/// Whether a block may write to `file`: an existing file under `root`, or
+/// a new one in one of `NEW_FILE_DIRS`
+fn plausible_file(file: &str, root: Option<&Path>) -> bool {
+    let path = Path::new(file);
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return false;
+    }
+    NEW_FILE_DIRS.iter().any(|dir| file.starts_with(dir))
+        || root.is_some_and(|root| root.join(path).is_file())
+}
+
+/// Whether a fence info string (`rust`, `rs`, `rust title="src/a.rs"`, ...)
+/// marks a block worth applying
+fn is_rust_fence(info: &str) -> bool {
+    let language = info.split(|c: char| c.is_whitespace() || c == ':' || c == ',').next().unwrap_or("");
+    matches!(language, "" | "rust" | "rs") || info_location(info).is_some()
+}
+
+/// Extracts every Rust code block of `reply`, with the file each one
+/// belongs to when the reply names it in one of the forms `CodeBlock::file`
+/// lists and the file is plausible for the project at `root`. An
+/// unterminated fence runs to the end of the reply.
+pub fn extract_blocks(reply: &str, root: Option<&Path>) -> Vec<CodeBlock> {
+    let lines: Vec<&str> = reply.lines().collect();
+    let mut blocks = Vec::new();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let Some(info) = lines[index].trim_start().strip_prefix("```") else {
+            index += 1;
+            continue;
+        };
+        let info = info.trim();
+        let heading = lines[..index].iter().rev().find(|line| !line.trim().is_empty());
+
+        let start = index + 1;
+        let mut end = start;
+        while end < lines.len() && !lines[end].trim_start().starts_with("```") {
+            end += 1;
+        }
+        index = end + 1;
+        if !is_rust_fence(info) {
+            continue;
+        }
+
+        let mut body = &lines[start..end];
+        let mut location = None;
+        if let Some(first) = body.first() {
+            location = comment_location(first);
+            if location.is_some() {
+                body = &body[1..];
+            }
+        }
+        let location = location
+            .or_else(|| info_location(info))
+            .or_else(|| heading.and_then(|line| heading_location(line)))
+            .filter(|(file, _)| plausible_file(file, root));
+
+        let code = body.join("\n");
+        if code.trim().is_empty() {
+            continue;
+        }
+        let (file, target) = match location {
+            Some((file, target)) => (Some(file), target),
+            None => (None, None),
+        };
+        blocks.push(CodeBlock { file, target, code });
+    }
+    blocks
+}
+
+/// Whether `input` is a pasted reply rather than a command
+pub fn looks_like_reply(input: &str) -> bool {
+    input.lines().any(|line| line.trim_start().starts_with("```"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_with_headers() {
+        let reply = "Here is the change.\n\n**src/npc.rs**\n```rust\npub struct Npc;\n```\n\nThen add a method:\n\n```rust\n// src/npc.rs::impl Npc\nfn tick(&self) {}\n```\n\nRun it with:\n```bash\ncargo run\n```\n";
+        let blocks = extract_blocks(reply, None);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].file.as_deref(), Some("src/npc.rs"));
+        assert_eq!(blocks[0].code, "pub struct Npc;");
+        assert_eq!(blocks[1].file.as_deref(), Some("src/npc.rs"));
+        assert_eq!(blocks[1].target, Some(InsertTarget::parse("impl Npc").unwrap()));
+        assert_eq!(blocks[1].code, "fn tick(&self) {}");
+    }
+
+    #[test]
+    fn test_block_without_file_and_unterminated_fence() {
+        let reply = "Try this:\n```\nfn a() {}\n```\n**src/b.rs**\n```rs\nfn b() {\n";
+        let blocks = extract_blocks(reply, None);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].file, None);
+        assert_eq!(blocks[1].file.as_deref(), Some("src/b.rs"));
+        assert_eq!(blocks[1].code, "fn b() {");
+    }
+
+    #[test]
+    fn test_passing_mentions_are_not_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("build.rs"), "fn main() {}\n").unwrap();
+        let reply = "Update `lib.rs`:\n```rust\n// replaces foo.rs\nfn a() {}\n```\n\
+            **build.rs**\n```rust\nfn main() {}\n```\n\
+            ```rust title=\"../secrets.rs\"\nfn c() {}\n```\n\
+            ```rust tests/combat.rs\nfn d() {}\n```\n";
+        let blocks = extract_blocks(reply, Some(temp_dir.path()));
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[0].file, None);
+        assert_eq!(blocks[0].code, "// replaces foo.rs\nfn a() {}");
+        assert_eq!(blocks[1].file.as_deref(), Some("build.rs"));
+        assert_eq!(blocks[2].file, None);
+        assert_eq!(blocks[3].file.as_deref(), Some("tests/combat.rs"));
+        assert_eq!(extract_blocks(reply, None)[1].file, None);
+    }
+}
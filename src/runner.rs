@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+
+/// Something a background process reported since the last `poll`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessEvent {
+    /// One line of stdout or stderr, in the order they arrived
+    Output { id: usize, line: String },
+    Finished {
+        id: usize,
+        exit_code: Option<i32>,
+        success: bool,
+    },
+}
+
+/// A process started by the runner, running or finished
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub id: usize,
+    /// What was run, e.g. `cargo test parser`
+    pub command_line: String,
+    /// OS process id; on Unix also the id of the process group
+    pub pid: Option<u32>,
+    pub started: Instant,
+    /// Runtime and success once the process exited
+    pub finished: Option<(Duration, bool)>,
+}
+
+impl ProcessInfo {
+    pub fn elapsed(&self) -> Duration {
+        match self.finished {
+            Some((elapsed, _)) => elapsed,
+            None => self.started.elapsed(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.finished.is_none()
+    }
+}
+
+/// Runs cargo and friends on a tokio runtime so the UI thread never waits.
+/// Output is streamed line by line and collected with `poll` each frame.
+pub struct ProcessRunner {
+    runtime: Runtime,
+    sender: Sender<ProcessEvent>,
+    receiver: Receiver<ProcessEvent>,
+    processes: Vec<ProcessInfo>,
+    /// Asks the task waiting on a process to kill it
+    kill_switches: HashMap<usize, oneshot::Sender<()>>,
+    next_id: usize,
+}
+
+impl ProcessRunner {
+    pub fn new() -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("failed to start the tokio runtime");
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            runtime,
+            sender,
+            receiver,
+            processes: Vec::new(),
+            kill_switches: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Starts `program args...` in `dir` and returns its id
+    pub fn spawn(&mut self, program: &str, args: &[String], dir: &Path) -> Result<usize> {
+        let _guard = self.runtime.enter();
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Its own group, so stopping cargo also stops rustc and the program it runs
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start {}: {}", program, e))?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut command_line = program.to_string();
+        for arg in args {
+            command_line.push(' ');
+            command_line.push_str(arg);
+        }
+        self.processes.push(ProcessInfo {
+            id,
+            command_line,
+            pid: child.id(),
+            started: Instant::now(),
+            finished: None,
+        });
+
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let sender = self.sender.clone();
+        let (kill_switch, killed) = oneshot::channel();
+        self.kill_switches.insert(id, kill_switch);
+        self.runtime.spawn(async move {
+            let stdout = tokio::spawn(forward_lines(stdout, id, sender.clone()));
+            let stderr = tokio::spawn(forward_lines(stderr, id, sender.clone()));
+            let status = tokio::select! {
+                status = child.wait() => status,
+                Ok(()) = killed => {
+                    let _ = child.kill().await;
+                    child.wait().await
+                }
+            };
+            // Drain both pipes so no line arrives after `Finished`
+            let _ = stdout.await;
+            let _ = stderr.await;
+            let _ = sender.send(ProcessEvent::Finished {
+                id,
+                exit_code: status.as_ref().ok().and_then(|status| status.code()),
+                success: status.map(|status| status.success()).unwrap_or(false),
+            });
+        });
+        Ok(id)
+    }
+
+    /// Everything reported since the last call, with finished processes
+    /// marked as such
+    pub fn poll(&mut self) -> Vec<ProcessEvent> {
+        let events: Vec<ProcessEvent> = self.receiver.try_iter().collect();
+        for event in &events {
+            if let ProcessEvent::Finished { id, success, .. } = event {
+                if let Some(process) = self.processes.iter_mut().find(|p| p.id == *id) {
+                    process.finished = Some((process.started.elapsed(), *success));
+                }
+                self.kill_switches.remove(id);
+            }
+        }
+        events
+    }
+
+    pub fn process(&self, id: usize) -> Option<&ProcessInfo> {
+        self.processes.iter().find(|process| process.id == id)
+    }
+
+    pub fn has_running(&self) -> bool {
+        self.processes.iter().any(ProcessInfo::is_running)
+    }
+
+    /// Every process started so far, oldest first
+    pub fn processes(&self) -> &[ProcessInfo] {
+        &self.processes
+    }
+
+    /// Asks a running process to exit: SIGTERM to its process group on
+    /// Unix, where a game loop can still save; elsewhere the same as `kill`
+    pub fn stop(&mut self, id: usize) -> Result<()> {
+        let pid = self.running(id)?.pid;
+        if signal_group(pid, Signal::Terminate) {
+            return Ok(());
+        }
+        self.kill(id)
+    }
+
+    /// Kills a running process and, on Unix, everything it started
+    pub fn kill(&mut self, id: usize) -> Result<()> {
+        let pid = self.running(id)?.pid;
+        signal_group(pid, Signal::Kill);
+        if let Some(kill_switch) = self.kill_switches.remove(&id) {
+            let _ = kill_switch.send(());
+        }
+        Ok(())
+    }
+
+    fn running(&self, id: usize) -> Result<&ProcessInfo> {
+        match self.process(id) {
+            Some(process) if process.is_running() => Ok(process),
+            Some(_) => Err(anyhow!("Process {} has already finished", id)),
+            None => Err(anyhow!("There is no process {}", id)),
+        }
+    }
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    id: usize,
+    sender: Sender<ProcessEvent>,
+) {
+    let Some(reader) = reader else {
+        return;
+    };
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if sender.send(ProcessEvent::Output { id, line }).is_err() {
+            break;
+        }
+    }
+}
+
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+/// Signals the process group led by `pid` (see `spawn`); false where
+/// there are no signals
+#[cfg(unix)]
+fn signal_group(pid: Option<u32>, signal: Signal) -> bool {
+    let Some(pid) = pid else {
+        return false;
+    };
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: kill(2) only takes plain integers; `-pid` addresses the group
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), signal);
+    }
+    true
+}
+
+#[cfg(not(unix))]
+fn signal_group(_pid: Option<u32>, _signal: Signal) -> bool {
+    false
+}
+
+/// Spinner frame for a running process, advancing ten times a second
+pub fn spinner(elapsed: Duration) -> char {
+    const FRAMES: [char; 8] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧'];
+    FRAMES[(elapsed.as_millis() / 100) as usize % FRAMES.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_is_streamed() {
+        let mut runner = ProcessRunner::new();
+        let args = vec![
+            "-c".to_string(),
+            "echo one; echo two >&2; exit 3".to_string(),
+        ];
+        let id = runner.spawn("sh", &args, Path::new(".")).unwrap();
+
+        let mut events = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while runner.process(id).unwrap().is_running() && Instant::now() < deadline {
+            events.extend(runner.poll());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let lines: Vec<&str> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProcessEvent::Output { line, .. } => Some(line.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(lines.contains(&"one") && lines.contains(&"two"));
+        assert!(runner.kill(id).is_err());
+        assert_eq!(
+            events.last(),
+            Some(&ProcessEvent::Finished {
+                id,
+                exit_code: Some(3),
+                success: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_stop_ends_the_process() {
+        let mut runner = ProcessRunner::new();
+        let args = vec!["-c".to_string(), "sleep 30".to_string()];
+        let id = runner.spawn("sh", &args, Path::new(".")).unwrap();
+        runner.stop(id).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut events = Vec::new();
+        while runner.process(id).unwrap().is_running() && Instant::now() < deadline {
+            events.extend(runner.poll());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matches!(
+            events.last(),
+            Some(ProcessEvent::Finished { success: false, .. })
+        ));
+        assert!(runner.processes()[0].elapsed() < Duration::from_secs(10));
+    }
+}
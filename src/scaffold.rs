@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+
+/// What `new <kind> <name>` creates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scaffold {
+    /// A module file under `src/`, given as `systems/ai` or `systems::ai`
+    Module(Vec<String>),
+    Bin(String),
+    Example(String),
+    Test(String),
+    /// A workspace member crate at a path such as `crates/physics`
+    Crate {
+        path: String,
+        bin: bool,
+    },
+}
+
+impl Scaffold {
+    /// Parses `module systems/ai`, `bin tool`, `crate crates/physics --bin`, ...
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut words = input.split_whitespace();
+        let kind = words.next().unwrap_or("");
+        let name = words
+            .next()
+            .ok_or_else(|| anyhow!("Usage: new <module|bin|example|test|crate> <name>"))?;
+        let rest: Vec<&str> = words.collect();
+
+        match kind {
+            "module" | "mod" => {
+                let path = name
+                    .trim_start_matches("./")
+                    .trim_start_matches("src/")
+                    .trim_end_matches(".rs")
+                    .replace("::", "/");
+                let chain: Vec<String> = path.split('/').map(String::from).collect();
+                if let Some(bad) = chain.iter().find(|segment| !is_identifier(segment)) {
+                    return Err(anyhow!("'{}' is not a valid module name", bad));
+                }
+                if chain
+                    .last()
+                    .is_some_and(|last| last == "mod" || last == "main" || last == "lib")
+                {
+                    return Err(anyhow!("'{}' would not be a new module", name));
+                }
+                Ok(Scaffold::Module(chain))
+            }
+            "bin" | "example" | "test" => {
+                if !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    return Err(anyhow!("'{}' is not a valid {} name", name, kind));
+                }
+                Ok(match kind {
+                    "bin" => Scaffold::Bin(name.to_string()),
+                    "example" => Scaffold::Example(name.to_string()),
+                    _ => Scaffold::Test(name.to_string()),
+                })
+            }
+            "crate" => {
+                let path = name.trim_start_matches("./").trim_end_matches('/');
+                if path.starts_with(['/', '\\']) || path.contains(':') {
+                    return Err(anyhow!("'{}' must be a path inside the project", name));
+                }
+                if let Some(bad) = path.split('/').find(|segment| !is_directory_name(segment)) {
+                    return Err(anyhow!("'{}' is not a valid directory name", bad));
+                }
+                Ok(Scaffold::Crate {
+                    path: path.to_string(),
+                    bin: rest.contains(&"--bin"),
+                })
+            }
+            _ => Err(anyhow!(
+                "Expected 'new module', 'new bin', 'new example', 'new test' or 'new crate'"
+            )),
+        }
+    }
+
+    /// The main file to create, relative to the project root
+    pub fn file(&self) -> String {
+        match self {
+            Scaffold::Module(chain) => format!("src/{}.rs", chain.join("/")),
+            Scaffold::Bin(name) => format!("src/bin/{}.rs", name),
+            Scaffold::Example(name) => format!("examples/{}.rs", name),
+            Scaffold::Test(name) => format!("tests/{}.rs", name),
+            Scaffold::Crate { path, bin: true } => format!("{}/src/main.rs", path),
+            Scaffold::Crate { path, bin: false } => format!("{}/src/lib.rs", path),
+        }
+    }
+
+    /// Starting content of `file()`
+    pub fn template(&self) -> String {
+        match self {
+            Scaffold::Module(chain) => format!("//! The `{}` module\n", chain.join("::")),
+            Scaffold::Bin(name)
+            | Scaffold::Example(name)
+            | Scaffold::Crate {
+                path: name,
+                bin: true,
+            } => {
+                format!(
+                    "fn main() {{\n    println!(\"Hello from {}!\");\n}}\n",
+                    crate_name(name)
+                )
+            }
+            Scaffold::Test(name) => format!(
+                "#[test]\n#[ignore = \"not written yet\"]\nfn {}() {{}}\n",
+                name.replace('-', "_")
+            ),
+            Scaffold::Crate { path, bin: false } => {
+                format!("//! The `{}` crate\n", crate_name(path))
+            }
+        }
+    }
+}
+
+/// `Cargo.toml` of a new workspace member
+pub fn crate_manifest(path: &str, edition: &str) -> String {
+    format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"{}\"\n\n[dependencies]\n",
+        crate_name(path),
+        edition
+    )
+}
+
+/// Package name of a member at `path`, the last path segment
+pub fn crate_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// A plain directory name, never `.` or `..`
+fn is_directory_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scaffolds() {
+        assert_eq!(
+            Scaffold::parse("module src/systems/ai.rs").unwrap(),
+            Scaffold::Module(vec!["systems".to_string(), "ai".to_string()])
+        );
+        assert_eq!(
+            Scaffold::parse("crate ./crates/physics/ --bin").unwrap(),
+            Scaffold::Crate {
+                path: "crates/physics".to_string(),
+                bin: true
+            }
+        );
+        assert!(Scaffold::parse("module systems::mod").is_err());
+        assert!(Scaffold::parse("bin level/editor").is_err());
+
+        for outside in ["../x", "crates/../../x", "/tmp/x", "C:/x", "crates/./x"] {
+            assert!(
+                Scaffold::parse(&format!("crate {}", outside)).is_err(),
+                "{}",
+                outside
+            );
+        }
+        assert_eq!(
+            Scaffold::parse("crate ../x").unwrap_err().to_string(),
+            "'..' is not a valid directory name"
+        );
+    }
+
+    #[test]
+    fn test_files_and_templates() {
+        let test = Scaffold::parse("test combat-rules").unwrap();
+        assert_eq!(test.file(), "tests/combat-rules.rs");
+        assert_eq!(
+            test.template(),
+            "#[test]\n#[ignore = \"not written yet\"]\nfn combat_rules() {}\n"
+        );
+
+        let library = Scaffold::parse("crate crates/physics").unwrap();
+        assert_eq!(library.file(), "crates/physics/src/lib.rs");
+        assert_eq!(library.template(), "//! The `physics` crate\n");
+
+        let example = Scaffold::parse("example demo").unwrap();
+        assert_eq!(example.file(), "examples/demo.rs");
+        assert!(example
+            .template()
+            .contains("println!(\"Hello from demo!\")"));
+    }
+}
@@ -0,0 +1,491 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+impl TestStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TestStatus::Passed => "ok",
+            TestStatus::Failed => "FAILED",
+            TestStatus::Ignored => "ignored",
+        }
+    }
+}
+
+/// File format of `test report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Junit,
+}
+
+impl ReportFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "json" => Some(ReportFormat::Json),
+            "junit" | "xml" => Some(ReportFormat::Junit),
+            _ => None,
+        }
+    }
+
+    /// Where the report goes when no path is given
+    pub fn default_file(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "test-report.json",
+            ReportFormat::Junit => "junit.xml",
+        }
+    }
+}
+
+/// Where and why a test panicked
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Panic {
+    pub message: String,
+    /// `src/npc.rs:42:9`
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestCase {
+    /// Test binary, e.g. `unittests src/main.rs` or `tests/combat.rs`
+    pub suite: String,
+    pub name: String,
+    pub status: TestStatus,
+    /// Seconds, when libtest reported it
+    pub duration: Option<f64>,
+    /// Captured output; libtest only shows it for failures
+    pub stdout: String,
+    pub panic: Option<Panic>,
+}
+
+/// A `test result:` line, one per test binary
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TestSummary {
+    pub suite: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub filtered_out: usize,
+    pub duration: Option<f64>,
+}
+
+/// Results of one `cargo test`, built up line by line while it runs
+#[derive(Debug, Default, Serialize)]
+pub struct TestRun {
+    pub cases: Vec<TestCase>,
+    pub summaries: Vec<TestSummary>,
+    #[serde(skip)]
+    suite: String,
+    /// Test whose `---- name stdout ----` section is being read
+    #[serde(skip)]
+    capturing: Option<usize>,
+}
+
+/// A libtest `--format json` event
+#[derive(Deserialize)]
+struct JsonEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    name: Option<String>,
+    stdout: Option<String>,
+    exec_time: Option<f64>,
+    #[serde(default)]
+    passed: usize,
+    #[serde(default)]
+    failed: usize,
+    #[serde(default)]
+    ignored: usize,
+    #[serde(default)]
+    filtered_out: usize,
+}
+
+impl TestRun {
+    pub fn count(&self, status: TestStatus) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| case.status == status)
+            .count()
+    }
+
+    pub fn failed_names(&self) -> Vec<&str> {
+        self.cases
+            .iter()
+            .filter(|case| case.status == TestStatus::Failed)
+            .map(|case| case.name.as_str())
+            .collect()
+    }
+
+    /// Reads one line of `cargo test` output, text or libtest JSON. Returns
+    /// the line to show: JSON events come back as the equivalent text line,
+    /// `None` for events with nothing to show.
+    pub fn feed(&mut self, line: &str) -> Option<String> {
+        if line.starts_with('{') {
+            if let Ok(event) = serde_json::from_str::<JsonEvent>(line) {
+                return self.feed_json(event);
+            }
+        }
+        self.feed_text(line);
+        Some(line.to_string())
+    }
+
+    fn feed_text(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if let Some(target) = trimmed.strip_prefix("Running ") {
+            self.suite = target.split(" (").next().unwrap_or(target).to_string();
+            self.capturing = None;
+            return;
+        }
+        if let Some(krate) = trimmed.strip_prefix("Doc-tests ") {
+            self.suite = format!("doc-tests {}", krate);
+            self.capturing = None;
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("test ") {
+            if let Some((name, outcome)) = rest.split_once(" ... ") {
+                let (outcome, duration) = match outcome.split_once(" <") {
+                    Some((outcome, time)) => (outcome, time.trim_end_matches("s>").parse().ok()),
+                    None => (outcome, None),
+                };
+                let status = match outcome.split(',').next().unwrap_or(outcome).trim() {
+                    "ok" => TestStatus::Passed,
+                    "FAILED" => TestStatus::Failed,
+                    "ignored" => TestStatus::Ignored,
+                    // `bench:` lines and the like
+                    _ => return,
+                };
+                self.push_case(name, status, duration, String::new());
+                return;
+            }
+        }
+
+        if let Some(summary) = line.strip_prefix("test result: ") {
+            self.capturing = None;
+            self.summaries.push(parse_summary(&self.suite, summary));
+            return;
+        }
+
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|rest| rest.strip_suffix(" stdout ----"))
+        {
+            self.capturing = self
+                .cases
+                .iter()
+                .rposition(|case| case.name == name && case.suite == self.suite);
+            return;
+        }
+        if line == "failures:" || line == "successes:" {
+            self.capturing = None;
+            return;
+        }
+        if let Some(index) = self.capturing {
+            let case = &mut self.cases[index];
+            case.stdout.push_str(line);
+            case.stdout.push('\n');
+            case.panic = parse_panic(&case.stdout);
+        }
+    }
+
+    fn feed_json(&mut self, event: JsonEvent) -> Option<String> {
+        match (event.kind.as_str(), event.event.as_str()) {
+            ("test", "ok" | "failed" | "ignored") => {
+                let status = match event.event.as_str() {
+                    "ok" => TestStatus::Passed,
+                    "failed" => TestStatus::Failed,
+                    _ => TestStatus::Ignored,
+                };
+                let name = event.name.unwrap_or_default();
+                let line = format!("test {} ... {}", name, status.label());
+                self.push_case(
+                    &name,
+                    status,
+                    event.exec_time,
+                    event.stdout.unwrap_or_default(),
+                );
+                Some(line)
+            }
+            ("suite", "ok" | "failed") => {
+                let summary = TestSummary {
+                    suite: self.suite.clone(),
+                    passed: event.passed,
+                    failed: event.failed,
+                    ignored: event.ignored,
+                    filtered_out: event.filtered_out,
+                    duration: event.exec_time,
+                };
+                let line = format!(
+                    "test result: {}. {} passed; {} failed; {} ignored; {} filtered out",
+                    if event.event == "ok" { "ok" } else { "FAILED" },
+                    summary.passed,
+                    summary.failed,
+                    summary.ignored,
+                    summary.filtered_out
+                );
+                self.summaries.push(summary);
+                Some(line)
+            }
+            _ => None,
+        }
+    }
+
+    fn push_case(&mut self, name: &str, status: TestStatus, duration: Option<f64>, stdout: String) {
+        self.cases.push(TestCase {
+            suite: self.suite.clone(),
+            name: name.to_string(),
+            status,
+            duration,
+            panic: parse_panic(&stdout),
+            stdout,
+        });
+    }
+
+    pub fn report(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            ReportFormat::Junit => self.to_junit(),
+        }
+    }
+
+    /// JUnit XML with one `<testsuite>` per test binary, as CI servers read it
+    pub fn to_junit(&self) -> String {
+        let mut suites: Vec<(&str, Vec<&TestCase>)> = Vec::new();
+        for case in &self.cases {
+            match suites.iter_mut().find(|(suite, _)| *suite == case.suite) {
+                Some((_, cases)) => cases.push(case),
+                None => suites.push((&case.suite, vec![case])),
+            }
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+            self.cases.len(),
+            self.count(TestStatus::Failed),
+            self.count(TestStatus::Ignored)
+        );
+        for (suite, cases) in suites {
+            let count = |status| cases.iter().filter(|case| case.status == status).count();
+            let _ = writeln!(
+                xml,
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+                escape_xml(suite),
+                cases.len(),
+                count(TestStatus::Failed),
+                count(TestStatus::Ignored)
+            );
+            for case in cases {
+                let _ = write!(
+                    xml,
+                    "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                    escape_xml(&case.name),
+                    escape_xml(suite),
+                    case.duration.unwrap_or(0.0)
+                );
+                match case.status {
+                    TestStatus::Passed if case.stdout.is_empty() => xml.push_str("/>\n"),
+                    status => {
+                        xml.push_str(">\n");
+                        if status == TestStatus::Failed {
+                            let (message, location) = match &case.panic {
+                                Some(panic) => (
+                                    panic.message.as_str(),
+                                    panic.location.as_deref().unwrap_or(""),
+                                ),
+                                None => ("failed", ""),
+                            };
+                            let _ = writeln!(
+                                xml,
+                                "      <failure message=\"{}\">{}</failure>",
+                                escape_xml(message),
+                                escape_xml(location)
+                            );
+                        } else if status == TestStatus::Ignored {
+                            xml.push_str("      <skipped/>\n");
+                        }
+                        if !case.stdout.is_empty() {
+                            let _ = writeln!(
+                                xml,
+                                "      <system-out>{}</system-out>",
+                                escape_xml(&case.stdout)
+                            );
+                        }
+                        xml.push_str("    </testcase>\n");
+                    }
+                }
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+/// `ok. 3 passed; 1 failed; 0 ignored; 0 measured; 2 filtered out; finished in 0.01s`
+fn parse_summary(suite: &str, summary: &str) -> TestSummary {
+    let mut parsed = TestSummary {
+        suite: suite.to_string(),
+        ..Default::default()
+    };
+    let counts = summary
+        .split_once(". ")
+        .map(|(_, counts)| counts)
+        .unwrap_or(summary);
+    for part in counts.split(';').map(str::trim) {
+        if let Some(time) = part.strip_prefix("finished in ") {
+            parsed.duration = time.trim_end_matches('s').parse().ok();
+            continue;
+        }
+        let Some((count, what)) = part.split_once(' ') else {
+            continue;
+        };
+        let Ok(count) = count.parse() else {
+            continue;
+        };
+        match what {
+            "passed" => parsed.passed = count,
+            "failed" => parsed.failed = count,
+            "ignored" => parsed.ignored = count,
+            "filtered out" => parsed.filtered_out = count,
+            _ => {}
+        }
+    }
+    parsed
+}
+
+/// Finds the panic in captured output, in the current
+/// `thread 'x' panicked at src/a.rs:1:2:\nmessage` form (newer toolchains
+/// add the thread id: `thread 'x' (123) panicked at`) or the older
+/// `thread 'x' panicked at 'message', src/a.rs:1:2`
+pub fn parse_panic(output: &str) -> Option<Panic> {
+    let mut lines = output.lines();
+    while let Some(line) = lines.next() {
+        let Some((_, rest)) = line
+            .strip_prefix("thread '")
+            .and_then(|rest| rest.split_once(" panicked at "))
+        else {
+            continue;
+        };
+        if let Some(location) = rest.strip_suffix(':') {
+            let message: Vec<&str> = lines
+                .take_while(|line| {
+                    !line.starts_with("note: ") && !line.starts_with("stack backtrace:")
+                })
+                .collect();
+            return Some(Panic {
+                message: message.join("\n").trim().to_string(),
+                location: Some(location.to_string()),
+            });
+        }
+        return Some(match rest.rsplit_once("', ") {
+            Some((message, location)) => Panic {
+                message: message.trim_start_matches('\'').to_string(),
+                location: Some(location.to_string()),
+            },
+            None => Panic {
+                message: rest.to_string(),
+                location: None,
+            },
+        });
+    }
+    None
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_OUTPUT: &str = "     Running unittests src/main.rs (target/debug/deps/game-1234)
+
+running 3 tests
+test npc::tests::test_spawn ... ok
+test npc::tests::test_flee ... ignored, slow
+test combat::tests::test_damage ... FAILED
+
+failures:
+
+---- combat::tests::test_damage stdout ----
+dealing 5 damage
+thread 'combat::tests::test_damage' (4242) panicked at src/combat.rs:42:9:
+assertion `left == right` failed
+  left: 5
+ right: 6
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    combat::tests::test_damage
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 2 filtered out; finished in 0.01s
+";
+
+    #[test]
+    fn test_parse_text_output() {
+        let mut run = TestRun::default();
+        for line in TEXT_OUTPUT.lines() {
+            run.feed(line);
+        }
+
+        assert_eq!(run.cases.len(), 3);
+        assert_eq!(run.cases[1].status, TestStatus::Ignored);
+        let failed = &run.cases[2];
+        assert_eq!(failed.suite, "unittests src/main.rs");
+        assert_eq!(failed.status, TestStatus::Failed);
+        assert!(failed.stdout.starts_with("dealing 5 damage\n"));
+        let panic = failed.panic.as_ref().unwrap();
+        assert_eq!(panic.location.as_deref(), Some("src/combat.rs:42:9"));
+        assert_eq!(
+            panic.message,
+            "assertion `left == right` failed\n  left: 5\n right: 6"
+        );
+        assert_eq!(
+            run.summaries,
+            vec![TestSummary {
+                suite: "unittests src/main.rs".to_string(),
+                passed: 1,
+                failed: 1,
+                ignored: 1,
+                filtered_out: 2,
+                duration: Some(0.01),
+            }]
+        );
+        assert_eq!(run.failed_names(), vec!["combat::tests::test_damage"]);
+        assert!(run.to_junit().contains(
+            "<failure message=\"assertion `left == right` failed\n  left: 5\n right: 6\">src/combat.rs:42:9</failure>"
+        ));
+    }
+
+    #[test]
+    fn test_parse_json_events() {
+        let mut run = TestRun::default();
+        assert_eq!(
+            run.feed(r#"{ "type": "test", "event": "started", "name": "a" }"#),
+            None
+        );
+        assert_eq!(
+            run.feed(r#"{ "type": "test", "name": "a", "event": "failed", "exec_time": 0.5, "stdout": "thread 'a' panicked at src/lib.rs:3:5:\nboom\n" }"#),
+            Some("test a ... FAILED".to_string())
+        );
+        run.feed(r#"{ "type": "suite", "event": "failed", "passed": 0, "failed": 1, "ignored": 0, "measured": 0, "filtered_out": 0, "exec_time": 0.5 }"#);
+
+        assert_eq!(run.cases[0].duration, Some(0.5));
+        assert_eq!(run.cases[0].panic.as_ref().unwrap().message, "boom");
+        assert_eq!(run.summaries[0].failed, 1);
+    }
+}
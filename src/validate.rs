@@ -0,0 +1,88 @@
+use crate::patch::InsertTarget;
+use anyhow::{anyhow, Result};
+
+/// Refuses `result` (the file after merging `snippet`) unless it parses.
+/// The error points into the snippet when the snippet itself is broken, and
+/// into the merged file otherwise. `original` is the file before the merge.
+pub fn check_merge(original: &str, result: &str, snippet: &str, target: Option<&InsertTarget>) -> Result<()> {
+    let Err(error) = syn::parse_file(result) else {
+        return Ok(());
+    };
+
+    let mut message = match snippet_error(snippet, target) {
+        Some((line, column, error)) => format!(
+            "The snippet is not valid Rust: {} at line {}, column {} of the snippet\n  {}",
+            error,
+            line,
+            column,
+            snippet.lines().nth(line - 1).unwrap_or("").trim()
+        ),
+        None => {
+            let start = error.span().start();
+            format!(
+                "The result would not be valid Rust: {} at line {}, column {}\n  {}",
+                error,
+                start.line,
+                start.column + 1,
+                result.lines().nth(start.line.saturating_sub(1)).unwrap_or("").trim()
+            )
+        }
+    };
+
+    if snippet.lines().any(|line| line.trim_start().starts_with("```")) {
+        message.push_str("\nThe snippet contains a markdown fence (```), paste only the code inside it.");
+    }
+    if syn::parse_file(original).is_err() {
+        message.push_str("\nThe file did not parse before this change either.");
+    }
+    message.push_str("\nNothing was written. Repeat with --force to write it anyway.");
+    Err(anyhow!(message))
+}
+
+/// Parses the snippet the way the inserter reads it for `target`; returns
+/// the 1-based line and column of the first error within the snippet
+fn snippet_error(snippet: &str, target: Option<&InsertTarget>) -> Option<(usize, usize, syn::Error)> {
+    let (prefix, suffix) = match target {
+        None | Some(InsertTarget::Module(_)) => ("", ""),
+        Some(InsertTarget::Impl { .. }) => ("impl __Vibe {\n", "\n}"),
+        // Fields, variants and derives have no single form to check against
+        Some(InsertTarget::Type(_)) => return None,
+    };
+
+    let wrapped = format!("{}{}{}", prefix, snippet, suffix);
+    let error = syn::parse_file(&wrapped).err()?;
+    let start = error.span().start();
+    let offset = prefix.lines().count();
+    let snippet_lines = snippet.lines().count().max(1);
+
+    // An error at the closing wrapper means the snippet ended too early
+    let line = start.line.saturating_sub(offset).clamp(1, snippet_lines);
+    let column = if start.line > offset + snippet_lines {
+        snippet.lines().last().map(|l| l.chars().count()).unwrap_or(0) + 1
+    } else {
+        start.column + 1
+    };
+    Some((line, column, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_points_into_snippet() {
+        let snippet = "fn a() {}\n\nfn b() {\n    let x = ;\n}";
+        let result = format!("fn main() {{}}\n\n{}", snippet);
+        let error = check_merge("fn main() {}", &result, snippet, None).unwrap_err().to_string();
+        assert!(error.contains("line 4, column 13 of the snippet"), "{}", error);
+        assert!(error.contains("let x = ;"));
+    }
+
+    #[test]
+    fn test_stray_fence_is_reported() {
+        let snippet = "```rust\nfn a() {}\n```";
+        let error = check_merge("", snippet, snippet, None).unwrap_err().to_string();
+        assert!(error.contains("markdown fence"));
+        assert!(check_merge("", "fn a() {}", "fn a() {}", None).is_ok());
+    }
+}
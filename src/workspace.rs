@@ -0,0 +1,275 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetKind {
+    /// `lib`, `rlib`, `proc-macro`, `cdylib`, ...
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+    /// `build.rs`
+    BuildScript,
+}
+
+impl TargetKind {
+    fn parse(kind: &str) -> Self {
+        match kind {
+            "bin" => TargetKind::Bin,
+            "example" => TargetKind::Example,
+            "test" => TargetKind::Test,
+            "bench" => TargetKind::Bench,
+            "custom-build" => TargetKind::BuildScript,
+            _ => TargetKind::Lib,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TargetKind::Lib => "lib",
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+            TargetKind::Bench => "bench",
+            TargetKind::BuildScript => "build-script",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub kind: TargetKind,
+    pub src_path: PathBuf,
+}
+
+impl Target {
+    /// `bin server`, `lib engine`
+    pub fn label(&self) -> String {
+        format!("{} {}", self.kind.label(), self.name)
+    }
+
+    /// The cargo options selecting this target; `run` only takes bins and
+    /// examples
+    pub fn cargo_args(&self, run: bool) -> Vec<String> {
+        let flag = match (&self.kind, run) {
+            (TargetKind::Bin, _) => "--bin",
+            (TargetKind::Example, _) => "--example",
+            (_, true) | (TargetKind::BuildScript, _) => return Vec::new(),
+            (TargetKind::Lib, false) => return vec!["--lib".to_string()],
+            (TargetKind::Test, false) => "--test",
+            (TargetKind::Bench, false) => "--bench",
+        };
+        vec![flag.to_string(), self.name.clone()]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub edition: String,
+    /// Directory of its Cargo.toml, relative to the project root
+    pub dir: PathBuf,
+    pub features: Vec<String>,
+    pub targets: Vec<Target>,
+}
+
+impl Package {
+    /// Finds a target by `kind:name`, by name alone when that is unambiguous,
+    /// or `lib`
+    pub fn find_target(&self, spec: &str) -> Result<&Target> {
+        let (kind, name) = match spec.split_once(':') {
+            Some((kind, name)) => (Some(kind), name),
+            None if spec == "lib" => (Some("lib"), ""),
+            None => (None, spec),
+        };
+        let matches: Vec<&Target> = self
+            .targets
+            .iter()
+            .filter(|target| kind.is_none_or(|kind| target.kind.label() == kind))
+            .filter(|target| name.is_empty() || target.name == name)
+            .collect();
+        match matches.as_slice() {
+            [target] => Ok(target),
+            [] => Err(anyhow!("{} has no target '{}'", self.name, spec)),
+            _ => Err(anyhow!(
+                "'{}' is ambiguous in {}, use one of: {}",
+                spec,
+                self.name,
+                matches
+                    .iter()
+                    .map(|target| format!("{}:{}", target.kind.label(), target.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// The packages of a cargo workspace, from `cargo metadata`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workspace {
+    pub packages: Vec<Package>,
+}
+
+/// The package and target that build, run, test, search and listing are
+/// limited to; all of the workspace when empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scope {
+    pub package: Option<String>,
+    pub target: Option<Target>,
+}
+
+#[derive(Deserialize)]
+struct RawMetadata {
+    packages: Vec<RawPackage>,
+}
+
+#[derive(Deserialize)]
+struct RawPackage {
+    name: String,
+    version: String,
+    edition: String,
+    manifest_path: PathBuf,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    targets: Vec<RawTarget>,
+}
+
+#[derive(Deserialize)]
+struct RawTarget {
+    name: String,
+    kind: Vec<String>,
+    src_path: PathBuf,
+}
+
+impl Workspace {
+    /// Runs `cargo metadata --offline --no-deps` in `root`
+    pub fn load(root: &Path) -> Result<Self> {
+        let output = Command::new("cargo")
+            .args([
+                "metadata",
+                "--offline",
+                "--no-deps",
+                "--format-version",
+                "1",
+            ])
+            .current_dir(root)
+            .output()
+            .map_err(|e| anyhow!("Failed to run cargo metadata: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        Self::from_metadata(&String::from_utf8_lossy(&output.stdout), &root)
+    }
+
+    /// Builds the model from `cargo metadata` JSON; paths under `root`
+    /// are made relative to it
+    pub fn from_metadata(json: &str, root: &Path) -> Result<Self> {
+        let metadata: RawMetadata = serde_json::from_str(json)
+            .map_err(|e| anyhow!("Failed to parse cargo metadata: {}", e))?;
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+
+        let mut packages: Vec<Package> = metadata
+            .packages
+            .into_iter()
+            .map(|raw| Package {
+                dir: relative(raw.manifest_path.parent().unwrap_or(Path::new(""))),
+                targets: raw
+                    .targets
+                    .into_iter()
+                    .map(|target| Target {
+                        kind: TargetKind::parse(
+                            target.kind.first().map(String::as_str).unwrap_or("lib"),
+                        ),
+                        src_path: relative(&target.src_path),
+                        name: target.name,
+                    })
+                    .collect(),
+                features: raw.features.into_keys().collect(),
+                name: raw.name,
+                version: raw.version,
+                edition: raw.edition,
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { packages })
+    }
+
+    pub fn package(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|package| package.name == name)
+    }
+
+    /// The package a file (relative to the project root) belongs to: the
+    /// one with the deepest directory containing it, so a root package
+    /// does not claim its members' files
+    pub fn package_of(&self, file: &Path) -> Option<&Package> {
+        self.packages
+            .iter()
+            .filter(|package| file.starts_with(&package.dir))
+            .max_by_key(|package| package.dir.components().count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"{"packages":[
+        {"name":"game","version":"0.1.0","edition":"2021","manifest_path":"/ws/Cargo.toml","features":{},
+         "targets":[{"name":"game","kind":["bin"],"src_path":"/ws/src/main.rs"},
+                    {"name":"editor","kind":["bin"],"src_path":"/ws/src/bin/editor.rs"}]},
+        {"name":"engine","version":"0.2.0","edition":"2024","manifest_path":"/ws/crates/engine/Cargo.toml",
+         "features":{"default":["audio"],"audio":[]},
+         "targets":[{"name":"engine","kind":["lib"],"src_path":"/ws/crates/engine/src/lib.rs"},
+                    {"name":"combat","kind":["test"],"src_path":"/ws/crates/engine/tests/combat.rs"},
+                    {"name":"build-script-build","kind":["custom-build"],"src_path":"/ws/crates/engine/build.rs"}]}
+    ],"workspace_root":"/ws"}"#;
+
+    #[test]
+    fn test_workspace_from_metadata() {
+        let workspace = Workspace::from_metadata(METADATA, Path::new("/ws")).unwrap();
+        let engine = workspace.package("engine").unwrap();
+        assert_eq!(engine.dir, Path::new("crates/engine"));
+        assert_eq!(engine.features, vec!["audio", "default"]);
+        assert_eq!(engine.targets[2].kind, TargetKind::BuildScript);
+
+        assert_eq!(
+            workspace
+                .package_of(Path::new("crates/engine/src/lib.rs"))
+                .unwrap()
+                .name,
+            "engine"
+        );
+        assert_eq!(
+            workspace.package_of(Path::new("src/main.rs")).unwrap().name,
+            "game"
+        );
+
+        let game = workspace.package("game").unwrap();
+        assert_eq!(
+            game.find_target("editor").unwrap().cargo_args(true),
+            vec!["--bin", "editor"]
+        );
+        assert_eq!(
+            engine.find_target("test:combat").unwrap().cargo_args(false),
+            vec!["--test", "combat"]
+        );
+        assert!(engine
+            .find_target("lib")
+            .unwrap()
+            .cargo_args(true)
+            .is_empty());
+        assert!(game.find_target("bin").is_err());
+    }
+}